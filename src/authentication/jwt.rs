@@ -11,6 +11,7 @@ use serde::Serialize;
 use sha2::Sha256;
 
 use crate::database::schema::User;
+use crate::pagination::Cursor;
 use crate::schema::Cabinet;
 use crate::schema::UserRole;
 
@@ -138,3 +139,17 @@ pub fn parse_cabinet_access_key(token: String) -> Result<CabinetRecipeAccessKey,
         .verify_with_key(&key)
         .map_err(|_| HtmlError::InvalidRequest.new("Invalid token"))
 }
+
+pub fn generate_page_cursor(cursor: &Cursor) -> String {
+    let key: Hmac<Sha256> = Hmac::new_from_slice(_private_key()).unwrap();
+
+    cursor.sign_with_key(&key).unwrap()
+}
+
+pub fn parse_page_cursor(token: &str) -> Result<Cursor, potion::Error> {
+    let key: Hmac<Sha256> = Hmac::new_from_slice(_private_key()).unwrap();
+
+    token
+        .verify_with_key(&key)
+        .map_err(|_| HtmlError::InvalidRequest.new("Invalid cursor"))
+}
@@ -1,6 +1,9 @@
+use crate::schema::UnitType;
+
 pub const PRODUCT_COUNT_PER_PAGE: i64 = 100;
 pub const INCREDIENT_COUNT_PER_PAGE: i64 = 10;
 pub const RECIPE_COUNT_PER_PAGE: i64 = 10;
+pub const RATING_COUNT_PER_PAGE: i64 = 10;
 
 pub const INCREDIENT_CATEGORIES: &[(&str, &str)] = &[
     ("light_alcohol_product", "Light alcohol product"),
@@ -39,6 +42,17 @@ pub const RECIPE_ORDERS: &[(&str, &str)] = &[
     ("price_superalko_desc", "Price Superalko (desc)"),
     ("price_alko_asc", "Price Alko (asc)"),
     ("price_alko_desc", "Price Alko (desc)"),
+    ("diluted_abv_asc", "Diluted ABV (asc)"),
+    ("diluted_abv_desc", "Diluted ABV (desc)"),
+    ("diluted_volume_asc", "Diluted volume (asc)"),
+    ("diluted_volume_desc", "Diluted volume (desc)"),
+    ("kcal_asc", "Calories (asc)"),
+    ("kcal_desc", "Calories (desc)"),
+    ("sugar_asc", "Sugar (asc)"),
+    ("sugar_desc", "Sugar (desc)"),
+    ("carbohydrates_asc", "Carbohydrates (asc)"),
+    ("carbohydrates_desc", "Carbohydrates (desc)"),
+    ("top_rated", "Top rated"),
 ];
 
 pub const PRODUCT_ORDERS: &[(&str, &str)] = &[
@@ -64,4 +78,104 @@ pub const PRODUCT_AVAILABILITIES: &[(&str, &str)] = &[
     ("viking_line", "Viking Line"),
 ];
 
-pub const UNITS: &[&str] = &["cl", "ml", "oz", "kpl"];
+/* Every `UnitType` by name, in the order of `UnitType::ALL` */
+pub const UNITS: &[&str] = &unit_names();
+
+const fn unit_names() -> [&'static str; UnitType::ALL.len()] {
+    let mut names = [""; UnitType::ALL.len()];
+    let mut i = 0;
+    while i < names.len() {
+        names[i] = UnitType::ALL[i].as_str();
+        i += 1;
+    }
+    names
+}
+
+/* Bounds for caller provided page sizes in cursor pagination */
+pub const MIN_PAGE_SIZE: i64 = 1;
+pub const MAX_PAGE_SIZE: i64 = 500;
+
+/* Ethanol in one standard serving, in ml */
+pub const STANDARD_SERVING_ML: f64 = 17.7;
+
+/* Volume of one `part`, recipes in parts are stored as if one part was a shot */
+pub const PART_ML: f64 = 30.0;
+
+/* Number of different recipes on a party menu, by default and at most */
+pub const PARTY_MENU_SIZE: usize = 5;
+pub const PARTY_MAX_MENU_SIZE: usize = 20;
+
+/* Colloquial names expanded in full-text search */
+pub const SEARCH_SYNONYMS: &[(&str, &str)] = &[
+    ("kossu", "koskenkorva"),
+    ("jallu", "jaloviina"),
+    ("kalja", "olut"),
+    ("bisse", "olut"),
+    ("lonkero", "long drink"),
+    ("skumppa", "kuohuviini"),
+    ("kuohari", "kuohuviini"),
+    ("punkku", "punaviini"),
+    ("valkkari", "valkoviini"),
+    ("siideri", "cider"),
+];
+
+/* Minimum similarity for a fuzzy incredient match to be used, and the number of candidates shown */
+pub const INCREDIENT_MATCH_THRESHOLD: f64 = 0.5;
+pub const INCREDIENT_MATCH_CANDIDATES: i64 = 5;
+
+/* English incredient names tried in Finnish when importing recipes */
+pub const INCREDIENT_TRANSLATIONS: &[(&str, &str)] = &[
+    ("lime juice", "limettimehu"),
+    ("lemon juice", "sitruunamehu"),
+    ("orange juice", "appelsiinimehu"),
+    ("cranberry juice", "karpalomehu"),
+    ("pineapple juice", "ananasmehu"),
+    ("apple juice", "omenamehu"),
+    ("simple syrup", "sokerisiirappi"),
+    ("sugar syrup", "sokerisiirappi"),
+    ("soda water", "soodavesi"),
+    ("soda", "soodavesi"),
+    ("tonic water", "tonic"),
+    ("ginger beer", "inkivääriolut"),
+    ("cola", "kola"),
+    ("sugar", "sokeri"),
+    ("salt", "suola"),
+    ("mint", "minttu"),
+    ("lime", "limetti"),
+    ("lemon", "sitruuna"),
+    ("egg white", "munanvalkuainen"),
+    ("cream", "kerma"),
+    ("milk", "maito"),
+    ("peach schnapps", "persikkalikööri"),
+    ("coffee liqueur", "kahvilikööri"),
+];
+
+/* Parsed recipes at least this similar to another recipe are likely duplicates, and hidden from the review queue on request */
+pub const DUPLICATE_THRESHOLD: f64 = 0.75;
+/* Least similarity of a listed duplicate candidate, and the number of candidates listed */
+pub const DUPLICATE_CANDIDATE_THRESHOLD: f64 = 0.4;
+pub const DUPLICATE_CANDIDATE_COUNT: i64 = 10;
+
+/* Water from melted ice as a share of the undiluted volume. Shaken and stirred drinks follow
+the quadratic models of the starting ABV (as a fraction) from Dave Arnold's Liquid Intelligence,
+as [a, b, c] for a * abv^2 + b * abv + c. Built and blended drinks use a fixed share. */
+pub const SHAKEN_DILUTION: [f64; 3] = [1.567, 1.742, 0.203];
+pub const STIRRED_DILUTION: [f64; 3] = [-1.21, 1.246, 0.145];
+pub const BUILT_DILUTION: f64 = 0.2;
+pub const BLENDED_DILUTION: f64 = 0.5;
+
+/* Calories of ethanol, which are added to the calories of the incredients */
+pub const ETHANOL_DENSITY: f64 = 0.789;
+pub const ETHANOL_KCAL_PER_G: f64 = 7.0;
+
+/* Prior of the bayesian average rating, as if every recipe had RATING_PRIOR_COUNT ratings of
+RATING_PRIOR_MEAN. Keeps recipes with a few high ratings from topping the ones with many. */
+pub const RATING_PRIOR_MEAN: f64 = 3.0;
+pub const RATING_PRIOR_COUNT: f64 = 5.0;
+
+/* Author of the recipes and incredients of deleted users, created by the `user_accounts` migration */
+pub const DELETED_USERNAME: &str = "[deleted]";
+
+/* Longest allowed profile fields, in characters */
+pub const DISPLAY_NAME_MAX_LENGTH: usize = 50;
+pub const BIO_MAX_LENGTH: usize = 1000;
@@ -1,16 +1,38 @@
 pub mod cabinets;
+pub mod duplicates;
+pub mod forks;
+pub mod import;
 pub mod incredients;
+pub mod interchange;
+pub mod makeable;
+pub mod party;
 pub mod price_history;
 pub mod products;
+pub mod ratings;
 pub mod recipes;
+pub mod revisions;
+pub mod scaling;
+pub mod search;
+pub mod shopping;
 pub mod tags;
 pub mod users;
 
 pub use cabinets::*;
+pub use duplicates::*;
+pub use forks::*;
+pub use import::*;
 pub use incredients::*;
+pub use interchange::*;
+pub use makeable::*;
+pub use party::*;
 pub use price_history::*;
 pub use products::*;
+pub use ratings::*;
 pub use recipes::*;
+pub use revisions::*;
+pub use scaling::*;
+pub use search::*;
+pub use shopping::*;
 pub use tags::*;
 pub use users::*;
 // Remove this for now (#39)
@@ -1,10 +1,10 @@
 use potion::HtmlError;
-use sqlx::{FromRow, Pool, Postgres, QueryBuilder};
+use sqlx::{Acquire, Executor, FromRow, Postgres, QueryBuilder};
 
 use crate::{
     authentication::permissions::ActionType,
     cryptography::generate_access_token,
-    error::QueryError,
+    error::{QueryError, SdkError},
     schema::{Cabinet, CabinetMember, CabinetMixer, CabinetMixerOwned, CabinetProduct},
 };
 
@@ -12,24 +12,28 @@ use crate::jwt::SessionData;
 
 use super::{get_incredient, get_product, get_user_by_id};
 
-pub async fn update_cabinet_checksum(id: i32, pool: &Pool<Postgres>) -> Result<(), potion::Error> {
+pub async fn update_cabinet_checksum<'e, E>(id: i32, executor: E) -> Result<(), potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let key = uuid::Uuid::new_v4().to_string();
 
     sqlx::query("UPDATE cabinets SET checksum = $1 WHERE id = $2")
         .bind(key)
         .bind(id)
-        .execute(pool)
+        .execute(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn create_cabinet(
-    name: &str,
-    user_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<i32, potion::Error> {
+pub async fn create_cabinet<'a, A>(name: &str, user_id: i32, conn: A) -> Result<i32, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     let key = uuid::Uuid::new_v4().to_string();
 
     let id: (i32,) = sqlx::query_as(
@@ -38,22 +42,27 @@ pub async fn create_cabinet(
     .bind(user_id)
     .bind(name)
     .bind(key)
-    .fetch_one(pool)
+    .fetch_one(&mut *tr)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
-    add_user_to_cabinet(id.0, user_id, &pool).await?;
+    add_user_to_cabinet(id.0, user_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(id.0)
 }
 
-pub async fn list_own_cabinets(
+pub async fn list_own_cabinets<'e, E>(
     user_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<Vec<Cabinet>, potion::Error> {
+    executor: E,
+) -> Result<Vec<Cabinet>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let list: Vec<Cabinet> = sqlx::query_as("SELECT * FROM cabinets WHERE owner_id = $1")
         .bind(user_id)
-        .fetch_all(pool)
+        .fetch_all(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
@@ -61,10 +70,13 @@ pub async fn list_own_cabinets(
 }
 
 /// Also lists owned cabinets, just includes friend cabinets too
-pub async fn list_friend_cabinets(
+pub async fn list_friend_cabinets<'e, E>(
     user_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<Vec<Cabinet>, potion::Error> {
+    executor: E,
+) -> Result<Vec<Cabinet>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let list: Vec<Cabinet> = sqlx::query_as(
         "
         SELECT c.*
@@ -74,7 +86,7 @@ pub async fn list_friend_cabinets(
         ",
     )
     .bind(user_id)
-    .fetch_all(pool)
+    .fetch_all(executor)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
@@ -83,11 +95,11 @@ pub async fn list_friend_cabinets(
 
 /// Deletes a cabinet with a given id.
 /// ATTENTION: DOES NOT CHECK FOR OWNERWHIP BY ITSELF
-pub async fn delete_cabinet(id: i32, pool: &Pool<Postgres>) -> Result<(), potion::Error> {
-    let mut tr = pool
-        .begin()
-        .await
-        .map_err(|_| QueryError::new("Could not start transaction".to_owned()).into())?;
+pub async fn delete_cabinet<'a, A>(id: i32, conn: A) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
     sqlx::query("DELETE FROM shared_cabinets WHERE cabinet_id = $1")
         .bind(id)
         .execute(&mut *tr)
@@ -112,41 +124,53 @@ pub async fn delete_cabinet(id: i32, pool: &Pool<Postgres>) -> Result<(), potion
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
-    tr.commit()
-        .await
-        .map_err(|_| QueryError::new("Could not commit transaction".to_owned()).into())?;
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
     Ok(())
 }
 
-pub async fn get_cabinet(id: i32, pool: &Pool<Postgres>) -> Result<Option<Cabinet>, potion::Error> {
+pub async fn get_cabinet<'e, E>(id: i32, executor: E) -> Result<Option<Cabinet>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let cabinet: Option<Cabinet> = sqlx::query_as("SELECT * FROM cabinets WHERE id = $1")
         .bind(id)
-        .fetch_optional(pool)
+        .fetch_optional(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(cabinet)
 }
 
-pub async fn get_cabinet_by_token(
+pub async fn get_cabinet_by_token<'e, E>(
     token: &str,
-    pool: &Pool<Postgres>,
-) -> Result<Option<Cabinet>, potion::Error> {
+    executor: E,
+) -> Result<Option<Cabinet>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let cabinet: Option<Cabinet> = sqlx::query_as("SELECT * FROM cabinets WHERE access_key = $1")
         .bind(token)
-        .fetch_optional(pool)
+        .fetch_optional(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(cabinet)
 }
 
-pub async fn get_cabinet_mut(
+pub async fn get_cabinet_mut<'a, A>(
     id: i32,
     session: SessionData,
-    pool: &Pool<Postgres>,
-) -> Result<Cabinet, potion::Error> {
-    let cabinet = get_cabinet(id, pool).await?;
+    conn: A,
+) -> Result<Cabinet, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let cabinet = get_cabinet(id, &mut *tr).await?;
+    let members = list_cabinet_access_list(id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     session.authenticate(ActionType::ManageOwnCabinets)?;
 
@@ -155,8 +179,7 @@ pub async fn get_cabinet_mut(
             Ok(_) => Ok(cabinet),
             Err(_) => {
                 if cabinet.owner_id != session.user_id {
-                    if list_cabinet_access_list(id, &pool)
-                        .await?
+                    if members
                         .iter()
                         .any(|member| member.user_id == session.user_id)
                     {
@@ -169,45 +192,54 @@ pub async fn get_cabinet_mut(
                 }
             }
         },
-        None => Err(HtmlError::InvalidRequest.new("No recipe exists with spcified id")),
+        None => Err(SdkError::not_found("No recipe exists with spcified id").into()),
     }
 }
 
-pub async fn list_cabinet_products(
+pub async fn list_cabinet_products<'e, E>(
     id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<Vec<CabinetProduct>, potion::Error> {
+    executor: E,
+) -> Result<Vec<CabinetProduct>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let list: Vec<CabinetProduct> =
         sqlx::query_as("SELECT * FROM cabinet_products WHERE cabinet_id = $1 ORDER BY id")
             .bind(id)
-            .fetch_all(pool)
+            .fetch_all(executor)
             .await
             .map_err(|e| QueryError::from(e).into())?;
 
     Ok(list)
 }
 
-pub async fn list_cabinet_mixers_rsm(
+pub async fn list_cabinet_mixers_rsm<'e, E>(
     id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<Vec<CabinetMixer>, potion::Error> {
+    executor: E,
+) -> Result<Vec<CabinetMixer>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let list: Vec<CabinetMixer> =
         sqlx::query_as("SELECT * FROM cabinet_mixers WHERE cabinet_id = $1 ORDER BY id")
             .bind(id)
-            .fetch_all(pool)
+            .fetch_all(executor)
             .await
             .map_err(|e| QueryError::from(e).into())?;
     Ok(list)
 }
 
-pub async fn list_cabinet_mixers(
+pub async fn list_cabinet_mixers<'e, E>(
     id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<Vec<CabinetMixerOwned>, potion::Error> {
+    executor: E,
+) -> Result<Vec<CabinetMixerOwned>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let list: Vec<CabinetMixer> =
         sqlx::query_as("SELECT * FROM cabinet_mixers WHERE cabinet_id = $1 ORDER BY id")
             .bind(id)
-            .fetch_all(pool)
+            .fetch_all(executor)
             .await
             .map_err(|e| QueryError::from(e).into())?;
 
@@ -233,136 +265,174 @@ pub async fn list_cabinet_mixers(
     Ok(list)
 }
 
-pub async fn list_cabinet_access_list(
+pub async fn list_cabinet_access_list<'e, E>(
     id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<Vec<CabinetMember>, potion::Error> {
+    executor: E,
+) -> Result<Vec<CabinetMember>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let list: Vec<CabinetMember> =
         sqlx::query_as("SELECT * FROM shared_cabinets WHERE cabinet_id = $1")
             .bind(id)
-            .fetch_all(pool)
+            .fetch_all(executor)
             .await
             .map_err(|e| QueryError::from(e).into())?;
 
     Ok(list)
 }
 
-pub async fn get_cabinet_mixer(
+pub async fn get_cabinet_mixer<'e, E>(
     i_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<Option<CabinetMixer>, potion::Error> {
+    executor: E,
+) -> Result<Option<CabinetMixer>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let mixer: Option<CabinetMixer> = sqlx::query_as("SELECT * FROM cabinet_mixers WHERE id = $1")
         .bind(i_id)
-        .fetch_optional(pool)
+        .fetch_optional(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(mixer)
 }
 
-pub async fn get_cabinet_mixer_owned(
+pub async fn get_cabinet_mixer_owned<'e, E>(
     id: i32,
     incredient_id: i32,
     user_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<Option<CabinetMixer>, potion::Error> {
+    executor: E,
+) -> Result<Option<CabinetMixer>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let mixer: Option<CabinetMixer> = sqlx::query_as("SELECT * FROM cabinet_mixers WHERE cabinet_id = $1 AND incredient_id = $2 AND owner_id = $3")
         .bind(id)
         .bind(incredient_id)
         .bind(user_id)
-        .fetch_optional(pool)
+        .fetch_optional(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(mixer)
 }
 
-pub async fn modify_mixer_in_cabinet(
+pub async fn modify_mixer_in_cabinet<'a, A>(
     id: i32,
     incredient_id: i32,
     user_id: i32,
-    amount: Option<i32>,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
-    let mixer = get_cabinet_mixer_owned(id, incredient_id, user_id, pool).await?;
+    amount: Option<f64>,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let mixer = get_cabinet_mixer_owned(id, incredient_id, user_id, &mut *tr).await?;
     if mixer.is_none() {
-        add_mixer_to_cabinet(id, user_id, incredient_id, amount, pool).await?;
+        add_mixer_to_cabinet(id, user_id, incredient_id, amount, &mut *tr).await?;
     } else {
         sqlx::query("UPDATE cabinet_mixers SET amount = $1 WHERE cabinet_id = $2 AND incredient_id = $3 AND owner_id = $4")
             .bind(amount)
             .bind(id)
             .bind(incredient_id)
             .bind(user_id)
-            .execute(pool)
+            .execute(&mut *tr)
             .await
             .map_err(|e| QueryError::from(e).into())?;
     }
 
-    update_cabinet_checksum(id, pool).await?;
+    update_cabinet_checksum(id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn modify_mixer_in_cabinet_rsm(
+pub async fn modify_mixer_in_cabinet_rsm<'a, A>(
     id: i32,
     mixer_id: i32,
-    amount: Option<i32>,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    amount: Option<f64>,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     sqlx::query("UPDATE cabinet_mixers SET amount = $1 WHERE id = $2")
         .bind(amount)
         .bind(mixer_id)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
-    update_cabinet_checksum(id, pool).await?;
+    update_cabinet_checksum(id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
 /// DOES NOT CHECK OWNERSHIP OR RIGHTS
-pub async fn set_mixer_usable(
-    id: i32,
-    cabinet_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+pub async fn set_mixer_usable<'a, A>(id: i32, cabinet_id: i32, conn: A) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     sqlx::query("UPDATE cabinet_mixers SET usable = true WHERE id = $1")
         .bind(id)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
-    update_cabinet_checksum(cabinet_id, pool).await?;
+    update_cabinet_checksum(cabinet_id, &mut *tr).await?;
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
     Ok(())
 }
 
 /// DOES NOT CHECK OWNERSHIP OR RIGHTS
-pub async fn set_mixer_unusable(
+pub async fn set_mixer_unusable<'a, A>(
     id: i32,
     cabinet_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     sqlx::query("UPDATE cabinet_mixers SET usable = false WHERE id = $1")
         .bind(id)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
-    update_cabinet_checksum(cabinet_id, pool).await?;
+    update_cabinet_checksum(cabinet_id, &mut *tr).await?;
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
     Ok(())
 }
 
-pub async fn add_to_cabinet(
+pub async fn add_to_cabinet<'a, A>(
     id: i32,
     user_id: i32,
     product_id: i32,
     amount_ml: Option<i32>,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
-    let product = get_product(product_id, pool).await?;
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let product = get_product(product_id, &mut *tr).await?;
     if product.is_none() {
-        return Err(HtmlError::InvalidRequest.new("Product with specified id doesn't exists"));
+        return Err(SdkError::not_found("Product with specified id doesn't exists").into());
     }
     let product = product.unwrap();
 
@@ -379,31 +449,36 @@ pub async fn add_to_cabinet(
     .bind(product.href)
     .bind(product.abv)
     .bind(amount_ml)
-    .execute(pool)
+    .execute(&mut *tr)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
     if result.rows_affected() <= 0 {
-        return Err(HtmlError::InvalidRequest
-            .new("Product is already in the cabinet")
-            .into());
+        return Err(SdkError::conflict("Product is already in the cabinet").into());
     }
 
-    update_cabinet_checksum(id, pool).await?;
+    update_cabinet_checksum(id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn add_mixer_to_cabinet(
+pub async fn add_mixer_to_cabinet<'a, A>(
     id: i32,
     user_id: i32,
     ingredient_id: i32,
-    amount_ml: Option<i32>,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
-    let ingredient = get_incredient(ingredient_id, pool).await?;
+    amount: Option<f64>,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let ingredient = get_incredient(ingredient_id, &mut *tr).await?;
     if ingredient.is_none() {
-        return Err(HtmlError::InvalidRequest.new("Product with specified id doesn't exists"));
+        return Err(SdkError::not_found("Product with specified id doesn't exists").into());
     }
     let incredient = ingredient.unwrap();
 
@@ -417,45 +492,55 @@ pub async fn add_mixer_to_cabinet(
     .bind(user_id)
     .bind(incredient.name)
     .bind(incredient.unit)
-    .bind(amount_ml)
-    .execute(pool)
+    .bind(amount)
+    .execute(&mut *tr)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
     if result.rows_affected() <= 0 {
-        return Err(HtmlError::InvalidRequest
-            .new("Mixer is already in the cabinet")
-            .into());
+        return Err(SdkError::conflict("Mixer is already in the cabinet").into());
     }
 
-    update_cabinet_checksum(id, pool).await?;
+    update_cabinet_checksum(id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
 /// Note: This method will perform automatic checks to determine the ownerships of imported products
 /// * This is due to the fact that such check would be impossible to implement outside this method withot additiona overhead
-pub async fn add_to_cabinet_bulk(
+pub async fn add_to_cabinet_bulk<'a, A>(
     cabinet_id: i32,
     id_map: &[i32],
     user_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
-    let product_list: Vec<CabinetProduct> = fetch_cabinet_products(&id_map, &pool)
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let product_list: Vec<CabinetProduct> = fetch_cabinet_products(&id_map, &mut *tr)
         .await?
         .drain(..)
         .filter(|p| p.owner_id == user_id)
         .collect();
 
-    insert_cabinet_products(cabinet_id, &product_list, user_id, &pool).await?;
+    insert_cabinet_products(cabinet_id, &product_list, user_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn fetch_cabinet_products(
+pub async fn fetch_cabinet_products<'e, E>(
     id_map: &[i32],
-    pool: &Pool<Postgres>,
-) -> Result<Vec<CabinetProduct>, potion::Error> {
+    executor: E,
+) -> Result<Vec<CabinetProduct>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     if id_map.len() <= 0 {
         return Ok(vec![]);
     }
@@ -469,7 +554,7 @@ pub async fn fetch_cabinet_products(
 
     let list: Vec<CabinetProduct> = query_builder
         .build()
-        .fetch_all(&*pool)
+        .fetch_all(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?
         .iter()
@@ -479,12 +564,17 @@ pub async fn fetch_cabinet_products(
     Ok(list)
 }
 
-pub async fn insert_cabinet_products(
+pub async fn insert_cabinet_products<'a, A>(
     cabinet_id: i32,
     product_map: &[CabinetProduct],
     user_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     if product_map.len() <= 0 {
         return Ok(());
     }
@@ -506,33 +596,40 @@ pub async fn insert_cabinet_products(
 
     query_builder
         .build()
-        .execute(&*pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
-    update_cabinet_checksum(cabinet_id, pool).await?;
+    update_cabinet_checksum(cabinet_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn remove_from_cabinet(
+pub async fn remove_from_cabinet<'a, A>(
     id: i32,
     product_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     let result = sqlx::query("DELETE FROM cabinet_products WHERE id = $1")
         .bind(product_id)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     if result.rows_affected() <= 0 {
-        return Err(HtmlError::InvalidRequest
-            .new("Product was already removed from the cabinet")
-            .into());
+        return Err(SdkError::not_found("Product was already removed from the cabinet").into());
     }
 
-    update_cabinet_checksum(id, pool).await?;
+    update_cabinet_checksum(id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
@@ -540,15 +637,20 @@ pub async fn remove_from_cabinet(
 /// Removes a mixer that you own from a cabinet
 // TODO: Move checking for ownership somewhere else so that admins are able to modify products not
 // owned by themselves
-pub async fn remove_mixer_from_cabinet(
+pub async fn remove_mixer_from_cabinet<'a, A>(
     id: i32,
     incredient_id: i32,
     user_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
-    let mixer = get_cabinet_mixer_owned(id, incredient_id, user_id, pool).await?;
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let mixer = get_cabinet_mixer_owned(id, incredient_id, user_id, &mut *tr).await?;
     if mixer.is_none() {
-        return Err(HtmlError::InvalidRequest.new("Mixer doesn't exists"));
+        return Err(SdkError::not_found("Mixer doesn't exists").into());
     }
     let mixer = mixer.unwrap();
 
@@ -556,137 +658,171 @@ pub async fn remove_mixer_from_cabinet(
         sqlx::query("DELETE FROM cabinet_mixers WHERE cabinet_id = $1 AND incredient_id = $2")
             .bind(id)
             .bind(mixer.incredient_id)
-            .execute(pool)
+            .execute(&mut *tr)
             .await
             .map_err(|e| QueryError::from(e).into())?;
 
     if result.rows_affected() <= 0 {
-        return Err(HtmlError::InvalidRequest
-            .new("Product was already removed from the cabinet")
-            .into());
+        return Err(SdkError::not_found("Product was already removed from the cabinet").into());
     }
 
-    update_cabinet_checksum(id, pool).await?;
+    update_cabinet_checksum(id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
 /// DOES NOT CHECK FOR OWNERSHIP
-pub async fn remove_mixer_from_cabinet_rsm(
+pub async fn remove_mixer_from_cabinet_rsm<'a, A>(
     id: i32,
     mixer_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     let result = sqlx::query("DELETE FROM cabinet_mixers WHERE id = $1")
         .bind(mixer_id)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     if result.rows_affected() <= 0 {
-        return Err(HtmlError::InvalidRequest
-            .new("Product was already removed from the cabinet")
-            .into());
+        return Err(SdkError::not_found("Product was already removed from the cabinet").into());
     }
 
-    update_cabinet_checksum(id, pool).await?;
+    update_cabinet_checksum(id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn set_product_unusable(
+pub async fn set_product_unusable<'a, A>(
     id: i32,
     product_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     sqlx::query("UPDATE cabinet_products SET usable = false WHERE id = $1")
         .bind(product_id)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
-    update_cabinet_checksum(id, pool).await?;
+    update_cabinet_checksum(id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn set_product_usable(
+pub async fn set_product_usable<'a, A>(
     id: i32,
     product_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     sqlx::query("UPDATE cabinet_products SET usable = true WHERE id = $1")
         .bind(product_id)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
-    update_cabinet_checksum(id, pool).await?;
+    update_cabinet_checksum(id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn set_cabinet_name(
-    id: i32,
-    name: &str,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+pub async fn set_cabinet_name<'a, A>(id: i32, name: &str, conn: A) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     sqlx::query("UPDATE cabinets SET name = $1 WHERE id = $2")
         .bind(name)
         .bind(id)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
-    update_cabinet_checksum(id, pool).await?;
+    update_cabinet_checksum(id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn set_product_amount(
+pub async fn set_product_amount<'a, A>(
     id: i32,
     product_id: i32,
     amount: Option<i32>,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     sqlx::query("UPDATE cabinet_products SET amount_ml = $1 WHERE id = $2")
         .bind(amount)
         .bind(product_id)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
-    update_cabinet_checksum(id, pool).await?;
+    update_cabinet_checksum(id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn generate_cabinet_access_token(
-    id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+pub async fn generate_cabinet_access_token<'a, A>(id: i32, conn: A) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     let token = generate_access_token();
 
     sqlx::query("UPDATE cabinets SET access_key = $1 WHERE id = $2")
         .bind(token)
         .bind(id)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
-    update_cabinet_checksum(id, pool).await?;
+    update_cabinet_checksum(id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn add_user_to_cabinet(
-    id: i32,
-    user_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
-    let user = get_user_by_id(pool, user_id).await?;
+pub async fn add_user_to_cabinet<'a, A>(id: i32, user_id: i32, conn: A) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let user = get_user_by_id(&mut *tr, user_id).await?;
     if user.is_none() {
-        return Err(HtmlError::InvalidRequest.new("User doesn't exists"));
+        return Err(SdkError::not_found("User doesn't exists").into());
     }
 
     sqlx::query(
@@ -695,38 +831,47 @@ pub async fn add_user_to_cabinet(
     .bind(id)
     .bind(user_id)
     .bind(user.unwrap().username)
-    .execute(pool)
+    .execute(&mut *tr)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
     Ok(())
 }
 
-pub async fn remove_user_from_cabinet(
+pub async fn remove_user_from_cabinet<'a, A>(
     id: i32,
     user_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     sqlx::query("DELETE FROM shared_cabinets WHERE cabinet_id = $1 AND user_id = $2")
         .bind(id)
         .bind(user_id)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     sqlx::query("DELETE FROM cabinet_products WHERE cabinet_id = $1 AND owner_id = $2")
         .bind(id)
         .bind(user_id)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     sqlx::query("DELETE FROM cabinet_mixers WHERE cabinet_id = $1 AND owner_id = $2")
         .bind(id)
         .bind(user_id)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
     Ok(())
 }
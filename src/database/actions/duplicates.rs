@@ -0,0 +1,283 @@
+use sqlx::{Acquire, Executor, Postgres};
+
+use crate::{
+    error::{QueryError, SdkError},
+    schema::{DuplicateCandidate, MatchKind},
+    StandardRecipeSyntax, DUPLICATE_CANDIDATE_COUNT, DUPLICATE_CANDIDATE_THRESHOLD,
+    DUPLICATE_THRESHOLD,
+};
+
+use super::{get_recipe, link_tags, match_incredient, record_recipe_revision};
+
+/// Normalized names of the incredients of the recipe `r`
+const RECIPE_INCREDIENT_NAMES: &str = "
+    ARRAY(
+        SELECT DISTINCT search_normalize(d.name)
+        FROM recipe_parts rp
+        INNER JOIN drink_incredients d ON d.id = rp.incredient_id
+        WHERE rp.recipe_id = r.recipe_id
+    )
+";
+
+/// Normalized name and incredient names of every recipe, comparable with the fingerprints of `parsed_drinks`
+fn recipe_fingerprints_cte() -> String {
+    format!(
+        "
+        recipe_fingerprints AS (
+            SELECT r.id, r.name, search_normalize(r.name) AS normalized_name,
+                {RECIPE_INCREDIENT_NAMES} AS incredient_names
+            FROM drink_recipes r
+        )
+    "
+    )
+}
+
+/// Similarity of two recipes between 0 and 1, the average of the name similarity and the share of incredients in common
+fn duplicate_score(name: &str, names: &str, other_name: &str, other_names: &str) -> String {
+    format!(
+        "(similarity({name}, {other_name})::FLOAT8 + array_jaccard({names}, {other_names})) / 2"
+    )
+}
+
+/// Stores the name and the normalized incredient names of a parsed recipe.
+/// Incredients resolved without fuzzy matching are stored by their name in `drink_incredients`,
+/// so that "lime juice" and "Limettimehu" compare as equal. Unparseable recipes get an empty fingerprint.
+async fn index_parsed_recipe<'a, A>(id: i32, value: String, conn: A) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let (name, names) = match StandardRecipeSyntax::try_from(value) {
+        Ok(srs) => {
+            let mut names = Vec::with_capacity(srs.parts.len());
+            for part in srs.parts {
+                let matched = match_incredient(&part.incredient_name, &mut *tr).await?;
+                let name = match (matched.incredient, matched.kind) {
+                    (Some(incredient), Some(kind)) if kind != MatchKind::Fuzzy => incredient.name,
+                    _ => part.incredient_name,
+                };
+                names.push(name);
+            }
+            (srs.name, names)
+        }
+        Err(_) => (String::new(), vec![]),
+    };
+
+    sqlx::query(
+        "
+        UPDATE parsed_drinks
+        SET name = $2, incredient_names = ARRAY(SELECT DISTINCT search_normalize(n) FROM unnest($3::TEXT[]) n)
+        WHERE id = $1
+    ",
+    )
+    .bind(id)
+    .bind(name)
+    .bind(names)
+    .execute(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+/// Fingerprints the parsed recipes that haven't been indexed yet, and computes their
+/// `duplicate_score` against the existing recipes and the parsed recipes queued before them,
+/// so that one of each group stays visible. Only candidates with names similar enough to reach
+/// `DUPLICATE_THRESHOLD` are scored, found with the trigram indexes on the normalized names.
+/// Returns the number of parsed recipes that were indexed.
+pub async fn index_parsed_recipes<'a, A>(conn: A) -> Result<u64, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let rows: Vec<(i32, String)> =
+        sqlx::query_as("SELECT id, value FROM parsed_drinks WHERE NOT added AND name IS NULL")
+            .fetch_all(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+
+    let indexed = rows.len() as u64;
+    for (id, value) in rows {
+        index_parsed_recipe(id, value, &mut *tr).await?;
+    }
+
+    // The incredients can add at most half of the score, so names less similar than this can't reach the threshold
+    sqlx::query(&format!(
+        "SET LOCAL pg_trgm.similarity_threshold = {}",
+        2. * DUPLICATE_THRESHOLD - 1.
+    ))
+    .execute(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    // Only unscored rows, which includes parsed recipes indexed by `find_duplicates`
+    let parsed_name = "search_normalize(p.name)";
+    sqlx::query(&format!(
+        "
+        UPDATE parsed_drinks p SET duplicate_score = COALESCE(GREATEST(
+            (
+                SELECT MAX({})
+                FROM drink_recipes r
+                WHERE search_normalize(r.name) % {parsed_name}
+            ),
+            (
+                SELECT MAX({})
+                FROM parsed_drinks o
+                WHERE search_normalize(o.name) % {parsed_name}
+                    AND o.id < p.id AND NOT o.added AND o.name IS NOT NULL
+            )
+        ), 0)
+        WHERE NOT p.added AND p.name IS NOT NULL AND p.duplicate_score IS NULL
+    ",
+        duplicate_score(
+            parsed_name,
+            "p.incredient_names",
+            "search_normalize(r.name)",
+            RECIPE_INCREDIENT_NAMES
+        ),
+        duplicate_score(
+            parsed_name,
+            "p.incredient_names",
+            "search_normalize(o.name)",
+            "o.incredient_names"
+        ),
+    ))
+    .execute(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(indexed)
+}
+
+/// Existing recipes and other queued parsed recipes that are similar to a parsed recipe, most similar first
+pub async fn find_duplicates<'a, A>(
+    parsed_id: i32,
+    conn: A,
+) -> Result<Vec<DuplicateCandidate>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let row: Option<(String, Option<String>)> =
+        sqlx::query_as("SELECT value, name FROM parsed_drinks WHERE id = $1")
+            .bind(parsed_id)
+            .fetch_optional(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+
+    match row {
+        None => return Err(SdkError::not_found("Parsed recipe doesn't exist").into()),
+        Some((value, None)) => index_parsed_recipe(parsed_id, value, &mut *tr).await?,
+        Some(_) => (),
+    }
+
+    let rows: Vec<DuplicateCandidate> = sqlx::query_as(&format!(
+        "
+        WITH {},
+        target AS (
+            SELECT search_normalize(name) AS normalized_name, incredient_names
+            FROM parsed_drinks
+            WHERE id = $1
+        ),
+        candidates AS (
+            SELECT 'recipe' AS source, rf.id, rf.name,
+                similarity(rf.normalized_name, t.normalized_name)::FLOAT8 AS name_score,
+                array_jaccard(rf.incredient_names, t.incredient_names) AS incredient_score
+            FROM recipe_fingerprints rf, target t
+            UNION ALL
+            SELECT 'parsed', o.id, o.name,
+                similarity(search_normalize(o.name), t.normalized_name)::FLOAT8,
+                array_jaccard(o.incredient_names, t.incredient_names)
+            FROM parsed_drinks o, target t
+            WHERE o.id != $1 AND NOT o.added AND o.name IS NOT NULL
+        )
+        SELECT *, (name_score + incredient_score) / 2 AS score
+        FROM candidates
+        WHERE (name_score + incredient_score) / 2 >= $2
+        ORDER BY score DESC, name
+        LIMIT $3
+    ",
+        recipe_fingerprints_cte()
+    ))
+    .bind(parsed_id)
+    .bind(DUPLICATE_CANDIDATE_THRESHOLD)
+    .bind(DUPLICATE_CANDIDATE_COUNT)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(rows)
+}
+
+/// Removes parsed recipes from the queue, see `skip_parsed_recipe`.
+/// Returns the number of parsed recipes removed.
+pub async fn skip_parsed_recipes<'e, E>(
+    parsed_ids: &[i32],
+    executor: E,
+) -> Result<u64, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let result = sqlx::query("DELETE FROM parsed_drinks WHERE id = ANY($1) AND NOT added")
+        .bind(parsed_ids)
+        .execute(executor)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(result.rows_affected())
+}
+
+/// Marks parsed recipes as duplicates of an existing recipe. They are removed from the queue,
+/// and their tags are added to the recipe. Returns the number of parsed recipes merged.
+pub async fn merge_parsed_recipes<'a, A>(
+    parsed_ids: &[i32],
+    recipe_id: i32,
+    user_id: i32,
+    conn: A,
+) -> Result<u64, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    if get_recipe(recipe_id, &mut *tr).await?.is_none() {
+        return Err(SdkError::not_found("Recipe doesn't exists").into());
+    }
+
+    let rows: Vec<(i32, String)> = sqlx::query_as(
+        "SELECT id, value FROM parsed_drinks WHERE id = ANY($1) AND NOT added FOR UPDATE",
+    )
+    .bind(parsed_ids)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let mut tags: Vec<String> = vec![];
+    for (_, value) in rows.iter() {
+        if let Ok(srs) = StandardRecipeSyntax::try_from(value.clone()) {
+            tags.extend(srs.tags);
+        }
+    }
+    link_tags(recipe_id, &tags, &mut *tr).await?;
+    record_recipe_revision(recipe_id, user_id, &mut *tr).await?;
+
+    let ids: Vec<i32> = rows.iter().map(|(id, _)| *id).collect();
+    sqlx::query("UPDATE parsed_drinks SET added = true WHERE id = ANY($1)")
+        .bind(&ids)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(ids.len() as u64)
+}
@@ -0,0 +1,140 @@
+use sqlx::{Acquire, Executor, Postgres};
+
+use crate::{
+    error::{QueryError, SdkError},
+    schema::{NewRecipePart, RecipeMethod, RecipeVariant},
+};
+
+use super::{
+    get_recipe, insert_recipe_with_parts, list_recipe_parts, record_recipe_revision,
+    set_recipe_method, set_recipe_servings,
+};
+
+/// Copies the parts, tags, info, servings and method of a recipe into a new recipe owned by `user_id`,
+/// linked to the original as its parent. The copy keeps the name of the original unless `name` is given.
+/// Names are unique per author, so forking one of your own recipes needs a new name.
+/// Returns the id of the new recipe.
+pub async fn fork_recipe<'a, A>(
+    recipe_id: i32,
+    user_id: i32,
+    name: Option<String>,
+    conn: A,
+) -> Result<i32, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let recipe = get_recipe(recipe_id, &mut *tr)
+        .await?
+        .ok_or_else(|| SdkError::not_found("Recipe doesn't exists").into())?;
+
+    let name = match name.map(|name| name.trim().to_owned()) {
+        Some(name) if name.is_empty() => {
+            return Err(SdkError::validation("Recipe name can't be empty").into())
+        }
+        Some(name) => name,
+        None => recipe.name.clone(),
+    };
+
+    let parts: Vec<NewRecipePart> = list_recipe_parts(&mut *tr, recipe.recipe_id)
+        .await?
+        .into_iter()
+        .map(|part| NewRecipePart {
+            incredient_id: part.incredient_id,
+            amount: part.amount,
+            unit: part.unit,
+        })
+        .collect();
+
+    let tag_ids: Vec<i32> =
+        sqlx::query_scalar("SELECT tag_id FROM recipe_tags_map WHERE recipe_id = $1")
+            .bind(recipe_id)
+            .fetch_all(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+
+    let id = insert_recipe_with_parts(
+        recipe.r#type.clone(),
+        user_id,
+        name,
+        recipe.info.clone(),
+        &parts,
+        &tag_ids,
+        &mut *tr,
+    )
+    .await?;
+
+    sqlx::query("UPDATE drink_recipes SET parent_id = $1 WHERE id = $2")
+        .bind(recipe_id)
+        .bind(id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    set_recipe_servings(id, recipe.servings, &mut *tr).await?;
+    set_recipe_method(id, &RecipeMethod::from(recipe), &mut *tr).await?;
+    record_recipe_revision(id, user_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(id)
+}
+
+/// Recipes forked directly from a recipe, oldest first
+pub async fn list_recipe_variants<'e, E>(
+    recipe_id: i32,
+    executor: E,
+) -> Result<Vec<RecipeVariant>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let rows: Vec<RecipeVariant> = sqlx::query_as(
+        "
+        SELECT r.id, r.name, r.author_id, u.username AS author, r.parent_id
+        FROM drink_recipes r
+        INNER JOIN users u ON u.id = r.author_id
+        WHERE r.parent_id = $1
+        ORDER BY r.id
+    ",
+    )
+    .bind(recipe_id)
+    .fetch_all(executor)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(rows)
+}
+
+/// Recipes a recipe was forked from, starting from its parent and ending with the original.
+/// Empty if the recipe isn't a fork.
+pub async fn list_recipe_ancestors<'e, E>(
+    recipe_id: i32,
+    executor: E,
+) -> Result<Vec<RecipeVariant>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let rows: Vec<RecipeVariant> = sqlx::query_as(
+        "
+        WITH RECURSIVE ancestors AS (
+            SELECT parent_id AS id, 1 AS depth FROM drink_recipes WHERE id = $1
+            UNION ALL
+            SELECT r.parent_id, a.depth + 1
+            FROM ancestors a
+            INNER JOIN drink_recipes r ON r.id = a.id
+        )
+        SELECT r.id, r.name, r.author_id, u.username AS author, r.parent_id
+        FROM ancestors a
+        INNER JOIN drink_recipes r ON r.id = a.id
+        INNER JOIN users u ON u.id = r.author_id
+        ORDER BY a.depth
+    ",
+    )
+    .bind(recipe_id)
+    .fetch_all(executor)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(rows)
+}
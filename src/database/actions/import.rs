@@ -0,0 +1,380 @@
+use sqlx::{Acquire, Executor, Postgres, QueryBuilder};
+
+use crate::{
+    error::{QueryError, SdkError},
+    recipe_text::{ParsedIngredient, ResolvedIngredient},
+    schema::{
+        ImportReport, ImportedPart, IncredientMatch, IncredientName, MatchKind, NewRecipePart,
+        ParsedRecipeRow, RecipeTag, RecipeType, UnresolvedPart,
+    },
+    search::TextSearch,
+    StandardRecipeSyntax, INCREDIENT_MATCH_CANDIDATES, INCREDIENT_MATCH_THRESHOLD,
+    INCREDIENT_TRANSLATIONS,
+};
+
+use super::{
+    check_piece_volumes, create_tag, find_incredient, find_tag, get_recipe, insert_recipe,
+    link_tag, list_recipe_tags, record_recipe_revision, set_search_threshold, write_recipe_parts,
+};
+
+/// Incredient matching `name`, with the closest candidates.
+/// The name is tried exactly (ignoring case), as an alias, and as the Finnish translation
+/// of a common English name, before being matched with full-text search and trigram similarity.
+pub async fn match_incredient<'a, A>(name: &str, conn: A) -> Result<IncredientMatch, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let translation = INCREDIENT_TRANSLATIONS
+        .iter()
+        .find(|(english, _)| english.eq_ignore_ascii_case(name.trim()))
+        .map(|(_, finnish)| *finnish);
+
+    let mut found = find_incredient(name.trim(), &mut *tr)
+        .await?
+        .map(|id| (id, MatchKind::Exact));
+    if found.is_none() {
+        found = find_incredient_by_alias(name.trim(), &mut *tr)
+            .await?
+            .map(|id| (id, MatchKind::Alias));
+    }
+    if let (None, Some(translation)) = (&found, translation) {
+        found = find_incredient(translation, &mut *tr)
+            .await?
+            .map(|id| (id, MatchKind::Translation));
+    }
+
+    if let Some((id, kind)) = found {
+        let name: String = sqlx::query_scalar("SELECT name FROM drink_incredients WHERE id = $1")
+            .bind(id)
+            .fetch_one(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+        let incredient = IncredientName { id, name };
+
+        tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+        return Ok(IncredientMatch {
+            incredient: Some(incredient.clone()),
+            kind: Some(kind),
+            score: 1.,
+            candidates: vec![incredient],
+        });
+    }
+
+    set_search_threshold(&mut *tr).await?;
+
+    let mut candidates: Vec<(i32, String, f64)> = vec![];
+    for query in [Some(name), translation].into_iter().flatten() {
+        for candidate in fuzzy_incredients(query, &mut *tr).await? {
+            match candidates.iter_mut().find(|(id, _, _)| *id == candidate.0) {
+                Some(existing) => existing.2 = existing.2.max(candidate.2),
+                None => candidates.push(candidate),
+            }
+        }
+    }
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    candidates.sort_by(|a, b| b.2.total_cmp(&a.2).then_with(|| a.1.cmp(&b.1)));
+    candidates.truncate(INCREDIENT_MATCH_CANDIDATES as usize);
+
+    let score = candidates.first().map(|(_, _, score)| *score).unwrap_or(0.);
+    let candidates: Vec<IncredientName> = candidates
+        .into_iter()
+        .map(|(id, name, _)| IncredientName { id, name })
+        .collect();
+    let incredient = match score >= INCREDIENT_MATCH_THRESHOLD {
+        true => candidates.first().cloned(),
+        false => None,
+    };
+
+    Ok(IncredientMatch {
+        kind: incredient.as_ref().map(|_| MatchKind::Fuzzy),
+        incredient,
+        score,
+        candidates,
+    })
+}
+
+async fn find_incredient_by_alias<'e, E>(
+    alias: &str,
+    executor: E,
+) -> Result<Option<i32>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let id: Option<i32> = sqlx::query_scalar(
+        "SELECT incredient_id FROM incredient_aliases WHERE LOWER(alias) = LOWER($1)",
+    )
+    .bind(alias)
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(id)
+}
+
+/// Matches full-text or fuzzily, scored by the average of trigram similarity to the
+/// whole name and to its closest part, so that "bitters" is close to "Angostura bitters"
+async fn fuzzy_incredients<'e, E>(
+    name: &str,
+    executor: E,
+) -> Result<Vec<(i32, String, f64)>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let Some(search) = TextSearch::new(name) else {
+        return Ok(vec![]);
+    };
+
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        "SELECT d.id, d.name, ((similarity(search_normalize(d.name), search_normalize(",
+    );
+    builder
+        .push_bind(search.query.clone())
+        .push(")) + word_similarity(search_normalize(")
+        .push_bind(search.query.clone())
+        .push(
+            "), search_normalize(d.name))) / 2)::FLOAT8 AS score FROM drink_incredients d WHERE ",
+        );
+    search.push_match("d.name", &mut builder);
+    builder
+        .push(" ORDER BY score DESC, d.name LIMIT ")
+        .push_bind(INCREDIENT_MATCH_CANDIDATES);
+
+    let rows: Vec<(i32, String, f64)> = builder
+        .build_query_as()
+        .fetch_all(executor)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(rows)
+}
+
+/// Resolves the names of parsed ingredients to incredients, see `match_incredient`.
+/// Items are returned in the same order, unresolved ones with `incredient` set to `None`.
+pub async fn resolve_ingredients<'a, A>(
+    items: &[ParsedIngredient],
+    conn: A,
+) -> Result<Vec<ResolvedIngredient>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let mut resolved = Vec::with_capacity(items.len());
+    for item in items {
+        let matched = match_incredient(&item.name, &mut *tr).await?;
+        resolved.push(ResolvedIngredient {
+            confidence: item.confidence * matched.score,
+            item: item.clone(),
+            incredient: matched.incredient,
+            kind: matched.kind,
+            match_score: matched.score,
+            candidates: matched.candidates,
+        });
+    }
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(resolved)
+}
+
+/// Creates a recipe from a parsed recipe, with its type, info, parts and tags.
+/// Recipes without a type are `Generated`.
+/// Every part is resolved with `match_incredient`, and parts that can't be resolved are
+/// left out and listed in the report. Garnish parts become the garnish of the recipe.
+/// Recipes have no optional parts, so optional parts are resolved too but left out, and listed
+/// in the report with their matching incredient as the first candidate.
+/// Missing tags are created.
+/// Everything happens in one transaction, nothing is created if any of the steps fail.
+pub async fn import_parsed_recipe<'a, A>(
+    parsed_id: i32,
+    user_id: i32,
+    name: String,
+    conn: A,
+) -> Result<ImportReport, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let row: Option<ParsedRecipeRow> =
+        sqlx::query_as("SELECT * FROM parsed_drinks WHERE id = $1 FOR UPDATE")
+            .bind(parsed_id)
+            .fetch_optional(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+
+    let Some(row) = row else {
+        return Err(SdkError::not_found("Parsed recipe doesn't exist").into());
+    };
+    if row.added {
+        return Err(SdkError::conflict("Parsed recipe has already been imported").into());
+    }
+
+    let srs = StandardRecipeSyntax::try_from(row.value).map_err(|e| e.into())?;
+
+    sqlx::query("UPDATE parsed_drinks SET added = true WHERE id = $1")
+        .bind(parsed_id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    let recipe_type = srs.recipe_type.clone().unwrap_or(RecipeType::Generated);
+    let id = insert_recipe(recipe_type, user_id, name, &mut *tr).await?;
+    let recipe = get_recipe(id, &mut *tr)
+        .await?
+        .ok_or_else(|| SdkError::internal("Failed to create recipe").into())?;
+
+    sqlx::query(
+        "UPDATE drink_recipes SET import_origin = $1, info = COALESCE($2, info) WHERE id = $3",
+    )
+    .bind(parsed_id)
+    .bind(&srs.info)
+    .bind(id)
+    .execute(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let mut parts: Vec<ImportedPart> = vec![];
+    let mut unresolved: Vec<UnresolvedPart> = vec![];
+    let mut garnishes: Vec<String> = vec![];
+
+    for part in srs.parts {
+        if part.garnish {
+            garnishes.push(part.incredient_name);
+            continue;
+        }
+
+        let (amount, unit) = (part.amount, part.unit);
+        let matched = match_incredient(&part.incredient_name, &mut *tr).await?;
+
+        if part.optional {
+            unresolved.push(UnresolvedPart {
+                source_name: part.incredient_name,
+                amount,
+                unit,
+                reason: String::from("Optional parts can't be added to recipes"),
+                candidates: matched.candidates,
+            });
+            continue;
+        }
+
+        let (Some(incredient), Some(kind)) = (matched.incredient, matched.kind) else {
+            unresolved.push(UnresolvedPart {
+                source_name: part.incredient_name,
+                amount,
+                unit,
+                reason: String::from("No matching incredient"),
+                candidates: matched.candidates,
+            });
+            continue;
+        };
+
+        // Recipes can contain an incredient only once
+        match parts
+            .iter_mut()
+            .find(|existing| existing.incredient.id == incredient.id)
+        {
+            Some(existing) => match unit.convert(amount, existing.unit.clone()) {
+                Ok((_, value)) => existing.amount += value,
+                Err(_) => unresolved.push(UnresolvedPart {
+                    source_name: part.incredient_name,
+                    amount,
+                    unit,
+                    reason: format!(
+                        "{} is already in the recipe in another unit",
+                        incredient.name
+                    ),
+                    candidates: vec![incredient],
+                }),
+            },
+            None => parts.push(ImportedPart {
+                source_name: part.incredient_name,
+                incredient,
+                amount,
+                unit,
+                kind,
+                score: matched.score,
+            }),
+        }
+    }
+
+    let new_parts: Vec<NewRecipePart> = parts
+        .iter()
+        .map(|part| NewRecipePart {
+            incredient_id: part.incredient.id,
+            amount: part.amount,
+            unit: part.unit.clone(),
+        })
+        .collect();
+    check_piece_volumes(&new_parts, None, &mut *tr).await?;
+    write_recipe_parts(recipe.recipe_id, &new_parts, &mut *tr).await?;
+
+    if !garnishes.is_empty() {
+        sqlx::query("UPDATE drink_recipes SET garnish = $1 WHERE id = $2")
+            .bind(garnishes.join(", "))
+            .bind(id)
+            .execute(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+    }
+
+    let (tags, created_tags) = link_tags(id, &srs.tags, &mut *tr).await?;
+    record_recipe_revision(id, user_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(ImportReport {
+        recipe_id: id,
+        parts,
+        unresolved,
+        tags,
+        created_tags,
+    })
+}
+
+/// Links tags to a recipe by name, creating the ones that don't exist.
+/// Returns the tags matching `names`, and the names of the tags that were created.
+pub(crate) async fn link_tags<'a, A>(
+    recipe_id: i32,
+    names: &[String],
+    conn: A,
+) -> Result<(Vec<RecipeTag>, Vec<String>), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let linked = list_recipe_tags(&mut *tr, recipe_id).await?;
+
+    let mut tags: Vec<RecipeTag> = vec![];
+    let mut created_tags: Vec<String> = vec![];
+    for name in names {
+        let tag_id = match find_tag(name, &mut *tr).await? {
+            Some(tag_id) => tag_id,
+            None => {
+                created_tags.push(name.clone());
+                create_tag(name, &mut *tr).await?
+            }
+        };
+
+        if tags.iter().any(|tag| tag.id == tag_id) {
+            continue;
+        }
+        if linked.iter().all(|tag| tag.tag_id != tag_id) {
+            link_tag(recipe_id, tag_id, &mut *tr).await?;
+        }
+        tags.push(RecipeTag {
+            id: tag_id,
+            name: name.clone(),
+        });
+    }
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok((tags, created_tags))
+}
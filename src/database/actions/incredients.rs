@@ -1,16 +1,18 @@
 use std::collections::HashMap;
 
 use potion::HtmlError;
-use sqlx::{Pool, Postgres, QueryBuilder};
+use sqlx::{Acquire, Executor, Postgres, QueryBuilder};
 
 use crate::{
     authentication::permissions::ActionType,
-    error::QueryError,
-    pagination::PageContext,
+    error::{QueryError, SdkError, UnitError},
+    filter::{IncredientFilter, QueryFilter},
+    pagination::{CursorPage, CursorRequest, CursorValue, Keyset, PageContext},
     schema::{
-        Incredient, IncredientColor, IncredientFilterObject, ProductOrder, ProductRow, ProductType,
-        RecipeAvailability, UnitType,
+        Incredient, IncredientAlias, IncredientColor, IncredientFilterObject, IncredientNutrition,
+        ProductOrder, ProductRow, ProductType, RecipeAvailability, UnitType,
     },
+    search::push_order,
 };
 
 use crate::{
@@ -22,81 +24,197 @@ use crate::{
     INCREDIENT_COUNT_PER_PAGE,
 };
 
-pub async fn list_incredients(pool: &Pool<Postgres>) -> Result<Vec<Incredient>, potion::Error> {
+use super::{set_search_threshold, update_recipe_cached_data};
+
+pub async fn list_incredients<'e, E>(executor: E) -> Result<Vec<Incredient>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let rows: Vec<Incredient> = sqlx::query_as("SELECT * FROM drink_incredients;")
-        .fetch_all(&*pool)
+        .fetch_all(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(rows)
 }
 
-pub async fn fetch_incredients(
+pub async fn fetch_incredients<'a, A>(
     category: Option<ProductType>,
     order: Option<IncredientOrder>,
     offset: i64,
     search: String,
     author: Option<i32>,
-    pool: &Pool<Postgres>,
-) -> Result<PageContext<IncredientRow>, potion::Error> {
-    let order = order
-        .map(|order| match order {
-            IncredientOrder::Alphabetical => "name",
-            IncredientOrder::AbvAsc => "abv_average",
-            IncredientOrder::AbvDesc => "abv_average DESC",
-            IncredientOrder::PriceSuperalkoAsc => "superalko_price_min",
-            IncredientOrder::PriceSuperalkoDesc => "superalko_price_max DESC",
-            IncredientOrder::PriceAlkoAsc => "alko_price_min",
-            IncredientOrder::PriceAlkoDesc => "alko_price_max DESC",
-        })
-        .unwrap_or("name");
-
-    let rows: Vec<IncredientRow> = match (category, author) {
-        (Some(category), Some(author)) => {
-            sqlx::query_as(&format!("SELECT d.*, COUNT(dd) OVER() FROM drink_incredients d LEFT JOIN drink_incredients dd ON dd.id = d.id WHERE d.type = $1 AND d.author_id = $2 AND d.name ILIKE $3 ORDER BY {order} LIMIT $4 OFFSET $5"))
-                .bind(category)
-                .bind(author)
-                .bind(search)
-                .bind(INCREDIENT_COUNT_PER_PAGE)
-                .bind(offset)
-                .fetch_all(pool).await.map_err(|e| QueryError::from(e).into())?
-        },
-        (None, Some(author)) => {
-            sqlx::query_as(&format!("SELECT d.*, COUNT(dd) OVER() FROM drink_incredients d LEFT JOIN drink_incredients dd ON dd.id = d.id WHERE d.author_id = $1 AND d.name ILIKE $2 ORDER BY {order} LIMIT $3 OFFSET $4"))
-                .bind(author)
-                .bind(search)
-                .bind(INCREDIENT_COUNT_PER_PAGE)
-                .bind(offset)
-                .fetch_all(pool).await.map_err(|e| QueryError::from(e).into())?
-        },
-        (None, None) => {
-            sqlx::query_as(&format!("SELECT d.*, COUNT(dd) OVER() FROM drink_incredients d LEFT JOIN drink_incredients dd ON dd.id = d.id WHERE d.name ILIKE $1 ORDER BY {order} LIMIT $2 OFFSET $3"))
-                .bind(search)
-                .bind(INCREDIENT_COUNT_PER_PAGE)
-                .bind(offset)
-                .fetch_all(pool).await.map_err(|e| QueryError::from(e).into())?
-        },
-        (Some(category), None) => {
-            sqlx::query_as(&format!("SELECT d.*, COUNT(dd) OVER() FROM drink_incredients d LEFT JOIN drink_incredients dd ON dd.id = d.id WHERE d.type = $1 AND d.name ILIKE $2 ORDER BY {order} LIMIT $3 OFFSET $4"))
-                .bind(category)
-                .bind(search)
-                .bind(INCREDIENT_COUNT_PER_PAGE)
-                .bind(offset)
-                .fetch_all(pool).await.map_err(|e| QueryError::from(e).into())?
-        },
+    conn: A,
+) -> Result<PageContext<IncredientRow>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let filter = IncredientFilter {
+        categories: category.into_iter().collect(),
+        author,
+        search: Some(search),
+        ..Default::default()
     };
 
+    filter_incredients(&filter, order, offset, conn).await
+}
+
+pub async fn filter_incredients<'a, A>(
+    filter: &IncredientFilter,
+    order: Option<IncredientOrder>,
+    offset: i64,
+    conn: A,
+) -> Result<PageContext<IncredientRow>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+    set_search_threshold(&mut *tr).await?;
+
+    let order = order.map(|order| match order {
+        IncredientOrder::Alphabetical => "name",
+        IncredientOrder::AbvAsc => "abv_average",
+        IncredientOrder::AbvDesc => "abv_average DESC",
+        IncredientOrder::PriceSuperalkoAsc => "superalko_price_min",
+        IncredientOrder::PriceSuperalkoDesc => "superalko_price_max DESC",
+        IncredientOrder::PriceAlkoAsc => "alko_price_min",
+        IncredientOrder::PriceAlkoDesc => "alko_price_max DESC",
+    });
+
+    let mut builder: QueryBuilder<Postgres> =
+        QueryBuilder::new("SELECT d.*, COUNT(*) OVER() FROM drink_incredients d WHERE TRUE");
+
+    filter.push_conditions(&mut builder);
+
+    push_order(
+        order,
+        &filter.search,
+        filter.search_mode,
+        "d.name",
+        &mut builder,
+    );
+
+    builder
+        .push(" LIMIT ")
+        .push_bind(INCREDIENT_COUNT_PER_PAGE)
+        .push(" OFFSET ")
+        .push_bind(offset);
+
+    let rows: Vec<IncredientRow> = builder
+        .build_query_as()
+        .fetch_all(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
     let total_count = *&rows.get(0).map(|p| p.count).unwrap_or(0);
     let page = PageContext::from_rows(rows, total_count, INCREDIENT_COUNT_PER_PAGE, offset);
     Ok(page)
 }
 
-pub async fn create_incredient(
+fn incredient_keyset(order: Option<IncredientOrder>) -> Keyset<IncredientRow> {
+    let (order, column, descending, value): (_, _, _, fn(&IncredientRow) -> CursorValue) =
+        match order.unwrap_or(IncredientOrder::Alphabetical) {
+            IncredientOrder::Alphabetical => ("alphabetical", "d.name", false, |r| {
+                CursorValue::Text(r.name.clone())
+            }),
+            IncredientOrder::AbvAsc => ("abv_asc", "d.abv_average", false, |r| {
+                CursorValue::Number(r.abv_average)
+            }),
+            IncredientOrder::AbvDesc => ("abv_desc", "d.abv_average", true, |r| {
+                CursorValue::Number(r.abv_average)
+            }),
+            IncredientOrder::PriceSuperalkoAsc => {
+                ("price_superalko_asc", "d.superalko_price_min", false, |r| {
+                    CursorValue::Number(r.superalko_price_min)
+                })
+            }
+            IncredientOrder::PriceSuperalkoDesc => {
+                ("price_superalko_desc", "d.superalko_price_max", true, |r| {
+                    CursorValue::Number(r.superalko_price_max)
+                })
+            }
+            IncredientOrder::PriceAlkoAsc => ("price_alko_asc", "d.alko_price_min", false, |r| {
+                CursorValue::Number(r.alko_price_min)
+            }),
+            IncredientOrder::PriceAlkoDesc => ("price_alko_desc", "d.alko_price_max", true, |r| {
+                CursorValue::Number(r.alko_price_max)
+            }),
+        };
+
+    Keyset {
+        order,
+        column,
+        id_column: "d.id",
+        descending,
+        value,
+        id: |r| r.id,
+    }
+}
+
+/// Keyset paginated version of `filter_incredients`
+pub async fn filter_incredients_by_cursor<'a, A>(
+    filter: &IncredientFilter,
+    order: Option<IncredientOrder>,
+    request: &CursorRequest,
+    conn: A,
+) -> Result<CursorPage<IncredientRow>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+    set_search_threshold(&mut *tr).await?;
+
+    let keyset = incredient_keyset(order);
+    let cursor = keyset.parse_cursor(request)?;
+    let page_size = request.page_size(INCREDIENT_COUNT_PER_PAGE);
+
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("SELECT d.*, ");
+
+    if request.include_total {
+        builder.push("(SELECT COUNT(*) FROM drink_incredients d WHERE TRUE");
+        filter.push_conditions(&mut builder);
+        builder.push(")");
+    } else {
+        builder.push("0::BIGINT");
+    }
+
+    builder.push(" AS count FROM drink_incredients d WHERE TRUE");
+    filter.push_conditions(&mut builder);
+    keyset.push_page(cursor.as_ref(), page_size, &mut builder);
+
+    let rows: Vec<IncredientRow> = builder
+        .build_query_as()
+        .fetch_all(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    let total_count = match request.include_total {
+        true => Some(rows.get(0).map(|p| p.count).unwrap_or(0)),
+        false => None,
+    };
+
+    Ok(CursorPage::from_rows(
+        rows,
+        &keyset,
+        cursor.as_ref(),
+        page_size,
+        total_count,
+    ))
+}
+
+pub async fn create_incredient<'e, E>(
     category: Option<ProductType>,
     name: String,
     user_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<i32, potion::Error> {
+    executor: E,
+) -> Result<i32, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let result: (i32,) = sqlx::query_as(
         "
         INSERT INTO drink_incredients(type, author_id, name, recipe_id)
@@ -107,18 +225,30 @@ pub async fn create_incredient(
     .bind(category)
     .bind(user_id)
     .bind(name)
-    .fetch_one(pool)
+    .fetch_one(executor)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
     Ok(result.0)
 }
 
-pub async fn delete_incredient(id: i32, pool: &Pool<Postgres>) -> Result<(), potion::Error> {
-    let mut tr = pool
-        .begin()
-        .await
-        .map_err(|_| QueryError::new("Could not start transaction".to_owned()).into())?;
+/// Deletes an incredient and removes it from recipes. Fails while recipe revisions still reference it.
+pub async fn delete_incredient<'a, A>(id: i32, conn: A) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let in_history: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM recipe_revision_parts WHERE incredient_id = $1)",
+    )
+    .bind(id)
+    .fetch_one(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+    if in_history {
+        return Err(SdkError::conflict("Incredient is used in recipe revisions").into());
+    }
 
     sqlx::query("DELETE FROM incredient_product_filters WHERE incredient_id = $1")
         .bind(id)
@@ -144,49 +274,111 @@ pub async fn delete_incredient(id: i32, pool: &Pool<Postgres>) -> Result<(), pot
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
-    tr.commit()
-        .await
-        .map_err(|_| QueryError::new("Could not commit transaction".to_owned()).into())?;
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
     Ok(())
 }
 
-pub async fn find_incredient(
-    name: &str,
-    pool: &Pool<Postgres>,
-) -> Result<Option<i32>, potion::Error> {
+pub async fn find_incredient<'e, E>(name: &str, executor: E) -> Result<Option<i32>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let row: Option<(i32,)> =
         sqlx::query_as("SELECT id FROM drink_incredients WHERE LOWER(name) = LOWER($1)")
             .bind(name)
-            .fetch_optional(&*pool)
+            .fetch_optional(executor)
             .await
             .map_err(|e| QueryError::from(e).into())?;
 
     Ok(row.map(|r| r.0))
 }
 
-pub async fn get_incredient_color(
+pub async fn list_incredient_aliases<'e, E>(
+    incredient_id: i32,
+    executor: E,
+) -> Result<Vec<IncredientAlias>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let rows: Vec<IncredientAlias> =
+        sqlx::query_as("SELECT * FROM incredient_aliases WHERE incredient_id = $1 ORDER BY alias")
+            .bind(incredient_id)
+            .fetch_all(executor)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(rows)
+}
+
+/// Alternative name used when resolving imported recipes. Aliases are unique regardless of case.
+pub async fn add_incredient_alias<'e, E>(
+    incredient_id: i32,
+    alias: &str,
+    executor: E,
+) -> Result<i32, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let alias = alias.trim();
+    if alias.is_empty() {
+        return Err(SdkError::validation("Alias can't be empty").into());
+    }
+
+    let id: i32 = sqlx::query_scalar(
+        "INSERT INTO incredient_aliases (incredient_id, alias) VALUES ($1, $2) RETURNING id",
+    )
+    .bind(incredient_id)
+    .bind(alias)
+    .fetch_one(executor)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(id)
+}
+
+pub async fn remove_incredient_alias<'e, E>(id: i32, executor: E) -> Result<(), potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query("DELETE FROM incredient_aliases WHERE id = $1")
+        .bind(id)
+        .execute(executor)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+pub async fn get_incredient_color<'e, E>(
     id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<Option<IncredientColor>, potion::Error> {
+    executor: E,
+) -> Result<Option<IncredientColor>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let row: Option<IncredientColor> =
         sqlx::query_as("SELECT * FROM incredient_colors WHERE incredient_id = $1")
             .bind(id)
-            .fetch_optional(&*pool)
+            .fetch_optional(executor)
             .await
             .map_err(|e| QueryError::from(e).into())?;
 
     Ok(row)
 }
 
-pub async fn set_incredient_color(
+pub async fn set_incredient_color<'a, A>(
     id: i32,
-    pool: &Pool<Postgres>,
+    conn: A,
     r: i32,
     g: i32,
     b: i32,
     a: i32,
-) -> Result<(), potion::Error> {
-    let _query = match get_incredient_color(id, pool).await? {
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let _query = match get_incredient_color(id, &mut *tr).await? {
         Some(_color) => sqlx::query(
             "UPDATE incredient_colors SET r = $1, g = $2, b = $3, a = $4 WHERE incredient_id = $5",
         )
@@ -195,7 +387,7 @@ pub async fn set_incredient_color(
         .bind(b)
         .bind(a)
         .bind(id)
-        .execute(&*pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?,
         None => sqlx::query(
@@ -206,33 +398,41 @@ pub async fn set_incredient_color(
         .bind(g)
         .bind(b)
         .bind(a)
-        .execute(&*pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?,
     };
 
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
     Ok(())
 }
 
-pub async fn get_incredient(
+pub async fn get_incredient<'e, E>(
     id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<Option<Incredient>, potion::Error> {
+    executor: E,
+) -> Result<Option<Incredient>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let row: Option<Incredient> = sqlx::query_as("SELECT * FROM drink_incredients WHERE id = $1")
         .bind(id)
-        .fetch_optional(&*pool)
+        .fetch_optional(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(row)
 }
 
-pub async fn get_incredient_mut(
+pub async fn get_incredient_mut<'e, E>(
     id: i32,
     session: SessionData,
-    pool: &Pool<Postgres>,
-) -> Result<Incredient, potion::Error> {
-    let incredient = get_incredient(id, pool).await?;
+    executor: E,
+) -> Result<Incredient, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let incredient = get_incredient(id, executor).await?;
 
     session.authenticate(ActionType::ManageOwnIncredients)?;
 
@@ -247,34 +447,40 @@ pub async fn get_incredient_mut(
                 }
             }
         },
-        None => Err(HtmlError::InvalidRequest.new("No incredient exists with spcified id")),
+        None => Err(SdkError::not_found("No incredient exists with spcified id").into()),
     }
 }
 
-pub async fn list_product_filter_noname(
-    pool: &Pool<Postgres>,
-) -> Result<Vec<IncredientFilterObjectNoName>, potion::Error> {
+pub async fn list_product_filter_noname<'e, E>(
+    executor: E,
+) -> Result<Vec<IncredientFilterObjectNoName>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let rows: Vec<IncredientFilterObjectNoName> = sqlx::query_as(
         "
         SELECT * FROM incredient_product_filters
     ",
     )
-    .fetch_all(&*pool)
+    .fetch_all(executor)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
     Ok(rows)
 }
 
-pub async fn get_product_filter_noname_all(
-    pool: &Pool<Postgres>,
-) -> Result<Vec<IngredientFilterList>, potion::Error> {
+pub async fn get_product_filter_noname_all<'e, E>(
+    executor: E,
+) -> Result<Vec<IngredientFilterList>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let rows: Vec<IncredientFilterObjectNoName> = sqlx::query_as(
         "
         SELECT * FROM incredient_product_filters
     ",
     )
-    .fetch_all(&*pool)
+    .fetch_all(executor)
     .await
     .map_err(|e| QueryError::from(e).into())?;
     let mut hashmap: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
@@ -295,10 +501,13 @@ pub async fn get_product_filter_noname_all(
     Ok(res)
 }
 
-pub async fn get_incredient_author(
+pub async fn get_incredient_author<'e, E>(
     id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<Option<String>, potion::Error> {
+    executor: E,
+) -> Result<Option<String>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let row: Option<(String,)> = sqlx::query_as(
         "
         SELECT u.username 
@@ -308,17 +517,20 @@ pub async fn get_incredient_author(
     ",
     )
     .bind(id)
-    .fetch_optional(&*pool)
+    .fetch_optional(executor)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
     Ok(row.map(|x| x.0))
 }
 
-pub async fn get_product_filter(
-    pool: &Pool<Postgres>,
+pub async fn get_product_filter<'e, E>(
+    executor: E,
     incredient_id: i32,
-) -> Result<Vec<IncredientFilterObject>, potion::Error> {
+) -> Result<Vec<IncredientFilterObject>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let rows: Vec<IncredientFilterObject> = sqlx::query_as(
         "
         SELECT f.incredient_id AS incredient_id, f.product_id AS product_id, p.name AS product_name
@@ -328,20 +540,23 @@ pub async fn get_product_filter(
     ",
     )
     .bind(incredient_id)
-    .fetch_all(&*pool)
+    .fetch_all(executor)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
     Ok(rows)
 }
 
-pub async fn fetch_product_filter(
-    pool: &Pool<Postgres>,
+pub async fn fetch_product_filter<'e, E>(
+    executor: E,
     incredient_id: i32,
     availability: Option<RecipeAvailability>,
     order: Option<ProductOrder>,
     offset: i64,
-) -> Result<PageContext<ProductRow>, potion::Error> {
+) -> Result<PageContext<ProductRow>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let availability = availability
         .map(|availability| match availability {
             RecipeAvailability::Any => "",
@@ -374,7 +589,7 @@ pub async fn fetch_product_filter(
     "
     ))
     .bind(incredient_id)
-    .fetch_all(&*pool)
+    .fetch_all(executor)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
@@ -384,31 +599,149 @@ pub async fn fetch_product_filter(
     Ok(page)
 }
 
-pub async fn update_incredient_info(
+pub async fn update_incredient_info<'e, E>(
     id: i32,
     category: Option<ProductType>,
     name: String,
     unit: UnitType,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    executor: E,
+) -> Result<(), potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     sqlx::query("UPDATE drink_incredients SET name = $1, type = $2, unit = $3 WHERE id = $4")
         .bind(name)
         .bind(category)
         .bind(unit)
         .bind(id)
-        .execute(&*pool)
+        .execute(executor)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+/// Sets the volume of one `kpl` of the incredient, and recomputes the recipes measured in pieces of it.
+/// The volume can't be removed while recipes measure the incredient in pieces.
+pub async fn set_incredient_piece_volume<'a, A>(
+    id: i32,
+    piece_volume_ml: Option<f64>,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    if piece_volume_ml.is_some_and(|volume| !(volume > 0.)) {
+        return Err(UnitError::InvalidVolume.into());
+    }
+
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    if piece_volume_ml.is_none() {
+        let in_pieces: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM recipe_parts WHERE incredient_id = $1 AND unit = 'kpl')",
+        )
+        .bind(id)
+        .fetch_one(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
+        if in_pieces {
+            return Err(SdkError::validation("Incredient is measured in pieces in recipes").into());
+        }
+    }
+
+    let result = sqlx::query("UPDATE drink_incredients SET piece_volume_ml = $1 WHERE id = $2")
+        .bind(piece_volume_ml)
+        .bind(id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    if result.rows_affected() == 0 {
+        return Err(SdkError::not_found("Incredient doesn't exist").into());
+    }
+
+    let recipe_ids: Vec<i32> = sqlx::query_scalar(
+        "
+        UPDATE recipe_parts SET amount_standard = amount * $1
+        WHERE incredient_id = $2 AND unit = 'kpl'
+        RETURNING recipe_id
+    ",
+    )
+    .bind(piece_volume_ml)
+    .bind(id)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    for recipe_id in recipe_ids {
+        update_recipe_cached_data(recipe_id, &mut *tr).await?;
+    }
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn update_incredient_price(
+/// Sets the nutrition of the incredient, and recomputes the recipes using it
+pub async fn set_incredient_nutrition<'a, A>(
+    id: i32,
+    nutrition: &IncredientNutrition,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    if [nutrition.kcal, nutrition.sugar, nutrition.carbohydrates]
+        .iter()
+        .flatten()
+        .any(|value| !(*value >= 0.))
+    {
+        return Err(SdkError::validation("Nutrition values can't be negative").into());
+    }
+
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let result = sqlx::query(
+        "UPDATE drink_incredients SET kcal = $1, sugar = $2, carbohydrates = $3 WHERE id = $4",
+    )
+    .bind(nutrition.kcal)
+    .bind(nutrition.sugar)
+    .bind(nutrition.carbohydrates)
+    .bind(id)
+    .execute(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    if result.rows_affected() == 0 {
+        return Err(SdkError::not_found("Incredient doesn't exist").into());
+    }
+
+    let recipe_ids: Vec<i32> =
+        sqlx::query_scalar("SELECT DISTINCT recipe_id FROM recipe_parts WHERE incredient_id = $1")
+            .bind(id)
+            .fetch_all(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+
+    for recipe_id in recipe_ids {
+        update_recipe_cached_data(recipe_id, &mut *tr).await?;
+    }
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+pub async fn update_incredient_price<'e, E>(
     id: i32,
     min: f64,
     max: f64,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    executor: E,
+) -> Result<(), potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let avg = (min + max) / 2.0;
 
     sqlx::query(
@@ -429,69 +762,91 @@ pub async fn update_incredient_price(
     .bind(avg)
     .bind(max)
     .bind(id)
-    .execute(&*pool)
+    .execute(executor)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn update_incredient_static_filter(
+pub async fn update_incredient_static_filter<'e, E>(
     id: i32,
     category: i32,
-    pool: &Pool<Postgres>,
+    executor: E,
     use_static_filter: bool,
-) -> Result<(), potion::Error> {
+) -> Result<(), potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     sqlx::query("UPDATE drink_incredients SET category = $1, use_static_filter = $2, use_static_filter_c = $2, static_filter_c = $1 WHERE id = $3")
         .bind(category)
         .bind(use_static_filter)
         .bind(id)
-        .execute(&*pool)
+        .execute(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn set_product_s_filter(
+pub async fn set_product_s_filter<'a, A>(
     id: i32,
     subcategory: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     sqlx::query("UPDATE drink_incredients SET static_filter = $1, use_static_filter = true, use_static_filter_c = false, static_filter_c = NULL WHERE id = $2")
         .bind(subcategory)
         .bind(id)
-        .execute(&*pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
-    update_incredient_cached_data(id, pool).await?;
+    update_incredient_cached_data(id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn set_product_c_filter(
+pub async fn set_product_c_filter<'a, A>(
     id: i32,
     category: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     sqlx::query("UPDATE drink_incredients SET static_filter_c = $1, use_static_filter_c = true WHERE id = $2")
         .bind(category)
         .bind(id)
-        .execute(&*pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
-    update_incredient_cached_data(id, pool).await?;
+    update_incredient_cached_data(id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn insert_product_filter(
+pub async fn insert_product_filter<'a, A>(
     id: i32,
     id_map: &[i32],
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     if id_map.len() > 0 {
         let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
             "INSERT INTO incredient_product_filters (incredient_id, product_id) ",
@@ -503,40 +858,54 @@ pub async fn insert_product_filter(
 
         query_builder
             .build()
-            .execute(&*pool)
+            .execute(&mut *tr)
             .await
             .map_err(|e| QueryError::from(e).into())?;
 
-        update_incredient_cached_data(id, pool).await?;
+        update_incredient_cached_data(id, &mut *tr).await?;
     }
 
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
     Ok(())
 }
 
-pub async fn remove_product_filter(
+pub async fn remove_product_filter<'a, A>(
     id: i32,
     product_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     sqlx::query(
         "DELETE FROM incredient_product_filters WHERE product_id = $1 AND incredient_id = $2",
     )
     .bind(product_id)
     .bind(id)
-    .execute(&*pool)
+    .execute(&mut *tr)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
-    update_incredient_cached_data(id, pool).await?;
+    update_incredient_cached_data(id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn calculate_incredient_cached_data(
+pub async fn calculate_incredient_cached_data<'a, A>(
     incredient_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<IncredientCacheData, potion::Error> {
-    let incredient = get_incredient(incredient_id, pool).await?;
+    conn: A,
+) -> Result<IncredientCacheData, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let incredient = get_incredient(incredient_id, &mut *tr).await?;
     if incredient.is_none() {
         return Err(HtmlError::InvalidRequest.default().into());
     }
@@ -572,7 +941,7 @@ pub async fn calculate_incredient_cached_data(
             ",
             )
             .bind(subcategory_id)
-            .fetch_optional(&*pool)
+            .fetch_optional(&mut *tr)
             .await
             .map_err(|e| QueryError::from(e).into())?,
             (None, Some(category_id)) | (Some(_), Some(category_id)) => sqlx::query_as(
@@ -599,7 +968,7 @@ pub async fn calculate_incredient_cached_data(
             ",
             )
             .bind(category_id)
-            .fetch_optional(&*pool)
+            .fetch_optional(&mut *tr)
             .await
             .map_err(|e| QueryError::from(e).into())?,
             (None, None) => sqlx::query_as(
@@ -628,22 +997,29 @@ pub async fn calculate_incredient_cached_data(
             ",
             )
             .bind(incredient_id)
-            .fetch_optional(&*pool)
+            .fetch_optional(&mut *tr)
             .await
             .map_err(|e| QueryError::from(e).into())?,
         };
 
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
     match data {
         Some(data) => Ok(data),
         None => Ok(IncredientCacheData::default()),
     }
 }
 
-pub async fn update_incredient_cached_data(
+pub async fn update_incredient_cached_data<'a, A>(
     incredient_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
-    let data = calculate_incredient_cached_data(incredient_id, pool).await?;
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let data = calculate_incredient_cached_data(incredient_id, &mut *tr).await?;
 
     sqlx::query(
         "
@@ -678,9 +1054,11 @@ pub async fn update_incredient_cached_data(
     .bind(data.alko_product_count)
     .bind(data.superalko_product_count)
     .bind(incredient_id)
-    .execute(&*pool)
+    .execute(&mut *tr)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
     Ok(())
 }
@@ -0,0 +1,460 @@
+use std::collections::{BTreeSet, HashMap};
+
+use sqlx::{Acquire, Postgres, QueryBuilder};
+
+use crate::{
+    error::{QueryError, SdkError},
+    filter::{QueryFilter, RecipeFilter},
+    interchange::{
+        InterchangePart, InterchangeRecipe, InterchangeResult, InterchangeStatus, InterchangeStep,
+        RecipeDocument, INTERCHANGE_VERSION,
+    },
+    schema::{
+        Glassware, IceType, IncredientColor, MatchKind, MethodAction, NewRecipePart, RecipeMethod,
+        RecipeStep, RecipeType, UnitType,
+    },
+};
+
+use super::{
+    check_piece_volumes, find_recipe, get_incredient_color, get_recipe, insert_recipe_with_parts,
+    link_tags, list_recipe_parts, list_recipe_tags, match_incredient, record_recipe_revision,
+    set_incredient_color, set_recipe_method, set_search_threshold,
+};
+
+#[derive(sqlx::FromRow)]
+struct ExportRecipeRow {
+    id: i32,
+    r#type: RecipeType,
+    name: String,
+    info: String,
+    recipe_id: i32,
+    glassware: Option<Glassware>,
+    ice: Option<IceType>,
+    garnish: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ExportPartRow {
+    recipe_id: i32,
+    incredient_id: i32,
+    name: String,
+    amount: f64,
+    unit: UnitType,
+    r: Option<i32>,
+    g: Option<i32>,
+    b: Option<i32>,
+    a: Option<i32>,
+}
+
+const RECIPE_COLUMNS_QUERY: &str =
+    "SELECT r.id, r.type, r.name, r.info, r.recipe_id, r.glassware, r.ice, r.garnish FROM drink_recipes r";
+
+pub async fn export_recipe<'a, A>(id: i32, conn: A) -> Result<RecipeDocument, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut builder: QueryBuilder<Postgres> =
+        QueryBuilder::new(format!("{RECIPE_COLUMNS_QUERY} WHERE r.id = "));
+    builder.push_bind(id);
+
+    let document = export(builder, conn).await?;
+    match document.recipes.is_empty() {
+        true => Err(SdkError::not_found("Recipe doesn't exists").into()),
+        false => Ok(document),
+    }
+}
+
+/// Every recipe matching the filter, ordered by name
+pub async fn export_recipes<'a, A>(
+    filter: &RecipeFilter,
+    conn: A,
+) -> Result<RecipeDocument, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut builder: QueryBuilder<Postgres> =
+        QueryBuilder::new(format!("{RECIPE_COLUMNS_QUERY} WHERE TRUE"));
+    filter.push_conditions(&mut builder);
+    builder.push(" ORDER BY r.name");
+
+    export(builder, conn).await
+}
+
+async fn export<'a, A>(
+    mut builder: QueryBuilder<'_, Postgres>,
+    conn: A,
+) -> Result<RecipeDocument, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+    set_search_threshold(&mut *tr).await?;
+
+    let recipes: Vec<ExportRecipeRow> = builder
+        .build_query_as()
+        .fetch_all(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    let parts_ids: Vec<i32> = recipes.iter().map(|recipe| recipe.recipe_id).collect();
+    let parts: Vec<ExportPartRow> = sqlx::query_as(
+        "
+        SELECT rp.recipe_id, d.id AS incredient_id, d.name, rp.amount, rp.unit, c.r, c.g, c.b, c.a
+        FROM recipe_parts rp
+        INNER JOIN drink_incredients d ON d.id = rp.incredient_id
+        LEFT JOIN incredient_colors c ON c.incredient_id = d.id
+        WHERE rp.recipe_id = ANY($1)
+        ORDER BY rp.amount_standard DESC, d.name
+    ",
+    )
+    .bind(&parts_ids)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let ids: Vec<i32> = recipes.iter().map(|recipe| recipe.id).collect();
+    let tags: Vec<(i32, String)> = sqlx::query_as(
+        "SELECT recipe_id, tag_name FROM recipe_tags_map WHERE recipe_id = ANY($1) ORDER BY tag_name",
+    )
+    .bind(&ids)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let steps: Vec<(i32, MethodAction, String)> = sqlx::query_as(
+        "SELECT recipe_id, action, instruction FROM recipe_steps WHERE recipe_id = ANY($1) ORDER BY position",
+    )
+    .bind(&ids)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    let mut parts_by_recipe: HashMap<i32, Vec<InterchangePart>> = HashMap::new();
+    parts.into_iter().for_each(|part| {
+        let color = match (part.r, part.g, part.b, part.a) {
+            (Some(r), Some(g), Some(b), Some(a)) => IncredientColor {
+                incredient_id: part.incredient_id,
+                r,
+                g,
+                b,
+                a,
+            }
+            .as_hex()
+            .ok(),
+            _ => None,
+        };
+
+        parts_by_recipe
+            .entry(part.recipe_id)
+            .or_default()
+            .push(InterchangePart {
+                incredient: part.name,
+                amount: part.amount,
+                unit: part.unit,
+                color,
+            })
+    });
+
+    let mut tags_by_recipe: HashMap<i32, Vec<String>> = HashMap::new();
+    tags.into_iter()
+        .for_each(|(recipe_id, tag)| tags_by_recipe.entry(recipe_id).or_default().push(tag));
+
+    let mut steps_by_recipe: HashMap<i32, Vec<InterchangeStep>> = HashMap::new();
+    steps
+        .into_iter()
+        .for_each(|(recipe_id, action, instruction)| {
+            steps_by_recipe
+                .entry(recipe_id)
+                .or_default()
+                .push(InterchangeStep {
+                    action,
+                    instruction,
+                })
+        });
+
+    let recipes = recipes
+        .into_iter()
+        .map(|recipe| InterchangeRecipe {
+            name: recipe.name,
+            recipe_type: recipe.r#type,
+            info: recipe.info,
+            parts: parts_by_recipe
+                .remove(&recipe.recipe_id)
+                .unwrap_or_default(),
+            tags: tags_by_recipe.remove(&recipe.id).unwrap_or_default(),
+            steps: steps_by_recipe.remove(&recipe.id).unwrap_or_default(),
+            glassware: recipe.glassware,
+            ice: recipe.ice,
+            garnish: recipe.garnish,
+        })
+        .collect();
+
+    Ok(RecipeDocument::new(recipes))
+}
+
+/// Imports every recipe of a document as `user_id`, in one transaction.
+/// Recipes are matched to existing ones by name: identical recipes are left as they are,
+/// so importing the same document again changes nothing, and different ones are reported as conflicts.
+/// Incredients are matched by exact name or alias, recipes with unknown incredients are skipped.
+/// A recipe that fails to import is reported as invalid without affecting the others.
+pub async fn import_recipes<'a, A>(
+    document: &RecipeDocument,
+    user_id: i32,
+    conn: A,
+) -> Result<Vec<InterchangeResult>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    if document.version > INTERCHANGE_VERSION {
+        return Err(SdkError::validation("Unsupported recipe document version").into());
+    }
+
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let mut results = Vec::with_capacity(document.recipes.len());
+    for recipe in document.recipes.iter() {
+        results.push(import_one(recipe, user_id, &mut tr).await?);
+    }
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(results)
+}
+
+pub async fn import_recipe<'a, A>(
+    recipe: &InterchangeRecipe,
+    user_id: i32,
+    conn: A,
+) -> Result<InterchangeResult, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let result = import_one(recipe, user_id, &mut tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(result)
+}
+
+async fn import_one(
+    recipe: &InterchangeRecipe,
+    user_id: i32,
+    tr: &mut sqlx::Transaction<'_, Postgres>,
+) -> Result<InterchangeResult, potion::Error> {
+    let name = recipe.name.trim();
+    let result = |status: InterchangeStatus, recipe_id: Option<i32>, detail: Option<String>| {
+        InterchangeResult {
+            name: name.to_owned(),
+            status,
+            recipe_id,
+            detail,
+        }
+    };
+
+    if name.is_empty() {
+        return Ok(result(
+            InterchangeStatus::Invalid,
+            None,
+            Some(String::from("Recipe name is empty")),
+        ));
+    }
+
+    let mut parts: Vec<NewRecipePart> = vec![];
+    let mut missing: Vec<String> = vec![];
+    for part in recipe.parts.iter() {
+        if part.amount <= 0. {
+            return Ok(result(
+                InterchangeStatus::Invalid,
+                None,
+                Some(format!("Amount of {} must be positive", part.incredient)),
+            ));
+        }
+
+        let matched = match_incredient(&part.incredient, &mut **tr).await?;
+        match (matched.incredient, matched.kind) {
+            (Some(incredient), Some(MatchKind::Exact | MatchKind::Alias)) => {
+                if parts.iter().any(|p| p.incredient_id == incredient.id) {
+                    return Ok(result(
+                        InterchangeStatus::Invalid,
+                        None,
+                        Some(format!(
+                            "{} is in the recipe more than once",
+                            incredient.name
+                        )),
+                    ));
+                }
+                parts.push(NewRecipePart {
+                    incredient_id: incredient.id,
+                    amount: part.amount,
+                    unit: part.unit.clone(),
+                });
+            }
+            _ => missing.push(part.incredient.clone()),
+        }
+    }
+
+    if !missing.is_empty() {
+        return Ok(result(
+            InterchangeStatus::Invalid,
+            None,
+            Some(format!("Unknown incredients: {}", missing.join(", "))),
+        ));
+    }
+
+    if let Some(id) = find_recipe(name, user_id, &mut **tr).await? {
+        let status = match is_identical(id, recipe, &parts, tr).await? {
+            true => InterchangeStatus::Unchanged,
+            false => InterchangeStatus::Conflict,
+        };
+        let detail = match status {
+            InterchangeStatus::Conflict => {
+                Some(String::from("A different recipe with this name exists"))
+            }
+            _ => None,
+        };
+        return Ok(result(status, Some(id), detail));
+    }
+
+    // A savepoint, so that a failing recipe doesn't abort the transaction of the whole document
+    let mut savepoint = tr.begin().await.map_err(|e| QueryError::from(e).into())?;
+    match insert_one(recipe, name, &parts, user_id, &mut savepoint).await {
+        Ok(id) => {
+            savepoint
+                .commit()
+                .await
+                .map_err(|e| QueryError::from(e).into())?;
+            Ok(result(InterchangeStatus::Created, Some(id), None))
+        }
+        Err(e) => {
+            savepoint
+                .rollback()
+                .await
+                .map_err(|e| QueryError::from(e).into())?;
+            Ok(result(InterchangeStatus::Invalid, None, e.info))
+        }
+    }
+}
+
+async fn insert_one(
+    recipe: &InterchangeRecipe,
+    name: &str,
+    parts: &[NewRecipePart],
+    user_id: i32,
+    tr: &mut sqlx::Transaction<'_, Postgres>,
+) -> Result<i32, potion::Error> {
+    check_piece_volumes(parts, None, &mut **tr).await?;
+    let id = insert_recipe_with_parts(
+        recipe.recipe_type.clone(),
+        user_id,
+        name.to_owned(),
+        recipe.info.clone(),
+        parts,
+        &[],
+        &mut **tr,
+    )
+    .await?;
+    link_tags(id, &recipe.tags, &mut **tr).await?;
+    set_recipe_method(id, &method(recipe), &mut **tr).await?;
+    record_recipe_revision(id, user_id, &mut **tr).await?;
+
+    for (part, new_part) in recipe.parts.iter().zip(parts.iter()) {
+        let Some(hex) = &part.color else {
+            continue;
+        };
+        if get_incredient_color(new_part.incredient_id, &mut **tr)
+            .await?
+            .is_some()
+        {
+            continue;
+        }
+        if let Ok(color) = IncredientColor::from_hex(hex.clone(), new_part.incredient_id) {
+            set_incredient_color(
+                new_part.incredient_id,
+                &mut **tr,
+                color.r,
+                color.g,
+                color.b,
+                color.a,
+            )
+            .await?;
+        }
+    }
+
+    Ok(id)
+}
+
+fn method(recipe: &InterchangeRecipe) -> RecipeMethod {
+    RecipeMethod {
+        steps: recipe
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| RecipeStep {
+                position: i as i32 + 1,
+                action: step.action,
+                instruction: step.instruction.trim().to_owned(),
+            })
+            .collect(),
+        glassware: recipe.glassware,
+        ice: recipe.ice,
+        garnish: recipe
+            .garnish
+            .as_ref()
+            .map(|garnish| garnish.trim().to_owned())
+            .filter(|garnish| !garnish.is_empty()),
+    }
+}
+
+/// Same type, info, method, parts and tags. Incredients are compared by id, tags regardless of order.
+async fn is_identical(
+    id: i32,
+    recipe: &InterchangeRecipe,
+    parts: &[NewRecipePart],
+    tr: &mut sqlx::Transaction<'_, Postgres>,
+) -> Result<bool, potion::Error> {
+    let existing = get_recipe(id, &mut **tr)
+        .await?
+        .ok_or_else(|| SdkError::not_found("Recipe doesn't exists").into())?;
+
+    if existing.r#type != recipe.recipe_type
+        || existing.info != recipe.info
+        || RecipeMethod::from(existing.clone()) != method(recipe)
+    {
+        return Ok(false);
+    }
+
+    let mut existing_parts: Vec<(i32, f64, UnitType)> =
+        list_recipe_parts(&mut **tr, existing.recipe_id)
+            .await?
+            .into_iter()
+            .map(|part| (part.incredient_id, part.amount, part.unit))
+            .collect();
+    let mut new_parts: Vec<(i32, f64, UnitType)> = parts
+        .iter()
+        .map(|part| (part.incredient_id, part.amount, part.unit.clone()))
+        .collect();
+    existing_parts.sort_by_key(|part| part.0);
+    new_parts.sort_by_key(|part| part.0);
+
+    let same_parts = existing_parts.len() == new_parts.len()
+        && existing_parts
+            .iter()
+            .zip(new_parts.iter())
+            .all(|(a, b)| a.0 == b.0 && a.2 == b.2 && (a.1 - b.1).abs() < 1e-9);
+    if !same_parts {
+        return Ok(false);
+    }
+
+    let existing_tags: BTreeSet<String> = list_recipe_tags(&mut **tr, id)
+        .await?
+        .into_iter()
+        .map(|tag| tag.tag_name)
+        .collect();
+    let new_tags: BTreeSet<String> = recipe.tags.iter().cloned().collect();
+
+    Ok(existing_tags == new_tags)
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+
+use sqlx::{Acquire, FromRow, Postgres};
+
+use crate::{
+    error::QueryError,
+    pagination::PageContext,
+    schema::{IncredientName, MakeableRecipe, RecipeRow, RecipeRowPartial, UnitType, Uuid},
+    RECIPE_COUNT_PER_PAGE,
+};
+
+use super::list_cabinet_mixers_rsm;
+
+/// Products that can be used as each incredient, following the same rules as
+/// `calculate_incredient_cached_data`: a static category filter takes precedence
+/// over a static subcategory filter, which takes precedence over the product filter list
+pub const INCREDIENT_PRODUCTS_CTE: &str = "
+    incredient_products AS (
+        SELECT d.id AS incredient_id, p.id AS product_id
+        FROM drink_incredients d
+        INNER JOIN products p ON p.category_id = d.static_filter_c
+        WHERE d.use_static_filter AND d.static_filter_c IS NOT NULL
+        UNION
+        SELECT d.id AS incredient_id, p.id AS product_id
+        FROM drink_incredients d
+        INNER JOIN products p ON p.subcategory_id = d.static_filter
+        WHERE d.use_static_filter AND d.static_filter_c IS NULL AND d.static_filter IS NOT NULL
+        UNION
+        SELECT f.incredient_id, f.product_id
+        FROM incredient_product_filters f
+        INNER JOIN drink_incredients d ON d.id = f.incredient_id
+        WHERE NOT d.use_static_filter OR (d.static_filter IS NULL AND d.static_filter_c IS NULL)
+    )
+";
+
+#[derive(FromRow)]
+struct MakeableRecipeRow {
+    #[sqlx(flatten)]
+    recipe: RecipeRowPartial,
+    parts_id: i32,
+    missing_ids: Vec<i32>,
+    missing_names: Vec<String>,
+}
+
+/// Recipes that can be made with the usable products and mixers of a cabinet.
+/// Recipes missing at most `max_missing` incredients are included as well, ordered after the makeable ones.
+pub async fn fetch_makeable_recipes<'a, A>(
+    cabinet_id: i32,
+    max_missing: i64,
+    offset: i64,
+    conn: A,
+) -> Result<PageContext<MakeableRecipe>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let rows: Vec<MakeableRecipeRow> = sqlx::query_as(&format!(
+        "
+        WITH {INCREDIENT_PRODUCTS_CTE},
+        available AS (
+            SELECT ip.incredient_id
+            FROM cabinet_products cp
+            INNER JOIN incredient_products ip ON ip.product_id = cp.product_id
+            WHERE cp.cabinet_id = $1 AND cp.usable
+            UNION
+            SELECT m.incredient_id
+            FROM cabinet_mixers m
+            WHERE m.cabinet_id = $1 AND m.usable
+        ),
+        coverage AS (
+            SELECT
+                rp.recipe_id,
+                COUNT(*) FILTER (WHERE a.incredient_id IS NULL) AS missing_count,
+                COALESCE(ARRAY_AGG(d.id ORDER BY d.name) FILTER (WHERE a.incredient_id IS NULL), '{{}}') AS missing_ids,
+                COALESCE(ARRAY_AGG(d.name ORDER BY d.name) FILTER (WHERE a.incredient_id IS NULL), '{{}}') AS missing_names
+            FROM recipe_parts rp
+            INNER JOIN drink_incredients d ON d.id = rp.incredient_id
+            LEFT JOIN available a ON a.incredient_id = rp.incredient_id
+            GROUP BY rp.recipe_id
+        )
+        SELECT r.*, r.recipe_id AS parts_id, c.missing_ids, c.missing_names, COUNT(*) OVER()
+        FROM drink_recipes r
+        INNER JOIN coverage c ON c.recipe_id = r.recipe_id
+        WHERE r.type != 'generated' AND c.missing_count <= $2
+        ORDER BY c.missing_count, r.name
+        LIMIT $3 OFFSET $4
+    "
+    ))
+    .bind(cabinet_id)
+    .bind(max_missing.max(0))
+    .bind(RECIPE_COUNT_PER_PAGE)
+    .bind(offset)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let stock = cabinet_stock(cabinet_id, &mut tr).await?;
+
+    let makeable_ids: Vec<i32> = rows
+        .iter()
+        .filter(|row| row.missing_ids.is_empty())
+        .map(|row| row.parts_id)
+        .collect();
+
+    let parts: Vec<(i32, i32, f64, UnitType)> = sqlx::query_as(
+        "SELECT recipe_id, incredient_id, amount, unit FROM recipe_parts WHERE recipe_id = ANY($1)",
+    )
+    .bind(&makeable_ids)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    let mut parts_by_recipe: HashMap<i32, Vec<(Uuid, f64, UnitType)>> = HashMap::new();
+    parts
+        .into_iter()
+        .for_each(|(recipe_id, incredient_id, amount, unit)| {
+            parts_by_recipe
+                .entry(recipe_id)
+                .or_default()
+                .push((incredient_id, amount, unit))
+        });
+
+    let total_count = rows.get(0).map(|row| row.recipe.count).unwrap_or(0);
+    let rows: Vec<MakeableRecipe> = rows
+        .into_iter()
+        .map(|row| {
+            let servings = match row.missing_ids.is_empty() {
+                true => parts_by_recipe
+                    .get(&row.parts_id)
+                    .and_then(|parts| servings_from_stock(parts, &stock)),
+                false => None,
+            };
+
+            MakeableRecipe {
+                missing: row
+                    .missing_ids
+                    .into_iter()
+                    .zip(row.missing_names)
+                    .map(|(id, name)| IncredientName { id, name })
+                    .collect(),
+                recipe: RecipeRow::from(row.recipe),
+                servings,
+            }
+        })
+        .collect();
+
+    Ok(PageContext::from_rows(
+        rows,
+        total_count,
+        RECIPE_COUNT_PER_PAGE,
+        offset,
+    ))
+}
+
+/// Tracked amount of each incredient in a cabinet, as `(amount, unit)` pairs.
+/// Incredients with any untracked item are `None`, as their amount is unknown.
+pub(crate) async fn cabinet_stock(
+    cabinet_id: i32,
+    tr: &mut sqlx::Transaction<'_, Postgres>,
+) -> Result<HashMap<Uuid, Option<Vec<(f64, UnitType)>>>, potion::Error> {
+    let products: Vec<(i32, Option<i32>)> = sqlx::query_as(&format!(
+        "
+        WITH {INCREDIENT_PRODUCTS_CTE}
+        SELECT ip.incredient_id, cp.amount_ml
+        FROM cabinet_products cp
+        INNER JOIN incredient_products ip ON ip.product_id = cp.product_id
+        WHERE cp.cabinet_id = $1 AND cp.usable
+    "
+    ))
+    .bind(cabinet_id)
+    .fetch_all(&mut **tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let mixers = list_cabinet_mixers_rsm(cabinet_id, &mut **tr).await?;
+
+    let mut stock: HashMap<Uuid, Option<Vec<(f64, UnitType)>>> = HashMap::new();
+    let mut add = |incredient_id: Uuid, amount: Option<(f64, UnitType)>| {
+        let entry = stock.entry(incredient_id).or_insert(Some(vec![]));
+        match (entry.as_mut(), amount) {
+            (Some(list), Some(amount)) => list.push(amount),
+            _ => *entry = None,
+        }
+    };
+
+    products.into_iter().for_each(|(incredient_id, amount_ml)| {
+        add(
+            incredient_id,
+            amount_ml.map(|amount| (amount.into(), UnitType::Ml)),
+        )
+    });
+
+    mixers
+        .into_iter()
+        .filter(|mixer| mixer.usable)
+        .for_each(|mixer| {
+            add(
+                mixer.incredient_id,
+                mixer.amount.map(|amount| (amount, mixer.unit)),
+            )
+        });
+
+    Ok(stock)
+}
+
+/// Smallest number of servings any tracked part allows.
+/// `None` if a tracked part is stocked only in units that can't be converted to the unit of the part.
+fn servings_from_stock(
+    parts: &[(Uuid, f64, UnitType)],
+    stock: &HashMap<Uuid, Option<Vec<(f64, UnitType)>>>,
+) -> Option<i32> {
+    let mut servings: Option<i32> = None;
+
+    for (incredient_id, amount, unit) in parts {
+        let Some(Some(available)) = stock.get(incredient_id) else {
+            continue;
+        };
+        let converted: Vec<f64> = available
+            .iter()
+            .filter_map(|(value, stock_unit)| stock_unit.convert(*value, unit.clone()).ok())
+            .map(|(_, value)| value)
+            .collect();
+        if converted.is_empty() {
+            return None;
+        }
+
+        if *amount > 0. {
+            let part_servings = (converted.iter().sum::<f64>() / amount).floor() as i32;
+            servings = Some(servings.map_or(part_servings, |s| s.min(part_servings)));
+        }
+    }
+
+    servings
+}
@@ -0,0 +1,154 @@
+use sqlx::{Acquire, Postgres, QueryBuilder};
+
+use crate::{
+    error::{QueryError, SdkError},
+    filter::{QueryFilter, Range, RecipeFilter},
+    schema::{
+        PartyMenuItem, PartyPlan, PartyRequest, RecipeAvailability, RecipeRow, RecipeRowPartial,
+        RecipeServings, Retailer,
+    },
+    PARTY_MAX_MENU_SIZE, PARTY_MENU_SIZE,
+};
+
+use super::generate_shopping_list;
+
+/// Picks a menu of recipes, and the number of batches of each, that gives every guest
+/// `servings_per_guest` standard servings within the budget.
+/// The menu is built from the recipes with the best alcohol per euro in the retailer.
+/// The widest menu that fits the budget is chosen, with the servings split evenly between the recipes.
+pub async fn plan_party<'a, A>(request: &PartyRequest, conn: A) -> Result<PartyPlan, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    if request.guests <= 0 || request.servings_per_guest <= 0. {
+        return Err(SdkError::validation("Guests and servings per guest must be positive").into());
+    }
+    if request.budget < 0. {
+        return Err(SdkError::validation("Budget can't be negative").into());
+    }
+
+    let (availability, price_per_serving, aer) = match request.retailer {
+        Retailer::Alko => (
+            RecipeAvailability::Alko,
+            "r.alko_price_per_serving",
+            "r.alko_aer",
+        ),
+        Retailer::Superalko => (
+            RecipeAvailability::Superalko,
+            "r.superalko_price_per_serving",
+            "r.superalko_aer",
+        ),
+        // TODO see #39
+        Retailer::VikingLine => {
+            return Err(SdkError::validation("Recipe prices aren't cached for Viking Line").into())
+        }
+    };
+
+    let menu_size = request
+        .menu_size
+        .unwrap_or(PARTY_MENU_SIZE)
+        .clamp(1, PARTY_MAX_MENU_SIZE);
+
+    let filter = RecipeFilter {
+        categories: request.categories.clone(),
+        abv: Range::new(None, request.max_abv),
+        availability: Some(availability),
+        tags: request.tags.clone(),
+        ..Default::default()
+    };
+
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let mut builder: QueryBuilder<Postgres> =
+        QueryBuilder::new("SELECT r.*, COUNT(*) OVER() FROM drink_recipes r WHERE TRUE");
+    filter.push_conditions(&mut builder);
+    builder
+        .push(format!(
+            " AND r.standard_servings > 0 AND {price_per_serving} > 0 ORDER BY {aer} DESC, r.name LIMIT "
+        ))
+        .push_bind(menu_size as i64);
+
+    let candidates: Vec<RecipeRowPartial> = builder
+        .build_query_as()
+        .fetch_all(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+    let candidates: Vec<RecipeRow> = candidates.into_iter().map(RecipeRow::from).collect();
+
+    if candidates.is_empty() {
+        return Err(SdkError::not_found("No recipes match the constraints").into());
+    }
+
+    let price = |recipe: &RecipeRow| match request.retailer {
+        Retailer::Superalko => recipe.superalko_price_per_serving,
+        _ => recipe.alko_price_per_serving,
+    };
+
+    let target_servings = f64::from(request.guests) * request.servings_per_guest;
+
+    // Narrower menus leave out the recipes with the worst alcohol per euro
+    let mut cheapest = f64::INFINITY;
+    let mut menu = None;
+    for size in (1..=candidates.len()).rev() {
+        let share = target_servings / size as f64;
+        let items: Vec<PartyMenuItem> = candidates[..size]
+            .iter()
+            .map(|recipe| {
+                let batches = (share / recipe.standard_servings).ceil() as i32;
+                let standard_servings = f64::from(batches) * recipe.standard_servings;
+                PartyMenuItem {
+                    estimated_price: standard_servings * price(recipe),
+                    recipe: recipe.clone(),
+                    batches,
+                    standard_servings,
+                }
+            })
+            .collect();
+
+        let total = items
+            .iter()
+            .fold(0., |total, item| total + item.estimated_price);
+        if total <= request.budget {
+            menu = Some(items);
+            break;
+        }
+        cheapest = cheapest.min(total);
+    }
+
+    let Some(menu) = menu else {
+        return Err(SdkError::validation(&format!(
+            "Budget of {:.2} isn't enough, the cheapest menu costs {:.2}",
+            request.budget, cheapest
+        ))
+        .into());
+    };
+
+    let servings: Vec<RecipeServings> = menu
+        .iter()
+        .map(|item| RecipeServings {
+            recipe_id: item.recipe.id,
+            servings: f64::from(item.batches),
+        })
+        .collect();
+    let shopping_list = generate_shopping_list(
+        &servings,
+        request.cabinet_id,
+        &[request.retailer.clone()],
+        &mut *tr,
+    )
+    .await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(PartyPlan {
+        target_servings,
+        standard_servings: menu
+            .iter()
+            .fold(0., |total, item| total + item.standard_servings),
+        estimated_price: menu
+            .iter()
+            .fold(0., |total, item| total + item.estimated_price),
+        menu,
+        shopping_list,
+    })
+}
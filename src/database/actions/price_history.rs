@@ -1,5 +1,5 @@
 use potion::HtmlError;
-use sqlx::{query, query_as, Pool, Postgres};
+use sqlx::{query, query_as, Acquire, Executor, Postgres};
 
 use crate::{
     actions::{fetch_all_products, get_product},
@@ -7,58 +7,71 @@ use crate::{
     schema::{Product, ProductPriceHistoryEntry},
 };
 
-pub async fn get_price_history(
+pub async fn get_price_history<'e, E>(
     id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<Vec<ProductPriceHistoryEntry>, potion::Error> {
+    executor: E,
+) -> Result<Vec<ProductPriceHistoryEntry>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let history: Vec<ProductPriceHistoryEntry> =
         query_as("SELECT * FROM product_price_history WHERE product_id = $1")
             .bind(id)
-            .fetch_all(pool)
+            .fetch_all(executor)
             .await
             .map_err(|e| QueryError::from(e).into())?;
 
     Ok(history)
 }
 
-pub async fn upsert_price_history(
-    product: &Product,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
-    let history = get_price_history(product.id, pool).await?;
+pub async fn upsert_price_history<'a, A>(product: &Product, conn: A) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let history = get_price_history(product.id, &mut *tr).await?;
 
     match history.last() {
         Some(entry) => {
             if entry.price == product.price {
-                update_price_entry(&product, pool).await?;
+                update_price_entry(&product, &mut *tr).await?;
             } else {
-                insert_price_entry(&product, pool).await?;
+                insert_price_entry(&product, &mut *tr).await?;
             }
         }
         None => {
-            insert_price_entry(&product, pool).await?;
+            insert_price_entry(&product, &mut *tr).await?;
         }
     }
 
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
     Ok(())
 }
 
-async fn insert_price_entry(product: &Product, pool: &Pool<Postgres>) -> Result<(), potion::Error> {
+async fn insert_price_entry<'e, E>(product: &Product, executor: E) -> Result<(), potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let _query = query("INSERT INTO product_price_history (product_id, price) VALUES ($1, $2)")
         .bind(product.id)
         .bind(product.price)
-        .execute(pool)
+        .execute(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-async fn update_price_entry(product: &Product, pool: &Pool<Postgres>) -> Result<(), potion::Error> {
+async fn update_price_entry<'e, E>(product: &Product, executor: E) -> Result<(), potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let _query = query("UPDATE product_price_history SET last_timestamp = (NOW() at time zone 'utc') WHERE product_id = $1 AND price = $2")
         .bind(product.id)
         .bind(product.price)
-        .execute(pool)
+        .execute(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
@@ -1,7 +1,9 @@
 use crate::{
     error::QueryError,
-    pagination::PageContext,
+    filter::{ProductFilter, QueryFilter},
+    pagination::{CursorPage, CursorRequest, CursorValue, Keyset, PageContext},
     schema::{Category, ProductOrder, SubCategory},
+    search::push_order,
 };
 
 use crate::{
@@ -9,166 +11,278 @@ use crate::{
     schema::{Product, ProductRow, RecipeAvailability},
 };
 
-use sqlx::{Pool, Postgres};
+use sqlx::{Acquire, Executor, Postgres, QueryBuilder};
 
-pub async fn get_product(id: i32, pool: &Pool<Postgres>) -> Result<Option<Product>, potion::Error> {
+use super::set_search_threshold;
+
+pub async fn get_product<'e, E>(id: i32, executor: E) -> Result<Option<Product>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let product: Option<Product> = sqlx::query_as("SELECT * FROM products WHERE id = $1")
         .bind(id)
-        .fetch_optional(&*pool)
+        .fetch_optional(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(product)
 }
 
-pub async fn get_product_categories(pool: &Pool<Postgres>) -> Result<Vec<Category>, potion::Error> {
+pub async fn get_product_categories<'e, E>(executor: E) -> Result<Vec<Category>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let rows: Vec<Category> = sqlx::query_as("SELECT * FROM categories")
-        .fetch_all(&*pool)
+        .fetch_all(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(rows)
 }
 
-pub async fn get_product_subcategories(
-    pool: &Pool<Postgres>,
+pub async fn get_product_subcategories<'e, E>(
+    executor: E,
     category_id: i32,
-) -> Result<Vec<SubCategory>, potion::Error> {
+) -> Result<Vec<SubCategory>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let rows: Vec<SubCategory> =
         sqlx::query_as("SELECT * FROM subcategories WHERE category_id = $1")
             .bind(category_id)
-            .fetch_all(pool)
+            .fetch_all(executor)
             .await
             .map_err(|e| QueryError::from(e).into())?;
 
     Ok(rows)
 }
 
-pub async fn list_product_subcategories(
-    pool: &Pool<Postgres>,
-) -> Result<Vec<SubCategory>, potion::Error> {
+pub async fn list_product_subcategories<'e, E>(
+    executor: E,
+) -> Result<Vec<SubCategory>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let rows: Vec<SubCategory> = sqlx::query_as("SELECT * FROM subcategories")
-        .fetch_all(pool)
+        .fetch_all(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(rows)
 }
 
-pub async fn get_product_category(
+pub async fn get_product_category<'e, E>(
     category_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<Option<Category>, potion::Error> {
+    executor: E,
+) -> Result<Option<Category>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let rows: Option<Category> = sqlx::query_as("SELECT * FROM categories WHERE id = $1")
         .bind(category_id)
-        .fetch_optional(pool)
+        .fetch_optional(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(rows)
 }
 
-pub async fn get_product_subcategory(
+pub async fn get_product_subcategory<'e, E>(
     subcategory_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<Option<SubCategory>, potion::Error> {
+    executor: E,
+) -> Result<Option<SubCategory>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let rows: Option<SubCategory> = sqlx::query_as("SELECT * FROM subcategories WHERE id = $1")
         .bind(subcategory_id)
-        .fetch_optional(pool)
+        .fetch_optional(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(rows)
 }
 
-pub async fn fetch_all_products(pool: &Pool<Postgres>) -> Result<Vec<Product>, potion::Error> {
+pub async fn fetch_all_products<'e, E>(executor: E) -> Result<Vec<Product>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let rows: Vec<Product> = sqlx::query_as(
         "
             SELECT * FROM products
         ",
     )
-    .fetch_all(pool)
+    .fetch_all(executor)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
     Ok(rows)
 }
 
-pub async fn fetch_products(
+pub async fn fetch_products<'a, A>(
     search: String,
     category_id: Option<i32>,
     sub_category: Option<i32>,
     order: Option<ProductOrder>,
     availability: Option<RecipeAvailability>,
     offset: i64,
-    pool: &Pool<Postgres>,
-) -> Result<PageContext<ProductRow>, potion::Error> {
-    let order = order
-        .map(|order| match order {
-            ProductOrder::Alphabetical => "name",
-            ProductOrder::PriceAsc => "p.price ASC",
-            ProductOrder::PriceDesc => "p.price DESC",
-            ProductOrder::UnitPriceAsc => "p.unit_price ASC",
-            ProductOrder::UnitPriceDesc => "p.unit_price DESC",
-            ProductOrder::AerAsc => "p.aer ASC",
-            ProductOrder::AerDesc => "p.aer DESC",
-        })
-        .unwrap_or("name");
-
-    let availability = availability
-        .map(|availability| match availability {
-            RecipeAvailability::Any => "",
-            RecipeAvailability::Alko => "AND p.retailer = 'alko'",
-            RecipeAvailability::Superalko => "AND p.retailer = 'superalko'",
-            RecipeAvailability::VikingLine => "AND p.retailer = 'viking_line'",
-        })
-        .unwrap_or("");
-
-    let rows: Vec<ProductRow> = match (category_id, sub_category) {
-        (Some(category_id), Some(subcategory_id)) => {
-            sqlx::query_as(&format!("
-                SELECT p.id, p.name, p.href, p.img, p.retailer, p.unit_price, p.price, p.abv, p.volume, p.last_available, p.currently_available, p.aer, COUNT(pp) OVER() FROM products p LEFT JOIN products pp ON pp.id = p.id WHERE p.category_id = $1 AND p.subcategory_id = $2 AND p.name ILIKE $3 {} ORDER BY {} LIMIT $4 OFFSET $5
-            ", availability, order))
-                .bind(category_id)
-                .bind(subcategory_id)
-                .bind(search)
-                .bind(PRODUCT_COUNT_PER_PAGE)
-                .bind(offset)
-                .fetch_all(pool).await.map_err(|e| QueryError::from(e).into())?
-        },
-        (Some(category_id), None) => {
-            sqlx::query_as(&format!("
-                SELECT p.id, p.name, p.href, p.img, p.retailer, p.unit_price, p.price, p.abv, p.volume, p.last_available, p.currently_available, p.aer, COUNT(pp) OVER() FROM products p LEFT JOIN products pp ON pp.id = p.id WHERE p.category_id = $1 AND p.name ILIKE $2 {} ORDER BY {} LIMIT $3 OFFSET $4
-            ", availability, order))
-                .bind(category_id)
-                .bind(search)
-                .bind(PRODUCT_COUNT_PER_PAGE)
-                .bind(offset)
-                .fetch_all(pool).await.map_err(|e| QueryError::from(e).into())?
-        },
-        (None, Some(subcategory_id)) => {
-            sqlx::query_as(&format!("
-                SELECT p.id, p.name, p.href, p.img, p.retailer, p.unit_price, p.price, p.abv, p.volume, p.last_available, p.currently_available, p.aer, COUNT(pp) OVER() FROM products p LEFT JOIN products pp ON pp.id = p.id WHERE p.subcategory_id = $1 AND p.name ILIKE $2 {} ORDER BY {} LIMIT $3 OFFSET $4
-            ", availability, order))
-                .bind(subcategory_id)
-                .bind(search)
-                .bind(PRODUCT_COUNT_PER_PAGE)
-                .bind(offset)
-                .fetch_all(&*pool).await.map_err(|e| QueryError::from(e).into())?
-        },
-        (None, None) => {
-            sqlx::query_as(&format!("
-                SELECT p.id, p.name, p.href, p.img, p.retailer, p.unit_price, p.price, p.abv, p.volume, p.last_available, p.currently_available, p.aer, COUNT(pp) OVER() FROM products p LEFT JOIN products pp ON pp.id = p.id WHERE p.name ILIKE $1 {} ORDER BY {} LIMIT $2 OFFSET $3
-            ", availability, order))
-                .bind(search)
-                .bind(PRODUCT_COUNT_PER_PAGE)
-                .bind(offset)
-                .fetch_all(&*pool).await.map_err(|e| QueryError::from(e).into())?
-        }
-    };
+    conn: A,
+) -> Result<PageContext<ProductRow>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let filter = ProductFilter {
+        search: Some(search),
+        categories: category_id.into_iter().collect(),
+        subcategories: sub_category.into_iter().collect(),
+        ..Default::default()
+    }
+    .with_availability(availability);
+
+    filter_products(&filter, order, offset, conn).await
+}
+
+pub async fn filter_products<'a, A>(
+    filter: &ProductFilter,
+    order: Option<ProductOrder>,
+    offset: i64,
+    conn: A,
+) -> Result<PageContext<ProductRow>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+    set_search_threshold(&mut *tr).await?;
+
+    let order = order.map(|order| match order {
+        ProductOrder::Alphabetical => "name",
+        ProductOrder::PriceAsc => "p.price ASC",
+        ProductOrder::PriceDesc => "p.price DESC",
+        ProductOrder::UnitPriceAsc => "p.unit_price ASC",
+        ProductOrder::UnitPriceDesc => "p.unit_price DESC",
+        ProductOrder::AerAsc => "p.aer ASC",
+        ProductOrder::AerDesc => "p.aer DESC",
+    });
+
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        "
+            SELECT p.id, p.name, p.href, p.img, p.retailer, p.unit_price, p.price, p.abv, p.volume, p.last_available, p.currently_available, p.aer, COUNT(*) OVER() FROM products p WHERE TRUE
+        ",
+    );
+
+    filter.push_conditions(&mut builder);
+
+    push_order(
+        order,
+        &filter.search,
+        filter.search_mode,
+        "p.name",
+        &mut builder,
+    );
+
+    builder
+        .push(" LIMIT ")
+        .push_bind(PRODUCT_COUNT_PER_PAGE)
+        .push(" OFFSET ")
+        .push_bind(offset);
+
+    let rows: Vec<ProductRow> = builder
+        .build_query_as()
+        .fetch_all(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     let total_count = *&rows.get(0).map(|p| p.count).unwrap_or(0);
     let page = PageContext::from_rows(rows, total_count, PRODUCT_COUNT_PER_PAGE, offset);
 
     Ok(page)
 }
+
+fn product_keyset(order: Option<ProductOrder>) -> Keyset<ProductRow> {
+    let (order, column, descending, value): (_, _, _, fn(&ProductRow) -> CursorValue) =
+        match order.unwrap_or(ProductOrder::Alphabetical) {
+            ProductOrder::Alphabetical => ("alphabetical", "p.name", false, |r| {
+                CursorValue::Text(r.name.clone())
+            }),
+            ProductOrder::PriceAsc => ("price_asc", "p.price", false, |r| {
+                CursorValue::Number(r.price)
+            }),
+            ProductOrder::PriceDesc => ("price_desc", "p.price", true, |r| {
+                CursorValue::Number(r.price)
+            }),
+            ProductOrder::UnitPriceAsc => ("unit_price_asc", "p.unit_price", false, |r| {
+                CursorValue::Number(r.unit_price)
+            }),
+            ProductOrder::UnitPriceDesc => ("unit_price_desc", "p.unit_price", true, |r| {
+                CursorValue::Number(r.unit_price)
+            }),
+            ProductOrder::AerAsc => ("aer_asc", "p.aer", false, |r| CursorValue::Number(r.aer)),
+            ProductOrder::AerDesc => ("aer_desc", "p.aer", true, |r| CursorValue::Number(r.aer)),
+        };
+
+    Keyset {
+        order,
+        column,
+        id_column: "p.id",
+        descending,
+        value,
+        id: |r| r.id,
+    }
+}
+
+/// Keyset paginated version of `filter_products`, which stays fast on deep pages
+pub async fn filter_products_by_cursor<'a, A>(
+    filter: &ProductFilter,
+    order: Option<ProductOrder>,
+    request: &CursorRequest,
+    conn: A,
+) -> Result<CursorPage<ProductRow>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+    set_search_threshold(&mut *tr).await?;
+
+    let keyset = product_keyset(order);
+    let cursor = keyset.parse_cursor(request)?;
+    let page_size = request.page_size(PRODUCT_COUNT_PER_PAGE);
+
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        "SELECT p.id, p.name, p.href, p.img, p.retailer, p.unit_price, p.price, p.abv, p.volume, p.last_available, p.currently_available, p.aer, ",
+    );
+
+    if request.include_total {
+        builder.push("(SELECT COUNT(*) FROM products p WHERE TRUE");
+        filter.push_conditions(&mut builder);
+        builder.push(")");
+    } else {
+        builder.push("0::BIGINT");
+    }
+
+    builder.push(" AS count FROM products p WHERE TRUE");
+    filter.push_conditions(&mut builder);
+    keyset.push_page(cursor.as_ref(), page_size, &mut builder);
+
+    let rows: Vec<ProductRow> = builder
+        .build_query_as()
+        .fetch_all(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    let total_count = match request.include_total {
+        true => Some(rows.get(0).map(|p| p.count).unwrap_or(0)),
+        false => None,
+    };
+
+    Ok(CursorPage::from_rows(
+        rows,
+        &keyset,
+        cursor.as_ref(),
+        page_size,
+        total_count,
+    ))
+}
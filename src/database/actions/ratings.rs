@@ -0,0 +1,216 @@
+use sqlx::{Acquire, Executor, Postgres};
+
+use crate::{
+    error::{QueryError, SdkError},
+    pagination::PageContext,
+    schema::RecipeRating,
+    RATING_COUNT_PER_PAGE, RATING_PRIOR_COUNT, RATING_PRIOR_MEAN,
+};
+
+/// Rating aggregates of every recipe in `drink_recipes r`, or of one recipe when `$1` is set
+const RATING_AGGREGATES_QUERY: &str = "
+    UPDATE drink_recipes r SET
+        rating_count = a.count,
+        rating_average = a.average,
+        rating_bayesian = ($2 * $3 + a.sum) / ($3 + a.count)
+    FROM (
+        SELECT d.id, COUNT(rr.score)::INT AS count,
+            COALESCE(AVG(rr.score), 0)::FLOAT8 AS average,
+            COALESCE(SUM(rr.score), 0)::FLOAT8 AS sum
+        FROM drink_recipes d
+        LEFT JOIN recipe_ratings rr ON rr.recipe_id = d.id
+        WHERE $1::INT IS NULL OR d.id = $1
+        GROUP BY d.id
+    ) a
+    WHERE r.id = a.id
+";
+
+const RATING_QUERY: &str = "
+    SELECT rr.*, u.username
+    FROM recipe_ratings rr
+    INNER JOIN users u ON u.id = rr.user_id
+";
+
+/// Rates a recipe from 1 to 5 with an optional review, replacing the earlier rating of the user
+pub async fn rate_recipe<'a, A>(
+    recipe_id: i32,
+    user_id: i32,
+    score: i32,
+    review: Option<String>,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    if !(1..=5).contains(&score) {
+        return Err(SdkError::validation("Score must be between 1 and 5").into());
+    }
+    let review = review
+        .map(|review| review.trim().to_owned())
+        .filter(|review| !review.is_empty());
+
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    lock_recipe(recipe_id, &mut *tr).await?;
+
+    sqlx::query(
+        "
+        INSERT INTO recipe_ratings (recipe_id, user_id, score, review)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (recipe_id, user_id) DO UPDATE
+        SET score = $3, review = $4, updated_at = (NOW() at time zone 'utc')
+    ",
+    )
+    .bind(recipe_id)
+    .bind(user_id)
+    .bind(score)
+    .bind(review)
+    .execute(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    update_recipe_rating(recipe_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+pub async fn remove_recipe_rating<'a, A>(
+    recipe_id: i32,
+    user_id: i32,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    lock_recipe(recipe_id, &mut *tr).await?;
+
+    let result = sqlx::query("DELETE FROM recipe_ratings WHERE recipe_id = $1 AND user_id = $2")
+        .bind(recipe_id)
+        .bind(user_id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    if result.rows_affected() == 0 {
+        return Err(SdkError::not_found("Rating doesn't exist").into());
+    }
+
+    update_recipe_rating(recipe_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+/// Locks the recipe row so concurrent ratings of it recompute the aggregates one at a time
+async fn lock_recipe<'e, E>(recipe_id: i32, executor: E) -> Result<(), potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let exists: Option<i32> =
+        sqlx::query_scalar("SELECT id FROM drink_recipes WHERE id = $1 FOR UPDATE")
+            .bind(recipe_id)
+            .fetch_optional(executor)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+    if exists.is_none() {
+        return Err(SdkError::not_found("Recipe doesn't exists").into());
+    }
+
+    Ok(())
+}
+
+pub async fn get_recipe_rating<'e, E>(
+    recipe_id: i32,
+    user_id: i32,
+    executor: E,
+) -> Result<Option<RecipeRating>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let row: Option<RecipeRating> = sqlx::query_as(&format!(
+        "{RATING_QUERY} WHERE rr.recipe_id = $1 AND rr.user_id = $2"
+    ))
+    .bind(recipe_id)
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(row)
+}
+
+/// Ratings of a recipe, latest first
+pub async fn list_recipe_ratings<'a, A>(
+    recipe_id: i32,
+    offset: i64,
+    conn: A,
+) -> Result<PageContext<RecipeRating>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let total_count: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM recipe_ratings WHERE recipe_id = $1")
+            .bind(recipe_id)
+            .fetch_one(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+
+    let rows: Vec<RecipeRating> = sqlx::query_as(&format!(
+        "{RATING_QUERY} WHERE rr.recipe_id = $1 ORDER BY rr.updated_at DESC, rr.user_id LIMIT $2 OFFSET $3"
+    ))
+    .bind(recipe_id)
+    .bind(RATING_COUNT_PER_PAGE)
+    .bind(offset)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(PageContext::from_rows(
+        rows,
+        total_count,
+        RATING_COUNT_PER_PAGE,
+        offset,
+    ))
+}
+
+/// Recomputes the rating count, average and bayesian average of a recipe from its ratings
+pub async fn update_recipe_rating<'e, E>(recipe_id: i32, executor: E) -> Result<(), potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query(RATING_AGGREGATES_QUERY)
+        .bind(Some(recipe_id))
+        .bind(RATING_PRIOR_MEAN)
+        .bind(RATING_PRIOR_COUNT)
+        .execute(executor)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+/// Recomputes the rating aggregates of every recipe, needed after changing `RATING_PRIOR_MEAN` or `RATING_PRIOR_COUNT`.
+/// Returns the number of recipes updated.
+pub async fn recalculate_recipe_ratings<'e, E>(executor: E) -> Result<u64, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let result = sqlx::query(RATING_AGGREGATES_QUERY)
+        .bind(None::<i32>)
+        .bind(RATING_PRIOR_MEAN)
+        .bind(RATING_PRIOR_COUNT)
+        .execute(executor)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(result.rows_affected())
+}
@@ -2,155 +2,210 @@ use std::collections::HashMap;
 
 use crate::{
     authentication::permissions::ActionType,
-    error::QueryError,
-    pagination::PageContext,
+    error::{QueryError, SdkError, UnitError},
+    filter::{QueryFilter, RecipeFilter},
+    pagination::{CursorPage, CursorRequest, CursorValue, Keyset, PageContext},
     schema::{
-        ParsedRecipe, ParsedRecipeRow, Recipe, RecipePart, RecipePartNoname, RecipeRow, RecipeType,
-        UnitType,
+        IceType, MethodAction, ParsedRecipe, ParsedRecipeRow, Recipe, RecipeDilution, RecipeMethod,
+        RecipePart, RecipePartNoname, RecipeRow, RecipeStep, RecipeType, UnitType,
     },
+    search::push_order,
 };
 
 use crate::{
-    constants::PRODUCT_COUNT_PER_PAGE,
     jwt::SessionData,
     schema::{
-        IngredientsForDrink, RecipeAvailability, RecipeCacheData, RecipeOrder, RecipePartNoId,
-        RecipeRowPartial, Uuid,
+        IngredientsForDrink, NewRecipePart, RecipeAvailability, RecipeCacheData, RecipeOrder,
+        RecipePartNoId, RecipeRowPartial, UnitVolumes, Uuid,
     },
-    RECIPE_COUNT_PER_PAGE,
+    DUPLICATE_THRESHOLD, ETHANOL_DENSITY, ETHANOL_KCAL_PER_G, RECIPE_COUNT_PER_PAGE,
+    STANDARD_SERVING_ML,
 };
 use potion::HtmlError;
-use sqlx::{Pool, Postgres};
+use sqlx::{Acquire, Executor, Pool, Postgres, QueryBuilder};
 
-pub async fn list_recipes(pool: &Pool<Postgres>) -> Result<Vec<Recipe>, potion::Error> {
+use super::{link_tag, record_parts_revisions, record_recipe_revision, set_search_threshold};
+
+pub async fn list_recipes<'e, E>(executor: E) -> Result<Vec<Recipe>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let rows: Vec<Recipe> = sqlx::query_as("SELECT * FROM drink_recipes")
-        .fetch_all(&*pool)
+        .fetch_all(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(rows)
 }
 
-pub async fn generate_parsed_recipe(
-    pool: &Pool<Postgres>,
-) -> Result<Option<ParsedRecipe>, potion::Error> {
-    let row: Option<ParsedRecipeRow> =
-        sqlx::query_as("SELECT * FROM parsed_drinks WHERE NOT added ORDER BY RANDOM() LIMIT 1")
-            .fetch_optional(&*pool)
-            .await
-            .map_err(|e| QueryError::from(e).into())?;
+/// Random parsed recipe from the review queue. With `hide_duplicates`, recipes that
+/// `index_parsed_recipes` found to be likely duplicates are left out.
+pub async fn generate_parsed_recipe<'e, E>(
+    hide_duplicates: bool,
+    executor: E,
+) -> Result<Option<ParsedRecipe>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let row: Option<ParsedRecipeRow> = sqlx::query_as(
+        "
+        SELECT * FROM parsed_drinks
+        WHERE NOT added AND (NOT $1 OR COALESCE(duplicate_score, 0) < $2)
+        ORDER BY RANDOM()
+        LIMIT 1
+    ",
+    )
+    .bind(hide_duplicates)
+    .bind(DUPLICATE_THRESHOLD)
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
 
     Ok(row.map(|r| r.try_into().ok()).flatten())
 }
 
-pub async fn fetch_parsed_recipe(
+pub async fn fetch_parsed_recipe<'e, E>(
     id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<Option<ParsedRecipe>, potion::Error> {
+    executor: E,
+) -> Result<Option<ParsedRecipe>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let row: Option<ParsedRecipeRow> = sqlx::query_as("SELECT * FROM parsed_drinks WHERE id = $1")
         .bind(id)
-        .fetch_optional(&*pool)
+        .fetch_optional(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(row.map(|r| r.try_into().ok()).flatten())
 }
 
-pub async fn fetch_recipes(
+pub async fn fetch_recipes<'a, A>(
     category: Option<RecipeType>,
     order: Option<RecipeOrder>,
     availability: Option<RecipeAvailability>,
     offset: i64,
     search: String,
     author: Option<i32>,
-    pool: &Pool<Postgres>,
-) -> Result<PageContext<RecipeRow>, potion::Error> {
-    let mut availability = availability
-        .map(|availability| match availability {
-            RecipeAvailability::Any => "",
-            RecipeAvailability::Alko => "AND r.available_alko",
-            RecipeAvailability::Superalko => "AND r.available_superalko",
-            // TODO see #39
-            RecipeAvailability::VikingLine => "",
-        })
-        .unwrap_or("");
-
-    if let Some(order) = &order {
-        if [
-            RecipeOrder::AerAlkoAsc,
-            RecipeOrder::AerAlkoDesc,
-            RecipeOrder::PriceAlkoAsc,
-            RecipeOrder::PriceAlkoDesc,
-        ]
-        .contains(order)
-        {
-            availability = "AND r.available_alko";
-        }
+    conn: A,
+) -> Result<PageContext<RecipeRow>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let filter = RecipeFilter {
+        include_generated: author.is_some(),
+        categories: category.into_iter().collect(),
+        author,
+        search: Some(search),
+        availability,
+        ..Default::default()
+    };
+
+    filter_recipes(&filter, order, offset, conn).await
+}
 
-        if [
-            RecipeOrder::AerSuperalkoAsc,
-            RecipeOrder::AerSuperalkoDesc,
-            RecipeOrder::PriceSuperalkoAsc,
-            RecipeOrder::PriceSuperalkoDesc,
-        ]
-        .contains(&order)
-        {
-            availability = "AND r.available_superalko";
+/// Ordering by price or aer only makes sense within the retailer in question
+fn order_availability(order: &Option<RecipeOrder>) -> Option<RecipeAvailability> {
+    match order {
+        Some(
+            RecipeOrder::AerAlkoAsc
+            | RecipeOrder::AerAlkoDesc
+            | RecipeOrder::PriceAlkoAsc
+            | RecipeOrder::PriceAlkoDesc,
+        ) => Some(RecipeAvailability::Alko),
+        Some(
+            RecipeOrder::AerSuperalkoAsc
+            | RecipeOrder::AerSuperalkoDesc
+            | RecipeOrder::PriceSuperalkoAsc
+            | RecipeOrder::PriceSuperalkoDesc,
+        ) => Some(RecipeAvailability::Superalko),
+        _ => None,
+    }
+}
+
+fn push_order_availability<'args>(
+    availability: Option<RecipeAvailability>,
+    builder: &mut QueryBuilder<'args, Postgres>,
+) {
+    match availability {
+        Some(RecipeAvailability::Alko) => {
+            builder.push(" AND r.available_alko");
         }
+        Some(RecipeAvailability::Superalko) => {
+            builder.push(" AND r.available_superalko");
+        }
+        _ => {}
     }
+}
 
-    let order = order
-        .map(|order| match order {
-            RecipeOrder::Alphabetical => "name",
-            RecipeOrder::AbvAsc => "abv_average",
-            RecipeOrder::AbvDesc => "abv_average DESC",
-            RecipeOrder::PriceSuperalkoAsc => "superalko_price_min",
-            RecipeOrder::PriceSuperalkoDesc => "superalko_price_max DESC",
-            RecipeOrder::PriceAlkoAsc => "alko_price_min",
-            RecipeOrder::PriceAlkoDesc => "alko_price_max DESC",
-            RecipeOrder::AerAlkoAsc => "alko_aer",
-            RecipeOrder::AerAlkoDesc => "alko_aer DESC",
-            RecipeOrder::AerSuperalkoAsc => "superalko_aer",
-            RecipeOrder::AerSuperalkoDesc => "superalko_aer DESC",
-            RecipeOrder::ServingsAsc => "standard_servings",
-            RecipeOrder::ServingsDesc => "standard_servings DESc",
-        })
-        .unwrap_or("name");
-
-    let rows: Vec<RecipeRowPartial> = match (category, author) {
-        (Some(category), Some(author)) => {
-            sqlx::query_as(&format!("SELECT r.*, COUNT(rr) OVER() FROM drink_recipes r LEFT JOIN drink_recipes rr ON rr.id = r.id WHERE r.type = $1 AND r.author_id = $2 AND r.name ILIKE $3 {availability} ORDER BY {order} LIMIT $4 OFFSET $5"))
-                .bind(category)
-                .bind(author)
-                .bind(search)
-                .bind(RECIPE_COUNT_PER_PAGE)
-                .bind(offset)
-                .fetch_all(&*pool).await.map_err(|e| QueryError::from(e).into())?
-        },
-        (None, Some(author)) => {
-            sqlx::query_as(&format!("SELECT r.*, COUNT(rr) OVER() FROM drink_recipes r LEFT JOIN drink_recipes rr ON rr.id = r.id WHERE r.author_id = $1 AND r.name ILIKE $2 {availability} ORDER BY {order} LIMIT $3 OFFSET $4"))
-                .bind(author)
-                .bind(search)
-                .bind(RECIPE_COUNT_PER_PAGE)
-                .bind(offset)
-                .fetch_all(&*pool).await.map_err(|e| QueryError::from(e).into())?
-        },
-        (None, None) => {
-            sqlx::query_as(&format!("SELECT r.*, COUNT(rr) OVER() FROM drink_recipes r LEFT JOIN drink_recipes rr ON rr.id = r.id WHERE r.type != 'generated' AND r.name ILIKE $1 {availability} ORDER BY {order} LIMIT $2 OFFSET $3"))
-                .bind(search)
-                .bind(RECIPE_COUNT_PER_PAGE)
-                .bind(offset)
-                .fetch_all(&*pool).await.map_err(|e| QueryError::from(e).into())?
-        },
-        (Some(category), None) => {
-            sqlx::query_as(&format!("SELECT r.*, COUNT(rr) OVER() FROM drink_recipes r LEFT JOIN drink_recipes rr ON rr.id = r.id r.type = $1 AND r.name ILIKE $2 {availability} ORDER BY {order} LIMIT $3 OFFSET $4"))
-                .bind(category)
-                .bind(search)
-                .bind(RECIPE_COUNT_PER_PAGE)
-                .bind(offset)
-                .fetch_all(&*pool).await.map_err(|e| QueryError::from(e).into())?
-        },
-    };
+pub async fn filter_recipes<'a, A>(
+    filter: &RecipeFilter,
+    order: Option<RecipeOrder>,
+    offset: i64,
+    conn: A,
+) -> Result<PageContext<RecipeRow>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+    set_search_threshold(&mut *tr).await?;
+
+    let availability = order_availability(&order);
+
+    let order = order.map(|order| match order {
+        RecipeOrder::Alphabetical => "name",
+        RecipeOrder::AbvAsc => "abv_average",
+        RecipeOrder::AbvDesc => "abv_average DESC",
+        RecipeOrder::PriceSuperalkoAsc => "superalko_price_min",
+        RecipeOrder::PriceSuperalkoDesc => "superalko_price_max DESC",
+        RecipeOrder::PriceAlkoAsc => "alko_price_min",
+        RecipeOrder::PriceAlkoDesc => "alko_price_max DESC",
+        RecipeOrder::AerAlkoAsc => "alko_aer",
+        RecipeOrder::AerAlkoDesc => "alko_aer DESC",
+        RecipeOrder::AerSuperalkoAsc => "superalko_aer",
+        RecipeOrder::AerSuperalkoDesc => "superalko_aer DESC",
+        RecipeOrder::ServingsAsc => "standard_servings",
+        RecipeOrder::ServingsDesc => "standard_servings DESC",
+        RecipeOrder::DilutedAbvAsc => "diluted_abv",
+        RecipeOrder::DilutedAbvDesc => "diluted_abv DESC",
+        RecipeOrder::DilutedVolumeAsc => "diluted_volume",
+        RecipeOrder::DilutedVolumeDesc => "diluted_volume DESC",
+        RecipeOrder::KcalAsc => "kcal",
+        RecipeOrder::KcalDesc => "kcal DESC",
+        RecipeOrder::SugarAsc => "sugar",
+        RecipeOrder::SugarDesc => "sugar DESC",
+        RecipeOrder::CarbohydratesAsc => "carbohydrates",
+        RecipeOrder::CarbohydratesDesc => "carbohydrates DESC",
+        RecipeOrder::TopRated => "rating_bayesian DESC",
+    });
+
+    let mut builder: QueryBuilder<Postgres> =
+        QueryBuilder::new("SELECT r.*, COUNT(*) OVER() FROM drink_recipes r WHERE TRUE");
+
+    filter.push_conditions(&mut builder);
+    push_order_availability(availability, &mut builder);
+
+    push_order(
+        order,
+        &filter.search,
+        filter.search_mode,
+        "r.name",
+        &mut builder,
+    );
+
+    builder
+        .push(" LIMIT ")
+        .push_bind(RECIPE_COUNT_PER_PAGE)
+        .push(" OFFSET ")
+        .push_bind(offset);
+
+    let rows: Vec<RecipeRowPartial> = builder
+        .build_query_as()
+        .fetch_all(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     let rows: Vec<RecipeRow> = rows.into_iter().map(|row| RecipeRow::from(row)).collect();
 
@@ -159,22 +214,170 @@ pub async fn fetch_recipes(
     Ok(page)
 }
 
-pub async fn list_recipes_by_incredient(
+fn recipe_keyset(order: Option<RecipeOrder>) -> Keyset<RecipeRow> {
+    let (order, column, descending, value): (_, _, _, fn(&RecipeRow) -> CursorValue) = match order
+        .unwrap_or(RecipeOrder::Alphabetical)
+    {
+        RecipeOrder::Alphabetical => ("alphabetical", "r.name", false, |r| {
+            CursorValue::Text(r.name.clone())
+        }),
+        RecipeOrder::AbvAsc => ("abv_asc", "r.abv_average", false, |r| {
+            CursorValue::Number(r.abv_average)
+        }),
+        RecipeOrder::AbvDesc => ("abv_desc", "r.abv_average", true, |r| {
+            CursorValue::Number(r.abv_average)
+        }),
+        RecipeOrder::PriceSuperalkoAsc => {
+            ("price_superalko_asc", "r.superalko_price_min", false, |r| {
+                CursorValue::Number(r.superalko_price_min)
+            })
+        }
+        RecipeOrder::PriceSuperalkoDesc => {
+            ("price_superalko_desc", "r.superalko_price_max", true, |r| {
+                CursorValue::Number(r.superalko_price_max)
+            })
+        }
+        RecipeOrder::PriceAlkoAsc => ("price_alko_asc", "r.alko_price_min", false, |r| {
+            CursorValue::Number(r.alko_price_min)
+        }),
+        RecipeOrder::PriceAlkoDesc => ("price_alko_desc", "r.alko_price_max", true, |r| {
+            CursorValue::Number(r.alko_price_max)
+        }),
+        RecipeOrder::AerAlkoAsc => ("aer_alko_asc", "r.alko_aer", false, |r| {
+            CursorValue::Number(r.alko_aer)
+        }),
+        RecipeOrder::AerAlkoDesc => ("aer_alko_desc", "r.alko_aer", true, |r| {
+            CursorValue::Number(r.alko_aer)
+        }),
+        RecipeOrder::AerSuperalkoAsc => ("aer_superalko_asc", "r.superalko_aer", false, |r| {
+            CursorValue::Number(r.superalko_aer)
+        }),
+        RecipeOrder::AerSuperalkoDesc => ("aer_superalko_desc", "r.superalko_aer", true, |r| {
+            CursorValue::Number(r.superalko_aer)
+        }),
+        RecipeOrder::ServingsAsc => ("servings_asc", "r.standard_servings", false, |r| {
+            CursorValue::Number(r.standard_servings)
+        }),
+        RecipeOrder::ServingsDesc => ("servings_desc", "r.standard_servings", true, |r| {
+            CursorValue::Number(r.standard_servings)
+        }),
+        RecipeOrder::DilutedAbvAsc => ("diluted_abv_asc", "r.diluted_abv", false, |r| {
+            CursorValue::Number(r.diluted_abv)
+        }),
+        RecipeOrder::DilutedAbvDesc => ("diluted_abv_desc", "r.diluted_abv", true, |r| {
+            CursorValue::Number(r.diluted_abv)
+        }),
+        RecipeOrder::DilutedVolumeAsc => ("diluted_volume_asc", "r.diluted_volume", false, |r| {
+            CursorValue::Number(r.diluted_volume)
+        }),
+        RecipeOrder::DilutedVolumeDesc => ("diluted_volume_desc", "r.diluted_volume", true, |r| {
+            CursorValue::Number(r.diluted_volume)
+        }),
+        RecipeOrder::KcalAsc => ("kcal_asc", "r.kcal", false, |r| CursorValue::Number(r.kcal)),
+        RecipeOrder::KcalDesc => ("kcal_desc", "r.kcal", true, |r| CursorValue::Number(r.kcal)),
+        RecipeOrder::SugarAsc => ("sugar_asc", "r.sugar", false, |r| {
+            CursorValue::Number(r.sugar)
+        }),
+        RecipeOrder::SugarDesc => ("sugar_desc", "r.sugar", true, |r| {
+            CursorValue::Number(r.sugar)
+        }),
+        RecipeOrder::CarbohydratesAsc => ("carbohydrates_asc", "r.carbohydrates", false, |r| {
+            CursorValue::Number(r.carbohydrates)
+        }),
+        RecipeOrder::CarbohydratesDesc => ("carbohydrates_desc", "r.carbohydrates", true, |r| {
+            CursorValue::Number(r.carbohydrates)
+        }),
+        RecipeOrder::TopRated => ("top_rated", "r.rating_bayesian", true, |r| {
+            CursorValue::Number(r.rating_bayesian)
+        }),
+    };
+
+    Keyset {
+        order,
+        column,
+        id_column: "r.id",
+        descending,
+        value,
+        id: |r| r.id,
+    }
+}
+
+/// Keyset paginated version of `filter_recipes`
+pub async fn filter_recipes_by_cursor<'a, A>(
+    filter: &RecipeFilter,
+    order: Option<RecipeOrder>,
+    request: &CursorRequest,
+    conn: A,
+) -> Result<CursorPage<RecipeRow>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+    set_search_threshold(&mut *tr).await?;
+
+    let availability = order_availability(&order);
+    let keyset = recipe_keyset(order);
+    let cursor = keyset.parse_cursor(request)?;
+    let page_size = request.page_size(RECIPE_COUNT_PER_PAGE);
+
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("SELECT r.*, ");
+
+    if request.include_total {
+        builder.push("(SELECT COUNT(*) FROM drink_recipes r WHERE TRUE");
+        filter.push_conditions(&mut builder);
+        push_order_availability(availability.clone(), &mut builder);
+        builder.push(")");
+    } else {
+        builder.push("0::BIGINT");
+    }
+
+    builder.push(" AS count FROM drink_recipes r WHERE TRUE");
+    filter.push_conditions(&mut builder);
+    push_order_availability(availability, &mut builder);
+    keyset.push_page(cursor.as_ref(), page_size, &mut builder);
+
+    let rows: Vec<RecipeRowPartial> = builder
+        .build_query_as()
+        .fetch_all(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    let rows: Vec<RecipeRow> = rows.into_iter().map(|row| RecipeRow::from(row)).collect();
+
+    let total_count = match request.include_total {
+        true => Some(rows.get(0).map(|p| p.count).unwrap_or(0)),
+        false => None,
+    };
+
+    Ok(CursorPage::from_rows(
+        rows,
+        &keyset,
+        cursor.as_ref(),
+        page_size,
+        total_count,
+    ))
+}
+
+pub async fn list_recipes_by_incredient<'e, E>(
     incredient_id: i32,
     offset: i64,
-    pool: &Pool<Postgres>,
-) -> Result<PageContext<RecipeRow>, potion::Error> {
+    executor: E,
+) -> Result<PageContext<RecipeRow>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let rows: Vec<RecipeRowPartial> = sqlx::query_as(
         "
-        SELECT r.*, count(rr) OVER()
+        SELECT r.*, COUNT(*) OVER()
         FROM recipe_parts rp
         INNER JOIN drink_recipes r ON r.recipe_id = rp.recipe_id
-        INNER JOIN drink_recipes rr ON rr.id = r.id
         WHERE rp.incredient_id = $1
     ",
     )
     .bind(incredient_id)
-    .fetch_all(&*pool)
+    .fetch_all(executor)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
@@ -185,10 +388,13 @@ pub async fn list_recipes_by_incredient(
     Ok(page)
 }
 
-pub async fn recipe_count_by_incredient(
+pub async fn recipe_count_by_incredient<'e, E>(
     incredient_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<i64, potion::Error> {
+    executor: E,
+) -> Result<i64, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let count: (i64,) = sqlx::query_as(
         "
         SELECT count(r.id) as count
@@ -198,17 +404,20 @@ pub async fn recipe_count_by_incredient(
     ",
     )
     .bind(incredient_id)
-    .fetch_one(&*pool)
+    .fetch_one(executor)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
     Ok(count.0)
 }
 
-pub async fn list_recipe_parts(
-    pool: &Pool<Postgres>,
+pub async fn list_recipe_parts<'e, E>(
+    executor: E,
     recipe_id: i32,
-) -> Result<Vec<RecipePart>, potion::Error> {
+) -> Result<Vec<RecipePart>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let rows: Vec<RecipePart> = sqlx::query_as("
         SELECT rp.recipe_id AS recipe_id, d.id AS incredient_id, rp.amount AS amount, rp.unit AS unit, d.name AS name
         FROM recipe_parts rp
@@ -216,33 +425,39 @@ pub async fn list_recipe_parts(
         WHERE rp.recipe_id = $1
     ")
     .bind(recipe_id)
-    .fetch_all(pool).await.map_err(|e| QueryError::from(e).into())?;
+    .fetch_all(executor).await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(rows)
 }
 
-pub async fn list_recipe_parts_all(
-    pool: &Pool<Postgres>,
-) -> Result<Vec<RecipePartNoname>, potion::Error> {
+pub async fn list_recipe_parts_all<'e, E>(
+    executor: E,
+) -> Result<Vec<RecipePartNoname>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let rows: Vec<RecipePartNoname> = sqlx::query_as(
         "
         SELECT * FROM recipe_parts
     ",
     )
-    .fetch_all(pool)
+    .fetch_all(executor)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
     Ok(rows)
 }
 
-pub async fn list_all_recipe_parts(
-    pool: &Pool<Postgres>,
-) -> Result<Vec<IngredientsForDrink>, potion::Error> {
+pub async fn list_all_recipe_parts<'e, E>(
+    executor: E,
+) -> Result<Vec<IngredientsForDrink>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let filters: Vec<RecipePart> = sqlx::query_as("SELECT r.recipe_id AS recipe_id, r.incredient_id AS incredient_id, r.amount AS amount, r.unit AS unit, d.name AS name
                                                   FROM recipe_parts r
                                                   INNER JOIN drink_incredients d ON d.id = r.incredient_id")
-        .fetch_all(pool).await.map_err(|e| QueryError::from(e).into())?;
+        .fetch_all(executor).await.map_err(|e| QueryError::from(e).into())?;
     let mut hashmap: HashMap<Uuid, Vec<RecipePartNoId>> = HashMap::new();
     filters
         .into_iter()
@@ -261,14 +476,39 @@ pub async fn list_all_recipe_parts(
         .collect())
 }
 
-pub async fn create_recipe(
+pub async fn create_recipe<'a, A>(
     category: RecipeType,
     user_id: i32,
     name: String,
-    pool: &Pool<Postgres>,
-) -> Result<i32, potion::Error> {
+    conn: A,
+) -> Result<i32, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let id = insert_recipe(category, user_id, name, &mut *tr).await?;
+    record_recipe_revision(id, user_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(id)
+}
+
+/// Creates an empty recipe without recording a revision
+pub(crate) async fn insert_recipe<'a, A>(
+    category: RecipeType,
+    user_id: i32,
+    name: String,
+    conn: A,
+) -> Result<i32, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     let recipe: (i32,) = sqlx::query_as("INSERT INTO recipes DEFAULT VALUES RETURNING id")
-        .fetch_one(pool)
+        .fetch_one(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
@@ -285,77 +525,320 @@ pub async fn create_recipe(
     .bind(user_id)
     .bind(name)
     .bind(recipe_id)
-    .fetch_one(pool)
+    .fetch_one(&mut *tr)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
     Ok(id.0)
 }
 
-pub async fn skip_parsed_recipe(
-    parsed_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
-    let _query = sqlx::query("DELETE FROM parsed_drinks WHERE id = $1")
-        .bind(parsed_id)
-        .execute(pool)
-        .await
-        .map_err(|e| QueryError::from(e).into())?;
-
-    Ok(())
+/// Creates a recipe together with its info, parts and tags.
+/// Nothing is created if any of the steps fail.
+pub async fn create_recipe_with_parts<'a, A>(
+    category: RecipeType,
+    user_id: i32,
+    name: String,
+    info: String,
+    parts: &[NewRecipePart],
+    tag_ids: &[i32],
+    conn: A,
+) -> Result<i32, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    check_piece_volumes(parts, None, &mut *tr).await?;
+    let id =
+        insert_recipe_with_parts(category, user_id, name, info, parts, tag_ids, &mut *tr).await?;
+    record_recipe_revision(id, user_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(id)
 }
 
-pub async fn import_parsed_recipe(
-    parsed_id: i32,
+/// `create_recipe_with_parts` without recording a revision
+pub(crate) async fn insert_recipe_with_parts<'a, A>(
+    category: RecipeType,
     user_id: i32,
     name: String,
-    pool: &Pool<Postgres>,
-) -> Result<i32, potion::Error> {
-    let _update = sqlx::query("UPDATE parsed_drinks SET added = true WHERE id = $1")
-        .bind(parsed_id)
-        .execute(pool)
+    info: String,
+    parts: &[NewRecipePart],
+    tag_ids: &[i32],
+    conn: A,
+) -> Result<i32, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let id = insert_recipe(category, user_id, name, &mut *tr).await?;
+    let recipe = get_recipe(id, &mut *tr)
+        .await?
+        .ok_or_else(|| HtmlError::InternalServerError.new("Failed to create recipe"))?;
+
+    sqlx::query("UPDATE drink_recipes SET info = $1 WHERE id = $2")
+        .bind(info)
+        .bind(id)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
-    let recipe: (i32,) = sqlx::query_as("INSERT INTO recipes DEFAULT VALUES RETURNING id")
-        .fetch_one(pool)
+    write_recipe_parts(recipe.recipe_id, parts, &mut *tr).await?;
+
+    for tag_id in tag_ids {
+        link_tag(id, *tag_id, &mut *tr).await?;
+    }
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(id)
+}
+
+/// Replaces every part of a recipe with `parts` and recalculates the cached data as a single unit
+pub async fn replace_recipe_parts<'a, A>(
+    recipe_id: i32,
+    parts: &[NewRecipePart],
+    author_id: i32,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    check_piece_volumes(parts, Some(recipe_id), &mut *tr).await?;
+    write_recipe_parts(recipe_id, parts, &mut *tr).await?;
+    record_parts_revisions(recipe_id, author_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+/// `replace_recipe_parts` without recording a revision
+pub(crate) async fn write_recipe_parts<'a, A>(
+    recipe_id: i32,
+    parts: &[NewRecipePart],
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    sqlx::query("DELETE FROM recipe_parts WHERE recipe_id = $1")
+        .bind(recipe_id)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
-    let recipe_id = recipe.0;
+    if parts.len() > 0 {
+        let incredient_ids: Vec<i32> = parts.iter().map(|part| part.incredient_id).collect();
+        let piece_volumes = fetch_piece_volumes(&incredient_ids, &mut *tr).await?;
+        let volumes: Vec<f64> = parts
+            .iter()
+            .map(|part| {
+                part_volume(
+                    &part.unit,
+                    part.amount,
+                    piece_volumes.get(&part.incredient_id).copied(),
+                )
+            })
+            .collect();
+
+        let mut query_builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "INSERT INTO recipe_parts (recipe_id, incredient_id, amount, amount_standard, unit) ",
+        );
+
+        let rows = parts.iter().zip(volumes).take(65535 / 5);
+        query_builder.push_values(rows, |mut b, (part, amount_ml)| {
+            b.push_bind(recipe_id)
+                .push_bind(part.incredient_id)
+                .push_bind(part.amount)
+                .push_bind(amount_ml)
+                .push_bind(part.unit.clone());
+        });
 
-    let id: (i32,) = sqlx::query_as(
+        query_builder
+            .build()
+            .execute(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+    }
+
+    update_recipe_cached_data(recipe_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+/// Volume of a part in ml, as stored in `recipe_parts.amount_standard`.
+/// Pieces of incredients without a volume per piece count as 0 ml, which flags the recipe
+/// with `unknown_piece_volume`. Only parts that were stored before piece volumes existed
+/// get here, new ones are rejected by `check_piece_volumes`.
+fn part_volume(unit: &UnitType, amount: f64, piece_ml: Option<f64>) -> f64 {
+    let volumes = UnitVolumes { piece_ml };
+
+    unit.to_ml(amount, &volumes).unwrap_or_default()
+}
+
+/// Rejects `kpl` parts of incredients without a volume per piece, unless the part is
+/// already stored in pieces in the recipe with the parts id `existing`
+pub(crate) async fn check_piece_volumes<'e, E>(
+    parts: &[NewRecipePart],
+    existing: Option<i32>,
+    executor: E,
+) -> Result<(), potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let piece_ids: Vec<i32> = parts
+        .iter()
+        .filter(|part| part.unit == UnitType::Kpl)
+        .map(|part| part.incredient_id)
+        .collect();
+
+    if piece_ids.is_empty() {
+        return Ok(());
+    }
+
+    let missing: Option<i32> = sqlx::query_scalar(
         "
-        INSERT INTO drink_recipes (type, author_id, name, recipe_id, import_origin)
-        VALUES ($1, $2, $3, $4, $5)
-        RETURNING id
+        SELECT d.id FROM drink_incredients d
+        WHERE d.id = ANY($1) AND d.piece_volume_ml IS NULL
+        AND NOT EXISTS (
+            SELECT 1 FROM recipe_parts rp
+            WHERE rp.recipe_id = $2 AND rp.incredient_id = d.id AND rp.unit = 'kpl'
+        )
+        LIMIT 1
     ",
     )
-    .bind(RecipeType::Generated)
-    .bind(user_id)
-    .bind(name)
-    .bind(recipe_id)
-    .bind(parsed_id)
-    .fetch_one(pool)
+    .bind(&piece_ids)
+    .bind(existing)
+    .fetch_optional(executor)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
-    Ok(id.0)
+    match missing {
+        Some(_) => Err(UnitError::MissingPieceVolume.into()),
+        None => Ok(()),
+    }
 }
 
-pub async fn delete_recipe(id: i32, pool: &Pool<Postgres>) -> Result<(), potion::Error> {
-    let recipe = get_recipe(id, &pool).await?;
+async fn fetch_piece_volumes<'e, E>(
+    incredient_ids: &[i32],
+    executor: E,
+) -> Result<HashMap<Uuid, f64>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let rows: Vec<(i32, f64)> = sqlx::query_as(
+        "SELECT id, piece_volume_ml FROM drink_incredients WHERE id = ANY($1) AND piece_volume_ml IS NOT NULL",
+    )
+    .bind(incredient_ids)
+    .fetch_all(executor)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(rows.into_iter().collect())
+}
+
+/// Recomputes `amount_standard` of every recipe part, and the cached data of the recipes that changed.
+/// Should be run after the unit volumes have changed, for example after the `units` migration.
+/// Returns the number of recipes that changed.
+pub async fn recalculate_part_volumes<'a, A>(conn: A) -> Result<u64, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let parts: Vec<(i32, i32, f64, UnitType, f64, Option<f64>)> = sqlx::query_as(
+        "
+        SELECT rp.recipe_id, rp.incredient_id, rp.amount, rp.unit, rp.amount_standard, d.piece_volume_ml
+        FROM recipe_parts rp
+        INNER JOIN drink_incredients d ON d.id = rp.incredient_id
+    ",
+    )
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let mut changed: Vec<i32> = vec![];
+    for (recipe_id, incredient_id, amount, unit, amount_standard, piece_ml) in parts {
+        let amount_ml = part_volume(&unit, amount, piece_ml);
+        if (amount_ml - amount_standard).abs() < 1e-9 {
+            continue;
+        }
+
+        sqlx::query(
+            "UPDATE recipe_parts SET amount_standard = $1 WHERE recipe_id = $2 AND incredient_id = $3",
+        )
+        .bind(amount_ml)
+        .bind(recipe_id)
+        .bind(incredient_id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+        if !changed.contains(&recipe_id) {
+            changed.push(recipe_id);
+        }
+    }
+
+    for recipe_id in changed.iter() {
+        update_recipe_cached_data(*recipe_id, &mut *tr).await?;
+    }
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(changed.len() as u64)
+}
+
+pub async fn skip_parsed_recipe<'e, E>(parsed_id: i32, executor: E) -> Result<(), potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let _query = sqlx::query("DELETE FROM parsed_drinks WHERE id = $1")
+        .bind(parsed_id)
+        .execute(executor)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+pub async fn delete_recipe<'a, A>(id: i32, conn: A) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let recipe = get_recipe(id, &mut *tr).await?;
     if recipe.is_none() {
-        return Err(HtmlError::InvalidRequest.new("Recipe doesn't exists"));
+        return Err(SdkError::not_found("Recipe doesn't exists").into());
     }
     let recipe = recipe.unwrap();
 
-    let mut tr = pool
-        .begin()
+    sqlx::query("DELETE FROM user_favorites WHERE drink_id = $1")
+        .bind(id)
+        .execute(&mut *tr)
         .await
-        .map_err(|_| QueryError::new("Could not start transaction".to_owned()).into())?;
+        .map_err(|e| QueryError::from(e).into())?;
 
-    sqlx::query("DELETE FROM user_favorites WHERE drink_id = $1")
+    sqlx::query("DELETE FROM recipe_ratings WHERE recipe_id = $1")
+        .bind(id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    // Forks of the recipe are attributed to its parent instead
+    sqlx::query("UPDATE drink_recipes SET parent_id = $1 WHERE parent_id = $2")
+        .bind(recipe.parent_id)
         .bind(id)
         .execute(&mut *tr)
         .await
@@ -367,6 +850,26 @@ pub async fn delete_recipe(id: i32, pool: &Pool<Postgres>) -> Result<(), potion:
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
+    sqlx::query("DELETE FROM recipe_steps WHERE recipe_id = $1")
+        .bind(id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    sqlx::query(
+        "DELETE FROM recipe_revision_parts WHERE revision_id IN (SELECT id FROM recipe_revisions WHERE recipe_id = $1)",
+    )
+    .bind(id)
+    .execute(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    sqlx::query("DELETE FROM recipe_revisions WHERE recipe_id = $1")
+        .bind(id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
     sqlx::query("DELETE FROM drink_recipes WHERE id = $1")
         .bind(id)
         .execute(&mut *tr)
@@ -385,27 +888,55 @@ pub async fn delete_recipe(id: i32, pool: &Pool<Postgres>) -> Result<(), potion:
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
-    tr.commit()
-        .await
-        .map_err(|_| QueryError::new("Could not commit transaction".to_owned()).into())?;
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
     Ok(())
 }
 
-pub async fn find_recipe(name: &str, pool: &Pool<Postgres>) -> Result<Option<i32>, potion::Error> {
-    let row: Option<(i32,)> =
-        sqlx::query_as("SELECT id FROM drink_recipes WHERE LOWER(name) = LOWER($1)")
-            .bind(name)
-            .fetch_optional(&*pool)
-            .await
-            .map_err(|e| QueryError::from(e).into())?;
+/// Recipe of `author_id` with the given name, ignoring case
+pub async fn find_recipe<'e, E>(
+    name: &str,
+    author_id: i32,
+    executor: E,
+) -> Result<Option<i32>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let row: Option<(i32,)> = sqlx::query_as(
+        "SELECT id FROM drink_recipes WHERE author_id = $1 AND LOWER(name) = LOWER($2)",
+    )
+    .bind(author_id)
+    .bind(name)
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
 
     Ok(row.map(|r| r.0))
 }
 
-pub async fn get_recipe(id: i32, pool: &Pool<Postgres>) -> Result<Option<Recipe>, potion::Error> {
-    let row: Option<Recipe> = sqlx::query_as("SELECT * FROM drink_recipes WHERE id = $1")
+/// Recipe with its method steps aggregated as JSON into a `steps` column
+const RECIPE_WITH_STEPS_QUERY: &str = "
+    SELECT r.*, COALESCE(
+        (
+            SELECT json_agg(
+                json_build_object('position', s.position, 'action', s.action, 'instruction', s.instruction)
+                ORDER BY s.position
+            )
+            FROM recipe_steps s
+            WHERE s.recipe_id = r.id
+        )::TEXT,
+        '[]'
+    ) AS steps
+    FROM drink_recipes r
+    WHERE r.id = $1
+";
+
+pub async fn get_recipe<'e, E>(id: i32, executor: E) -> Result<Option<Recipe>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let row: Option<Recipe> = sqlx::query_as(RECIPE_WITH_STEPS_QUERY)
         .bind(id)
-        .fetch_optional(&*pool)
+        .fetch_optional(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
@@ -416,7 +947,7 @@ pub async fn get_recipe_owned(
     id: i32,
     pool: Pool<Postgres>,
 ) -> Result<Option<Recipe>, potion::Error> {
-    let row: Option<Recipe> = sqlx::query_as("SELECT * FROM drink_recipes WHERE id = $1")
+    let row: Option<Recipe> = sqlx::query_as(RECIPE_WITH_STEPS_QUERY)
         .bind(id)
         .fetch_optional(&pool)
         .await
@@ -425,10 +956,10 @@ pub async fn get_recipe_owned(
     Ok(row)
 }
 
-pub async fn get_recipe_author(
-    id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<Option<String>, potion::Error> {
+pub async fn get_recipe_author<'e, E>(id: i32, executor: E) -> Result<Option<String>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let row: Option<(String,)> = sqlx::query_as(
         "
         SELECT u.username 
@@ -438,19 +969,22 @@ pub async fn get_recipe_author(
     ",
     )
     .bind(id)
-    .fetch_optional(&*pool)
+    .fetch_optional(executor)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
     Ok(row.map(|x| x.0))
 }
 
-pub async fn get_recipe_mut(
+pub async fn get_recipe_mut<'e, E>(
     id: i32,
     session: SessionData,
-    pool: &Pool<Postgres>,
-) -> Result<Recipe, potion::Error> {
-    let recipe = get_recipe(id, pool).await?;
+    executor: E,
+) -> Result<Recipe, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let recipe = get_recipe(id, executor).await?;
     session.authenticate(ActionType::ManageOwnRecipes)?;
 
     match recipe {
@@ -464,37 +998,237 @@ pub async fn get_recipe_mut(
                 }
             }
         },
-        None => Err(HtmlError::InvalidRequest.new("No recipe exists with spcified id")),
+        None => Err(SdkError::not_found("No recipe exists with spcified id").into()),
     }
 }
 
-pub async fn update_recipe_info(
+pub async fn update_recipe_info<'a, A>(
     id: i32,
     name: String,
     category: RecipeType,
     info: String,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    author_id: i32,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     sqlx::query("UPDATE drink_recipes SET name = $1, type = $2, info = $3 WHERE id = $4")
         .bind(name)
         .bind(category)
         .bind(info)
         .bind(id)
-        .execute(&*pool)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    record_recipe_revision(id, author_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+pub async fn list_recipe_steps<'e, E>(
+    id: i32,
+    executor: E,
+) -> Result<Vec<RecipeStep>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let rows: Vec<RecipeStep> = sqlx::query_as(
+        "SELECT position, action, instruction FROM recipe_steps WHERE recipe_id = $1 ORDER BY position",
+    )
+    .bind(id)
+    .fetch_all(executor)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(rows)
+}
+
+/// Sets the number of servings the recipe makes, and recomputes its nutrition per serving
+pub async fn set_recipe_servings<'a, A>(
+    id: i32,
+    servings: i32,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    if servings < 1 {
+        return Err(SdkError::validation("Recipe must make at least one serving").into());
+    }
+
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let parts_id: Option<i32> = sqlx::query_scalar(
+        "UPDATE drink_recipes SET servings = $1 WHERE id = $2 RETURNING recipe_id",
+    )
+    .bind(servings)
+    .bind(id)
+    .fetch_optional(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+    let Some(parts_id) = parts_id else {
+        return Err(SdkError::not_found("Recipe doesn't exists").into());
+    };
+
+    update_recipe_cached_data(parts_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+/// Replaces the steps, glassware, ice and garnish of a recipe.
+/// Steps are numbered from 1 in the order given, an empty garnish is stored as none.
+pub async fn set_recipe_method<'a, A>(
+    id: i32,
+    method: &RecipeMethod,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let garnish = method
+        .garnish
+        .as_ref()
+        .map(|garnish| garnish.trim().to_owned())
+        .filter(|garnish| !garnish.is_empty());
+
+    let result = sqlx::query(
+        "UPDATE drink_recipes SET glassware = $2, ice = $3, garnish = $4 WHERE id = $1",
+    )
+    .bind(id)
+    .bind(method.glassware)
+    .bind(method.ice)
+    .bind(garnish)
+    .execute(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+    if result.rows_affected() == 0 {
+        return Err(SdkError::not_found("Recipe doesn't exists").into());
+    }
+
+    sqlx::query("DELETE FROM recipe_steps WHERE recipe_id = $1")
+        .bind(id)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
+    if !method.steps.is_empty() {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "INSERT INTO recipe_steps (recipe_id, position, action, instruction) ",
+        );
+        builder.push_values(method.steps.iter().enumerate(), |mut b, (i, step)| {
+            b.push_bind(id)
+                .push_bind(i as i32 + 1)
+                .push_bind(step.action)
+                .push_bind(step.instruction.trim().to_owned());
+        });
+        builder
+            .build()
+            .execute(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+    }
+
+    update_recipe_dilution(id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
     Ok(())
 }
 
-pub async fn add_to_recipe(
+/// Recomputes the diluted volume and ABV of a recipe from its cached volume and ABV and its method
+pub async fn update_recipe_dilution<'a, A>(
+    id: i32,
+    conn: A,
+) -> Result<RecipeDilution, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let row: Option<(f64, f64, Option<IceType>)> =
+        sqlx::query_as("SELECT total_volume, abv_average, ice FROM drink_recipes WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+    let Some((volume, abv, ice)) = row else {
+        return Err(SdkError::not_found("Recipe doesn't exists").into());
+    };
+
+    let actions: Vec<MethodAction> = list_recipe_steps(id, &mut *tr)
+        .await?
+        .into_iter()
+        .map(|step| step.action)
+        .collect();
+    let dilution = RecipeDilution::new(&actions, ice, volume, abv);
+
+    sqlx::query("UPDATE drink_recipes SET diluted_volume = $2, diluted_abv = $3 WHERE id = $1")
+        .bind(id)
+        .bind(dilution.diluted_volume)
+        .bind(dilution.diluted_abv)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(dilution)
+}
+
+/// Recomputes the diluted volume and ABV of every recipe, for example after the `recipe_dilution` migration.
+/// Returns the number of recipes updated.
+pub async fn recalculate_recipe_dilution<'a, A>(conn: A) -> Result<u64, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let ids: Vec<i32> = sqlx::query_scalar("SELECT id FROM drink_recipes")
+        .fetch_all(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    for id in ids.iter() {
+        update_recipe_dilution(*id, &mut *tr).await?;
+    }
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(ids.len() as u64)
+}
+
+pub async fn add_to_recipe<'a, A>(
     recipe_id: i32,
     base: i32,
     unit: UnitType,
-    amount: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
-    let amount_ml = unit.convert(amount.into(), UnitType::Ml).1;
+    amount: f64,
+    author_id: i32,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let part = NewRecipePart {
+        incredient_id: base,
+        amount,
+        unit: unit.clone(),
+    };
+    check_piece_volumes(&[part], Some(recipe_id), &mut *tr).await?;
+
+    let piece_volumes = fetch_piece_volumes(&[base], &mut *tr).await?;
+    let amount_ml = part_volume(&unit, amount, piece_volumes.get(&base).copied());
 
     sqlx::query(
         "
@@ -509,20 +1243,29 @@ pub async fn add_to_recipe(
     .bind(amount)
     .bind(amount_ml)
     .bind(unit)
-    .execute(&*pool)
+    .execute(&mut *tr)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
-    update_recipe_cached_data(recipe_id, pool).await?;
+    update_recipe_cached_data(recipe_id, &mut *tr).await?;
+    record_parts_revisions(recipe_id, author_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn remove_from_recipe(
+pub async fn remove_from_recipe<'a, A>(
     recipe_id: i32,
     incredient_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    author_id: i32,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     sqlx::query(
         "
         DELETE FROM recipe_parts WHERE recipe_id = $1 AND incredient_id = $2;
@@ -530,19 +1273,25 @@ pub async fn remove_from_recipe(
     )
     .bind(recipe_id)
     .bind(incredient_id)
-    .execute(&*pool)
+    .execute(&mut *tr)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
-    update_recipe_cached_data(recipe_id, pool).await?;
+    update_recipe_cached_data(recipe_id, &mut *tr).await?;
+    record_parts_revisions(recipe_id, author_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn calculate_recipe_cached_data(
+pub async fn calculate_recipe_cached_data<'e, E>(
     recipe_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<RecipeCacheData, potion::Error> {
+    executor: E,
+) -> Result<RecipeCacheData, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let data: Option<RecipeCacheData> = sqlx::query_as("
         SELECT COUNT(e1) AS incredient_count,
             bool_and(e1.apc) AS available_alko,
@@ -550,9 +1299,9 @@ pub async fn calculate_recipe_cached_data(
 
             COALESCE(SUM(e1.volume), 0) AS total_volume,
 
-            ((SUM(e1.ethanol_min) + SUM(e1.ethanol_max)) / 2) / 17.7 AS standard_servings,
-            (( SUM(e1.alko_price_min) + SUM(e1.alko_price_average) ) / 2) / COALESCE( NULLIF( ( ( ( SUM(e1.ethanol_min) + SUM(e1.ethanol_max) ) / 2) / 17.7), 0 ), 1) AS alko_price_per_serving,
-            (( SUM(e1.superalko_price_average) + SUM(e1.superalko_price_min) ) / 2) / COALESCE( NULLIF( ( ( ( SUM(e1.ethanol_min) + SUM(e1.ethanol_max) ) / 2) / 17.7), 0 ), 1) AS superalko_price_per_serving,
+            ((SUM(e1.ethanol_min) + SUM(e1.ethanol_max)) / 2) / $3 AS standard_servings,
+            (( SUM(e1.alko_price_min) + SUM(e1.alko_price_average) ) / 2) / COALESCE( NULLIF( ( ( ( SUM(e1.ethanol_min) + SUM(e1.ethanol_max) ) / 2) / $3), 0 ), 1) AS alko_price_per_serving,
+            (( SUM(e1.superalko_price_average) + SUM(e1.superalko_price_min) ) / 2) / COALESCE( NULLIF( ( ( ( SUM(e1.ethanol_min) + SUM(e1.ethanol_max) ) / 2) / $3), 0 ), 1) AS superalko_price_per_serving,
 
             ( ( ( SUM(e1.ethanol_min) + SUM(e1.ethanol_max) ) / 2) / COALESCE ( NULLIF ( ( ( SUM(e1.alko_price_average) + SUM(e1.alko_price_min) ) / 2 ), 0 ), 1 ) ) / 10 as alko_aer,
             ( ( ( SUM(e1.ethanol_min) + SUM(e1.ethanol_max) ) / 2) / COALESCE ( NULLIF ( ( ( SUM(e1.superalko_price_average) + SUM(e1.superalko_price_min) ) / 2 ), 0 ), 1 ) ) / 10 as superalko_aer,
@@ -567,7 +1316,13 @@ pub async fn calculate_recipe_cached_data(
 
             (SUM(e1.ethanol_min) / COALESCE (NULLIF ( SUM(e1.volume), 0), 1)) * 100 AS abv_min,
             ( ( (SUM(e1.ethanol_min) / COALESCE (NULLIF ( SUM(e1.volume), 0), 1 )) + ( SUM(e1.ethanol_max) / COALESCE (NULLIF ( SUM(e1.volume), 0), 1) ) ) / 2) * 100 AS abv_average,
-            (SUM(e1.ethanol_max) / COALESCE (NULLIF ( SUM(e1.volume), 0), 1)) * 100 AS abv_max
+            (SUM(e1.ethanol_max) / COALESCE (NULLIF ( SUM(e1.volume), 0), 1)) * 100 AS abv_max,
+
+            COALESCE(SUM(e1.kcal) + ((SUM(e1.ethanol_min) + SUM(e1.ethanol_max)) / 2) * $2, 0) AS kcal,
+            COALESCE(SUM(e1.sugar), 0) AS sugar,
+            COALESCE(SUM(e1.carbohydrates), 0) AS carbohydrates,
+
+            COALESCE(bool_or(e1.unknown_piece_volume), FALSE) AS unknown_piece_volume
         FROM (
             SELECT rp.recipe_id AS id,
                 rp.amount_standard AS volume,
@@ -583,16 +1338,31 @@ pub async fn calculate_recipe_cached_data(
                 (d.superalko_price_average / 1000) * rp.amount_standard as superalko_price_average,
                 (d.superalko_price_max / 1000) * rp.amount_standard as superalko_price_max,
 
+                COALESCE(d.kcal, 0) * n.amount AS kcal,
+                COALESCE(d.sugar, 0) * n.amount AS sugar,
+                COALESCE(d.carbohydrates, 0) * n.amount AS carbohydrates,
+
                 d.alko_product_count > 0 AS apc,
-                d.superalko_product_count > 0 AS sapc
+                d.superalko_product_count > 0 AS sapc,
+
+                rp.unit = 'kpl' AND d.piece_volume_ml IS NULL AS unknown_piece_volume
             FROM recipe_parts rp
             INNER JOIN drink_incredients d ON d.id = rp.incredient_id
+            CROSS JOIN LATERAL (
+                SELECT COALESCE(CASE
+                    WHEN d.unit != 'kpl' THEN rp.amount_standard / 100
+                    WHEN rp.unit = 'kpl' THEN rp.amount
+                    ELSE rp.amount_standard / NULLIF(d.piece_volume_ml, 0)
+                END, 0) AS amount
+            ) n
             WHERE rp.recipe_id = $1
-            GROUP BY (rp.recipe_id, d.id, rp.amount_standard, d.alko_product_count, d.superalko_product_count, d.abv_min, d.alko_price_min, d.superalko_price_min, d.abv_max, d.alko_price_max, d.superalko_price_max, d.alko_price_average, d.superalko_price_average)
+            GROUP BY (rp.recipe_id, d.id, rp.amount_standard, d.alko_product_count, d.superalko_product_count, d.abv_min, d.alko_price_min, d.superalko_price_min, d.abv_max, d.alko_price_max, d.superalko_price_max, d.alko_price_average, d.superalko_price_average, d.kcal, d.sugar, d.carbohydrates, n.amount, rp.unit, d.piece_volume_ml)
         ) e1;
     ")
     .bind(recipe_id)
-    .fetch_optional(&*pool).await.map_err(|e| QueryError::from(e).into())?;
+    .bind(ETHANOL_DENSITY * ETHANOL_KCAL_PER_G)
+    .bind(STANDARD_SERVING_ML)
+    .fetch_optional(executor).await.map_err(|e| QueryError::from(e).into())?;
 
     match data {
         Some(data) => Ok(data),
@@ -600,13 +1370,15 @@ pub async fn calculate_recipe_cached_data(
     }
 }
 
-pub async fn update_recipe_cached_data(
-    recipe_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
-    let data = calculate_recipe_cached_data(recipe_id, pool).await?;
+pub async fn update_recipe_cached_data<'a, A>(recipe_id: i32, conn: A) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
 
-    sqlx::query(
+    let data = calculate_recipe_cached_data(recipe_id, &mut *tr).await?;
+
+    let ids: Vec<i32> = sqlx::query_scalar(
         "
         UPDATE drink_recipes SET
         abv_min = $1, 
@@ -626,8 +1398,13 @@ pub async fn update_recipe_cached_data(
         alko_aer = $15,
         superalko_aer = $16,
         available_alko = $17,
-        available_superalko = $18
+        available_superalko = $18,
+        kcal = $20 / servings,
+        sugar = $21 / servings,
+        carbohydrates = $22 / servings,
+        unknown_piece_volume = $23
         WHERE recipe_id = $19
+        RETURNING id
     ",
     )
     .bind(data.abv_min)
@@ -649,18 +1426,27 @@ pub async fn update_recipe_cached_data(
     .bind(data.available_alko)
     .bind(data.available_superalko)
     .bind(recipe_id)
-    .execute(&*pool)
+    .bind(data.kcal)
+    .bind(data.sugar)
+    .bind(data.carbohydrates)
+    .bind(data.unknown_piece_volume)
+    .fetch_all(&mut *tr)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
+    for id in ids {
+        update_recipe_dilution(id, &mut *tr).await?;
+    }
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
     Ok(())
 }
 
-pub async fn is_favorite(
-    id: i32,
-    user_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<bool, potion::Error> {
+pub async fn is_favorite<'e, E>(id: i32, user_id: i32, executor: E) -> Result<bool, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let result: Option<(i32,)> = sqlx::query_as(
         "
         SELECT drink_id FROM user_favorites WHERE drink_id = $1 AND user_id = $2
@@ -668,67 +1454,75 @@ pub async fn is_favorite(
     )
     .bind(id)
     .bind(user_id)
-    .fetch_optional(&*pool)
+    .fetch_optional(executor)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
     Ok(result.is_some())
 }
 
-pub async fn fetch_favorites(
+pub async fn fetch_favorites<'e, E>(
     user_id: i32,
     offset: i64,
-    pool: &Pool<Postgres>,
-) -> Result<PageContext<RecipeRowPartial>, potion::Error> {
+    executor: E,
+) -> Result<PageContext<RecipeRowPartial>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let rows: Vec<RecipeRowPartial> = sqlx::query_as("
-        SELECT r.*, COUNT(rr) OVER() FROM user_favorites f LEFT JOIN drink_recipes r ON r.id = f.drink_id LEFT JOIN drink_recipes rr ON rr.id = r.id WHERE f.user_id = $1 LIMIT $2 OFFSET $3
+        SELECT r.*, COUNT(*) OVER() FROM user_favorites f INNER JOIN drink_recipes r ON r.id = f.drink_id WHERE f.user_id = $1 ORDER BY r.name LIMIT $2 OFFSET $3
     ")
         .bind(user_id)
         .bind(RECIPE_COUNT_PER_PAGE)
         .bind(offset)
-        .fetch_all(pool).await.map_err(|e| QueryError::from(e).into())?;
+        .fetch_all(executor).await.map_err(|e| QueryError::from(e).into())?;
 
     let total_count = *&rows.get(0).map(|p| p.count).unwrap_or(0);
-    let page = PageContext::from_rows(rows, total_count, PRODUCT_COUNT_PER_PAGE, offset);
+    let page = PageContext::from_rows(rows, total_count, RECIPE_COUNT_PER_PAGE, offset);
 
     Ok(page)
 }
 
-pub async fn add_to_favorites(
-    id: i32,
-    user_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+pub async fn add_to_favorites<'a, A>(id: i32, user_id: i32, conn: A) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     let result = sqlx::query("INSERT INTO user_favorites (user_id, drink_id) VALUES ($1, $2) ON CONFLICT DO NOTHING RETURNING *;")
         .bind(user_id)
         .bind(id)
-        .execute(pool).await.map_err(|e| QueryError::from(e).into())?;
+        .execute(&mut *tr).await.map_err(|e| QueryError::from(e).into())?;
 
     if result.rows_affected() <= 0 {
-        return Err(HtmlError::InvalidRequest
-            .new("Recipe is already in favorites")
-            .into());
+        return Err(SdkError::conflict("Recipe is already in favorites").into());
     }
 
     sqlx::query("UPDATE drink_recipes SET favorite_count = favorite_count + 1  WHERE id = $1;")
-        .bind(user_id)
         .bind(id)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
     Ok(())
 }
 
-pub async fn remove_from_favorites(
+pub async fn remove_from_favorites<'a, A>(
     id: i32,
     user_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     let result = sqlx::query("DELETE FROM user_favorites WHERE user_id = $1 AND drink_id = $2")
         .bind(user_id)
         .bind(id)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
@@ -739,11 +1533,12 @@ pub async fn remove_from_favorites(
     }
 
     sqlx::query("UPDATE drink_recipes SET favorite_count = favorite_count - 1  WHERE id = $1;")
-        .bind(user_id)
         .bind(id)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
     Ok(())
 }
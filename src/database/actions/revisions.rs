@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{Acquire, Postgres};
+
+use crate::{
+    error::{QueryError, SdkError},
+    schema::{
+        NewRecipePart, RecipeRevision, RecipeTag, RecipeType, RevisionDiff, RevisionPart, UnitType,
+    },
+};
+
+use super::{link_tag, update_recipe_tag_list, write_recipe_parts};
+
+#[derive(sqlx::FromRow)]
+struct RevisionRow {
+    id: i32,
+    recipe_id: i32,
+    revision: i32,
+    author_id: Option<i32>,
+    created_at: NaiveDateTime,
+    name: String,
+    r#type: RecipeType,
+    info: String,
+    tag_ids: Vec<i32>,
+}
+
+#[derive(sqlx::FromRow)]
+struct RevisionPartRow {
+    revision_id: i32,
+    incredient_id: i32,
+    name: String,
+    amount: f64,
+    unit: UnitType,
+}
+
+/// Revisions of a recipe, or only `revision`, newest first
+async fn fetch_revisions(
+    recipe_id: i32,
+    revision: Option<i32>,
+    tr: &mut sqlx::Transaction<'_, Postgres>,
+) -> Result<Vec<RecipeRevision>, potion::Error> {
+    let rows: Vec<RevisionRow> = sqlx::query_as(
+        "
+        SELECT * FROM recipe_revisions
+        WHERE recipe_id = $1 AND ($2::INTEGER IS NULL OR revision = $2)
+        ORDER BY revision DESC
+    ",
+    )
+    .bind(recipe_id)
+    .bind(revision)
+    .fetch_all(&mut **tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let ids: Vec<i32> = rows.iter().map(|row| row.id).collect();
+    let parts: Vec<RevisionPartRow> = sqlx::query_as(
+        "
+        SELECT p.revision_id, p.incredient_id, d.name, p.amount, p.unit
+        FROM recipe_revision_parts p
+        INNER JOIN drink_incredients d ON d.id = p.incredient_id
+        WHERE p.revision_id = ANY($1)
+        ORDER BY d.name
+    ",
+    )
+    .bind(&ids)
+    .fetch_all(&mut **tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let tag_ids: Vec<i32> = rows.iter().flat_map(|row| row.tag_ids.clone()).collect();
+    let tags: Vec<RecipeTag> = sqlx::query_as("SELECT * FROM recipe_tags WHERE id = ANY($1)")
+        .bind(&tag_ids)
+        .fetch_all(&mut **tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    let mut parts_by_revision: HashMap<i32, Vec<RevisionPart>> = HashMap::new();
+    parts.into_iter().for_each(|part| {
+        parts_by_revision
+            .entry(part.revision_id)
+            .or_default()
+            .push(RevisionPart {
+                incredient_id: part.incredient_id,
+                name: part.name,
+                amount: part.amount,
+                unit: part.unit,
+            })
+    });
+
+    Ok(rows
+        .into_iter()
+        .map(|row| RecipeRevision {
+            id: row.id,
+            recipe_id: row.recipe_id,
+            revision: row.revision,
+            author_id: row.author_id,
+            created_at: row.created_at.and_utc(),
+            name: row.name,
+            r#type: row.r#type,
+            info: row.info,
+            parts: parts_by_revision.remove(&row.id).unwrap_or_default(),
+            // Deleted tags are left out
+            tags: tags
+                .iter()
+                .filter(|tag| row.tag_ids.contains(&tag.id))
+                .cloned()
+                .collect(),
+        })
+        .collect())
+}
+
+/// Current state of a recipe in the shape of a revision, with `revision` 0
+async fn current_state(
+    recipe_id: i32,
+    tr: &mut sqlx::Transaction<'_, Postgres>,
+) -> Result<Option<RecipeRevision>, potion::Error> {
+    let row: Option<(String, RecipeType, String)> =
+        sqlx::query_as("SELECT name, type, info FROM drink_recipes WHERE id = $1")
+            .bind(recipe_id)
+            .fetch_optional(&mut **tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+    let Some((name, r#type, info)) = row else {
+        return Ok(None);
+    };
+
+    let parts: Vec<RevisionPart> = sqlx::query_as(
+        "
+        SELECT rp.incredient_id, d.name, rp.amount, rp.unit
+        FROM drink_recipes r
+        INNER JOIN recipe_parts rp ON rp.recipe_id = r.recipe_id
+        INNER JOIN drink_incredients d ON d.id = rp.incredient_id
+        WHERE r.id = $1
+        ORDER BY d.name
+    ",
+    )
+    .bind(recipe_id)
+    .fetch_all(&mut **tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let tags: Vec<RecipeTag> = sqlx::query_as(
+        "
+        SELECT t.* FROM recipe_tags t
+        INNER JOIN recipe_tags_map m ON m.tag_id = t.id
+        WHERE m.recipe_id = $1
+        ORDER BY t.id
+    ",
+    )
+    .bind(recipe_id)
+    .fetch_all(&mut **tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(Some(RecipeRevision {
+        id: 0,
+        recipe_id,
+        revision: 0,
+        author_id: None,
+        created_at: Utc::now(),
+        name,
+        r#type,
+        info,
+        parts,
+        tags,
+    }))
+}
+
+/// Records the current name, type, info, parts and tags of a recipe as a new revision.
+/// Nothing is recorded if they are the same as in the latest revision.
+/// Returns the number of the new revision.
+pub async fn record_recipe_revision<'a, A>(
+    recipe_id: i32,
+    author_id: i32,
+    conn: A,
+) -> Result<Option<i32>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let Some(current) = current_state(recipe_id, &mut tr).await? else {
+        return Err(SdkError::not_found("Recipe doesn't exists").into());
+    };
+
+    let latest: Option<(i32,)> = sqlx::query_as(
+        "SELECT revision FROM recipe_revisions WHERE recipe_id = $1 ORDER BY revision DESC LIMIT 1",
+    )
+    .bind(recipe_id)
+    .fetch_optional(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    if let Some((latest,)) = latest {
+        let previous = fetch_revisions(recipe_id, Some(latest), &mut tr).await?;
+        if previous
+            .first()
+            .is_some_and(|previous| previous.same_content(&current))
+        {
+            return Ok(None);
+        }
+    }
+    let revision = latest.map(|(latest,)| latest).unwrap_or(0) + 1;
+
+    let (id,): (i32,) = sqlx::query_as(
+        "
+        INSERT INTO recipe_revisions (recipe_id, revision, author_id, name, type, info, tag_ids)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id
+    ",
+    )
+    .bind(recipe_id)
+    .bind(revision)
+    .bind(author_id)
+    .bind(&current.name)
+    .bind(&current.r#type)
+    .bind(&current.info)
+    .bind(current.tags.iter().map(|tag| tag.id).collect::<Vec<i32>>())
+    .fetch_one(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    sqlx::query(
+        "
+        INSERT INTO recipe_revision_parts (revision_id, incredient_id, amount, unit)
+        SELECT $1, rp.incredient_id, rp.amount, rp.unit
+        FROM drink_recipes r
+        INNER JOIN recipe_parts rp ON rp.recipe_id = r.recipe_id
+        WHERE r.id = $2
+    ",
+    )
+    .bind(id)
+    .bind(recipe_id)
+    .execute(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(Some(revision))
+}
+
+/// Records a revision of every recipe using the parts `recipe_id` of `recipes`
+pub(crate) async fn record_parts_revisions<'a, A>(
+    recipe_id: i32,
+    author_id: i32,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let ids: Vec<i32> = sqlx::query_scalar("SELECT id FROM drink_recipes WHERE recipe_id = $1")
+        .bind(recipe_id)
+        .fetch_all(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    for id in ids {
+        record_recipe_revision(id, author_id, &mut *tr).await?;
+    }
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+/// Every revision of a recipe, newest first
+pub async fn list_recipe_revisions<'a, A>(
+    recipe_id: i32,
+    conn: A,
+) -> Result<Vec<RecipeRevision>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let revisions = fetch_revisions(recipe_id, None, &mut tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(revisions)
+}
+
+pub async fn get_recipe_revision<'a, A>(
+    recipe_id: i32,
+    revision: i32,
+    conn: A,
+) -> Result<Option<RecipeRevision>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let revision = fetch_revisions(recipe_id, Some(revision), &mut tr)
+        .await?
+        .into_iter()
+        .next();
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(revision)
+}
+
+pub async fn diff_recipe_revisions<'a, A>(
+    recipe_id: i32,
+    from: i32,
+    to: i32,
+    conn: A,
+) -> Result<RevisionDiff, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let mut revisions = vec![];
+    for revision in [from, to] {
+        let revision = fetch_revisions(recipe_id, Some(revision), &mut tr)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| SdkError::not_found("Revision doesn't exist").into())?;
+        revisions.push(revision);
+    }
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(RevisionDiff::new(&revisions[0], &revisions[1]))
+}
+
+/// Restores the name, type, info, parts and tags of an earlier revision and recomputes the cached data.
+/// The rollback is recorded as a new revision, so history is never lost.
+/// Recipes sharing the parts get a new revision as well.
+/// Returns the number of the new revision, or `None` if the recipe was already the same as `revision`.
+pub async fn rollback_recipe<'a, A>(
+    recipe_id: i32,
+    revision: i32,
+    author_id: i32,
+    conn: A,
+) -> Result<Option<i32>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let target = fetch_revisions(recipe_id, Some(revision), &mut tr)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| SdkError::not_found("Revision doesn't exist").into())?;
+
+    let (parts_id,): (i32,) = sqlx::query_as(
+        "UPDATE drink_recipes SET name = $2, type = $3, info = $4 WHERE id = $1 RETURNING recipe_id",
+    )
+    .bind(recipe_id)
+    .bind(&target.name)
+    .bind(&target.r#type)
+    .bind(&target.info)
+    .fetch_one(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let parts: Vec<NewRecipePart> = target
+        .parts
+        .iter()
+        .map(|part| NewRecipePart {
+            incredient_id: part.incredient_id,
+            amount: part.amount,
+            unit: part.unit.clone(),
+        })
+        .collect();
+    write_recipe_parts(parts_id, &parts, &mut *tr).await?;
+
+    sqlx::query("DELETE FROM recipe_tags_map WHERE recipe_id = $1")
+        .bind(recipe_id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+    for tag in target.tags.iter() {
+        link_tag(recipe_id, tag.id, &mut *tr).await?;
+    }
+    update_recipe_tag_list(recipe_id, &mut *tr).await?;
+
+    let revision = record_recipe_revision(recipe_id, author_id, &mut *tr).await?;
+    // Other recipes sharing the parts changed too
+    record_parts_revisions(parts_id, author_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(revision)
+}
@@ -0,0 +1,164 @@
+use sqlx::{Acquire, FromRow, Postgres};
+
+use crate::{
+    error::{QueryError, SdkError},
+    schema::{RecipeScale, ScaledRecipe, ScaledRecipePart, UnitType},
+    STANDARD_SERVING_ML,
+};
+
+#[derive(FromRow)]
+struct ScalingPart {
+    incredient_id: i32,
+    name: String,
+    amount: f64,
+    unit: UnitType,
+    amount_standard: f64,
+    abv_min: f64,
+    abv_max: f64,
+    alko_price_min: f64,
+    alko_price_average: f64,
+    superalko_price_min: f64,
+    superalko_price_average: f64,
+}
+
+/// Sums of a recipe's parts, computed the same way as in `calculate_recipe_cached_data`
+#[derive(Default)]
+struct PartTotals {
+    volume: f64,
+    ethanol_min: f64,
+    ethanol_max: f64,
+    alko_price_min: f64,
+    alko_price_average: f64,
+    superalko_price_min: f64,
+    superalko_price_average: f64,
+}
+
+impl PartTotals {
+    fn add(&mut self, part: &ScalingPart, amount_standard: f64) {
+        self.volume += amount_standard;
+        self.ethanol_min += part.abv_min / 100. * amount_standard;
+        self.ethanol_max += part.abv_max / 100. * amount_standard;
+        self.alko_price_min += part.alko_price_min / 1000. * amount_standard;
+        self.alko_price_average += part.alko_price_average / 1000. * amount_standard;
+        self.superalko_price_min += part.superalko_price_min / 1000. * amount_standard;
+        self.superalko_price_average += part.superalko_price_average / 1000. * amount_standard;
+    }
+
+    fn standard_servings(&self) -> f64 {
+        (self.ethanol_min + self.ethanol_max) / 2. / STANDARD_SERVING_ML
+    }
+}
+
+/// Rescales the parts of a recipe, rounding every amount to a bar-friendly increment of its unit.
+/// Volume, ABV, price and servings are recomputed from the rounded amounts.
+pub async fn scale_recipe<'a, A>(
+    recipe_id: i32,
+    scale: RecipeScale,
+    conn: A,
+) -> Result<ScaledRecipe, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let parts_id: Option<i32> =
+        sqlx::query_scalar("SELECT recipe_id FROM drink_recipes WHERE id = $1")
+            .bind(recipe_id)
+            .fetch_optional(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+
+    let Some(parts_id) = parts_id else {
+        return Err(SdkError::not_found("Recipe doesn't exist").into());
+    };
+
+    let parts: Vec<ScalingPart> = sqlx::query_as(
+        "
+        SELECT rp.incredient_id, d.name, rp.amount, rp.unit, rp.amount_standard,
+            d.abv_min, d.abv_max, d.alko_price_min, d.alko_price_average,
+            d.superalko_price_min, d.superalko_price_average
+        FROM recipe_parts rp
+        INNER JOIN drink_incredients d ON d.id = rp.incredient_id
+        WHERE rp.recipe_id = $1
+        ORDER BY rp.amount_standard DESC, d.name
+    ",
+    )
+    .bind(parts_id)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    let mut original = PartTotals::default();
+    parts
+        .iter()
+        .for_each(|part| original.add(part, part.amount_standard));
+
+    let factor = match scale {
+        RecipeScale::Factor(factor) => factor,
+        RecipeScale::Volume(volume) if original.volume > 0. => volume / original.volume,
+        RecipeScale::Volume(_) => {
+            return Err(SdkError::validation("Recipe has no measurable volume").into())
+        }
+        RecipeScale::Servings(servings) if original.standard_servings() > 0. => {
+            servings / original.standard_servings()
+        }
+        RecipeScale::Servings(_) => {
+            return Err(SdkError::validation("Recipe doesn't contain alcohol").into())
+        }
+    };
+
+    if !factor.is_finite() || factor <= 0. {
+        return Err(SdkError::validation("Scale must be positive").into());
+    }
+
+    let mut scaled = PartTotals::default();
+    let parts: Vec<ScaledRecipePart> = parts
+        .into_iter()
+        .map(|part| {
+            let amount = part.unit.round(part.amount * factor);
+            let amount_standard = match part.amount > 0. {
+                true => part.amount_standard * amount / part.amount,
+                false => part.amount_standard * factor,
+            };
+            scaled.add(&part, amount_standard);
+
+            ScaledRecipePart {
+                incredient_id: part.incredient_id,
+                name: part.name,
+                amount,
+                unit: part.unit,
+                amount_standard,
+            }
+        })
+        .collect();
+
+    let standard_servings = scaled.standard_servings();
+    let volume = match scaled.volume > 0. {
+        true => scaled.volume,
+        false => 1.,
+    };
+    let servings = match standard_servings > 0. {
+        true => standard_servings,
+        false => 1.,
+    };
+    let alko_price_average = (scaled.alko_price_min + scaled.alko_price_average) / 2.;
+    let superalko_price_average =
+        (scaled.superalko_price_min + scaled.superalko_price_average) / 2.;
+
+    Ok(ScaledRecipe {
+        recipe_id,
+        factor,
+        parts,
+        total_volume: scaled.volume,
+        standard_servings,
+        abv_average: (scaled.ethanol_min + scaled.ethanol_max) / 2. / volume * 100.,
+        abv_min: scaled.ethanol_min / volume * 100.,
+        abv_max: scaled.ethanol_max / volume * 100.,
+        alko_price_average,
+        superalko_price_average,
+        alko_price_per_serving: alko_price_average / servings,
+        superalko_price_per_serving: superalko_price_average / servings,
+    })
+}
@@ -0,0 +1,85 @@
+use sqlx::{Acquire, Executor, Postgres, QueryBuilder};
+
+use crate::{
+    error::QueryError,
+    search::{SearchEntity, SearchHit, TextSearch, FUZZY_THRESHOLD},
+};
+
+/// Sets the threshold of the `<%` operator used by `TextSearch` for the rest of the transaction
+pub(crate) async fn set_search_threshold<'e, E>(executor: E) -> Result<(), potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query(&format!(
+        "SET LOCAL pg_trgm.word_similarity_threshold = {FUZZY_THRESHOLD}"
+    ))
+    .execute(executor)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+/// Ranked search over products, incredients and recipes at once.
+/// Searches every entity if `entities` is empty.
+pub async fn search_all<'a, A>(
+    query: &str,
+    entities: &[SearchEntity],
+    limit: i64,
+    conn: A,
+) -> Result<Vec<SearchHit>, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+    set_search_threshold(&mut *tr).await?;
+
+    let Some(search) = TextSearch::new(query) else {
+        return Ok(vec![]);
+    };
+
+    let entities = match entities.is_empty() {
+        true => SearchEntity::ALL,
+        false => entities,
+    };
+
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("");
+
+    for (i, entity) in entities.iter().enumerate() {
+        let (table, alias, condition) = match entity {
+            SearchEntity::Product => ("products", "p", ""),
+            SearchEntity::Incredient => ("drink_incredients", "d", ""),
+            SearchEntity::Recipe => ("drink_recipes", "r", " AND r.type != 'generated'"),
+        };
+        let column = format!("{alias}.name");
+
+        if i > 0 {
+            builder.push(" UNION ALL ");
+        }
+
+        builder.push(format!(
+            "(SELECT '{}' AS kind, {alias}.id, {column}, ",
+            entity.as_str()
+        ));
+        search.push_rank(&column, &mut builder);
+        builder.push(" AS score, ");
+        search.push_highlight(&column, &mut builder);
+        builder.push(format!(" AS highlight FROM {table} {alias} WHERE "));
+        search.push_match(&column, &mut builder);
+        builder.push(condition).push(")");
+    }
+
+    builder
+        .push(" ORDER BY score DESC, name LIMIT ")
+        .push_bind(limit);
+
+    let rows: Vec<SearchHit> = builder
+        .build_query_as()
+        .fetch_all(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(rows)
+}
@@ -0,0 +1,192 @@
+use std::collections::{BTreeMap, HashMap};
+
+use sqlx::{Acquire, FromRow, Postgres};
+
+use crate::{
+    error::QueryError,
+    schema::{
+        IncredientName, Product, RecipeServings, Retailer, RetailerShoppingList, ShoppingList,
+        ShoppingListItem, UnitType, UnmeasuredIncredient, Uuid,
+    },
+};
+
+use super::{cabinet_stock, INCREDIENT_PRODUCTS_CTE};
+
+#[derive(FromRow)]
+struct ShoppingPart {
+    drink_id: i32,
+    incredient_id: i32,
+    incredient_name: String,
+    amount: f64,
+    amount_standard: f64,
+    unit: UnitType,
+}
+
+#[derive(FromRow)]
+struct CandidateProduct {
+    incredient_id: i32,
+    #[sqlx(flatten)]
+    product: Product,
+}
+
+/// Aggregates the volume of every incredient needed for the recipes, subtracts what the cabinet
+/// already holds, and picks the cheapest products to cover the rest.
+/// Only products from `retailers` are considered, or from any retailer if it's empty.
+pub async fn generate_shopping_list<'a, A>(
+    recipes: &[RecipeServings],
+    cabinet_id: Option<i32>,
+    retailers: &[Retailer],
+    conn: A,
+) -> Result<ShoppingList, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let drink_ids: Vec<i32> = recipes.iter().map(|r| r.recipe_id).collect();
+    let parts: Vec<ShoppingPart> = sqlx::query_as(
+        "
+        SELECT r.id AS drink_id, rp.incredient_id, d.name AS incredient_name, rp.amount, rp.amount_standard, rp.unit
+        FROM drink_recipes r
+        INNER JOIN recipe_parts rp ON rp.recipe_id = r.recipe_id
+        INNER JOIN drink_incredients d ON d.id = rp.incredient_id
+        WHERE r.id = ANY($1)
+    ",
+    )
+    .bind(&drink_ids)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let mut names: HashMap<Uuid, String> = HashMap::new();
+    let mut required: BTreeMap<Uuid, f64> = BTreeMap::new();
+    let mut unmeasured: BTreeMap<(Uuid, UnitType), f64> = BTreeMap::new();
+
+    for part in parts {
+        let servings: f64 = recipes
+            .iter()
+            .filter(|r| r.recipe_id == part.drink_id)
+            .map(|r| r.servings)
+            .sum();
+
+        names.insert(part.incredient_id, part.incredient_name);
+        if part.amount_standard > 0. {
+            *required.entry(part.incredient_id).or_default() += part.amount_standard * servings;
+        } else {
+            *unmeasured
+                .entry((part.incredient_id, part.unit))
+                .or_default() += part.amount * servings;
+        }
+    }
+
+    let stock = match cabinet_id {
+        Some(cabinet_id) => cabinet_stock(cabinet_id, &mut tr).await?,
+        None => HashMap::new(),
+    };
+
+    let mut list = ShoppingList::default();
+
+    // Untracked cabinet items are assumed to be enough
+    required.retain(|incredient_id, required_ml| {
+        let held_ml = match stock.get(incredient_id) {
+            Some(Some(amounts)) => amounts
+                .iter()
+                .filter_map(|(value, unit)| unit.convert(*value, UnitType::Ml).ok())
+                .map(|(_, value)| value)
+                .sum(),
+            Some(None) => f64::INFINITY,
+            None => 0.,
+        };
+
+        *required_ml -= held_ml;
+        if *required_ml <= 0. {
+            list.in_cabinet.push(IncredientName {
+                id: *incredient_id,
+                name: names[incredient_id].clone(),
+            });
+            return false;
+        }
+        true
+    });
+
+    list.unmeasured = unmeasured
+        .into_iter()
+        .filter(|((incredient_id, _), _)| !stock.contains_key(incredient_id))
+        .map(|((incredient_id, unit), amount)| UnmeasuredIncredient {
+            incredient_id,
+            incredient_name: names[&incredient_id].clone(),
+            amount,
+            unit,
+        })
+        .collect();
+
+    let incredient_ids: Vec<i32> = required.keys().copied().collect();
+    let candidates: Vec<CandidateProduct> = sqlx::query_as(&format!(
+        "
+        WITH {INCREDIENT_PRODUCTS_CTE}
+        SELECT ip.incredient_id, p.*
+        FROM incredient_products ip
+        INNER JOIN products p ON p.id = ip.product_id
+        WHERE ip.incredient_id = ANY($1) AND p.volume > 0 AND p.price > 0
+    "
+    ))
+    .bind(&incredient_ids)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    let mut by_retailer: BTreeMap<Retailer, Vec<ShoppingListItem>> = BTreeMap::new();
+
+    for (incredient_id, required_ml) in required {
+        let best = candidates
+            .iter()
+            .filter(|c| c.incredient_id == incredient_id)
+            .filter(|c| retailers.is_empty() || retailers.contains(&c.product.retailer))
+            .map(|c| {
+                let bottle_ml = c.product.volume * 1000.;
+                let bottles = (required_ml / bottle_ml).ceil() as i32;
+                let total_price = f64::from(bottles) * c.product.price;
+                let leftover_ml = f64::from(bottles) * bottle_ml - required_ml;
+                (c, bottles, total_price, leftover_ml)
+            })
+            .min_by(|a, b| a.2.total_cmp(&b.2).then(a.3.total_cmp(&b.3)));
+
+        match best {
+            Some((candidate, bottles, total_price, leftover_ml)) => by_retailer
+                .entry(candidate.product.retailer.clone())
+                .or_default()
+                .push(ShoppingListItem {
+                    incredient_id,
+                    incredient_name: names[&incredient_id].clone(),
+                    product: candidate.product.clone(),
+                    required_ml,
+                    bottles,
+                    total_price,
+                    leftover_ml,
+                }),
+            None => list.unavailable.push(IncredientName {
+                id: incredient_id,
+                name: names[&incredient_id].clone(),
+            }),
+        }
+    }
+
+    list.retailers = by_retailer
+        .into_iter()
+        .map(|(retailer, items)| RetailerShoppingList {
+            total_price: items
+                .iter()
+                .fold(0., |total, item| total + item.total_price),
+            retailer,
+            items,
+        })
+        .collect();
+    list.total_price = list
+        .retailers
+        .iter()
+        .fold(0., |total, r| total + r.total_price);
+
+    Ok(list)
+}
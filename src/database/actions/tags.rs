@@ -1,74 +1,115 @@
 use crate::{
-    error::QueryError,
+    error::{QueryError, SdkError},
     schema::{LinkedRecipeTag, RecipeTag},
 };
 
-use potion::HtmlError;
-use sqlx::{Pool, Postgres};
+use sqlx::{Acquire, Executor, Postgres};
 
-pub async fn create_tag(name: &str, pool: &Pool<Postgres>) -> Result<i32, potion::Error> {
+use super::record_recipe_revision;
+
+pub async fn create_tag<'e, E>(name: &str, executor: E) -> Result<i32, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let id: (i32,) = sqlx::query_as(
         "INSERT INTO recipe_tags (name) VALUES ($1) ON CONFLICT DO NOTHING RETURNING *",
     )
     .bind(name)
-    .fetch_one(pool)
+    .fetch_one(executor)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
     Ok(id.0)
 }
 
-pub async fn get_tag(id: i32, pool: &Pool<Postgres>) -> Result<Option<RecipeTag>, potion::Error> {
+pub async fn get_tag<'e, E>(id: i32, executor: E) -> Result<Option<RecipeTag>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let list: Option<RecipeTag> = sqlx::query_as("SELECT * FROM recipe_tags WHERE id = $1")
         .bind(id)
-        .fetch_optional(pool)
+        .fetch_optional(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(list)
 }
 
-pub async fn find_tag(name: &str, pool: &Pool<Postgres>) -> Result<Option<i32>, potion::Error> {
+pub async fn find_tag<'e, E>(name: &str, executor: E) -> Result<Option<i32>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let list: Option<(i32,)> = sqlx::query_as("SELECT id FROM recipe_tags WHERE name = $1")
         .bind(name)
-        .fetch_optional(pool)
+        .fetch_optional(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(list.map(|tag| tag.0))
 }
 
-pub async fn list_tags(pool: &Pool<Postgres>) -> Result<Vec<RecipeTag>, potion::Error> {
+pub async fn list_tags<'e, E>(executor: E) -> Result<Vec<RecipeTag>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let list: Vec<RecipeTag> = sqlx::query_as("SELECT * FROM recipe_tags")
-        .fetch_all(pool)
+        .fetch_all(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(list)
 }
 
-pub async fn list_recipe_tags(
-    pool: &Pool<Postgres>,
+pub async fn list_recipe_tags<'e, E>(
+    executor: E,
     recipe_id: i32,
-) -> Result<Vec<LinkedRecipeTag>, potion::Error> {
+) -> Result<Vec<LinkedRecipeTag>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let list: Vec<LinkedRecipeTag> =
         sqlx::query_as("SELECT * FROM recipe_tags_map WHERE recipe_id = $1")
             .bind(recipe_id)
-            .fetch_all(pool)
+            .fetch_all(executor)
             .await
             .map_err(|e| QueryError::from(e).into())?;
 
     Ok(list)
 }
 
-pub async fn add_tag_to_recipe(
+pub async fn add_tag_to_recipe<'a, A>(
     recipe_id: i32,
     tag_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
-    let tag = get_tag(tag_id, pool).await?;
+    author_id: i32,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    link_tag(recipe_id, tag_id, &mut *tr).await?;
+    record_recipe_revision(recipe_id, author_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+/// Links a tag to a recipe without recording a revision
+pub(crate) async fn link_tag<'a, A>(
+    recipe_id: i32,
+    tag_id: i32,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let tag = get_tag(tag_id, &mut *tr).await?;
     if tag.is_none() {
-        return Err(HtmlError::InvalidRequest.new("Tag doesn't exists"));
+        return Err(SdkError::not_found("Tag doesn't exists").into());
     }
     let tag = tag.unwrap();
 
@@ -76,49 +117,64 @@ pub async fn add_tag_to_recipe(
             .bind(recipe_id)
             .bind(tag_id)
             .bind(tag.name)
-            .execute(pool)
+            .execute(&mut *tr)
             .await
             .map_err(|e| QueryError::from(e).into())?;
 
-    update_recipe_tag_list(recipe_id, pool).await?;
+    update_recipe_tag_list(recipe_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn remove_tag_from_recipe(
+pub async fn remove_tag_from_recipe<'a, A>(
     recipe_id: i32,
     tag_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
+    author_id: i32,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
     sqlx::query("DELETE FROM recipe_tags_map WHERE recipe_id = $1 AND tag_id = $2")
         .bind(recipe_id)
         .bind(tag_id)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
-    update_recipe_tag_list(recipe_id, pool).await?;
+    update_recipe_tag_list(recipe_id, &mut *tr).await?;
+    record_recipe_revision(recipe_id, author_id, &mut *tr).await?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
 
     Ok(())
 }
 
-pub async fn update_recipe_tag_list(
-    recipe_id: i32,
-    pool: &Pool<Postgres>,
-) -> Result<(), potion::Error> {
-    let tags = list_recipe_tags(pool, recipe_id).await?;
+pub async fn update_recipe_tag_list<'a, A>(recipe_id: i32, conn: A) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let tags = list_recipe_tags(&mut *tr, recipe_id).await?;
     let tag_list = tags
         .iter()
         .map(|tag| tag.tag_name.to_owned())
         .collect::<Vec<String>>()
         .join("|");
 
-    sqlx::query("UPDATE drink_recipes SET tag_list = $2 WHERE recipe_id = $1")
+    sqlx::query("UPDATE drink_recipes SET tag_list = $2 WHERE id = $1")
         .bind(recipe_id)
         .bind(tag_list)
-        .execute(pool)
+        .execute(&mut *tr)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
     Ok(())
 }
@@ -1,32 +1,49 @@
+use std::collections::HashSet;
+
+use chrono::Utc;
+
 use crate::{
-    authentication::{cryptography::verify_password, jwt::generate_jwt_session},
-    error::QueryError,
-    schema::User,
+    authentication::{
+        cryptography::{hash_pasword, verify_password},
+        jwt::generate_jwt_session,
+    },
+    error::{QueryError, SdkError},
+    filter::RecipeFilter,
+    schema::{
+        AuthoredContent, AuthoredRevision, Cabinet, CabinetMixer, CabinetProduct, IncredientName,
+        RecipeName, RecipeRating, User, UserDataExport, UserProfile,
+    },
+    BIO_MAX_LENGTH, DELETED_USERNAME, DISPLAY_NAME_MAX_LENGTH,
 };
 
 use potion::HtmlError;
-use sqlx::{Pool, Postgres};
+use sqlx::{Acquire, Executor, Postgres};
 
-pub async fn get_user(
-    pool: &Pool<Postgres>,
-    username: &str,
-) -> Result<Option<User>, potion::Error> {
+use super::{
+    delete_cabinet, export_recipes, list_friend_cabinets, list_own_cabinets,
+    remove_user_from_cabinet, update_recipe_rating,
+};
+
+pub async fn get_user<'e, E>(executor: E, username: &str) -> Result<Option<User>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let row: Option<User> = sqlx::query_as("SELECT * FROM users WHERE username = $1")
         .bind(username)
-        .fetch_optional(&*pool)
+        .fetch_optional(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
     Ok(row)
 }
 
-pub async fn get_user_by_id(
-    pool: &Pool<Postgres>,
-    user_id: i32,
-) -> Result<Option<User>, potion::Error> {
+pub async fn get_user_by_id<'e, E>(executor: E, user_id: i32) -> Result<Option<User>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
     let row: Option<User> = sqlx::query_as("SELECT * FROM users WHERE id = $1")
         .bind(user_id)
-        .fetch_optional(&*pool)
+        .fetch_optional(executor)
         .await
         .map_err(|e| QueryError::from(e).into())?;
 
@@ -34,11 +51,18 @@ pub async fn get_user_by_id(
 }
 
 /// Creates a user with username and password, which is the hashed version of their password
-pub async fn register_user(
+pub async fn register_user<'e, E>(
     username: &str,
     password: &str,
-    pool: &Pool<Postgres>,
-) -> Result<bool, potion::Error> {
+    executor: E,
+) -> Result<bool, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    if username.trim() == DELETED_USERNAME {
+        return Err(SdkError::validation("Username is reserved").into());
+    }
+
     let query = sqlx::query(
         "
         INSERT INTO users (username, password)
@@ -48,27 +72,28 @@ pub async fn register_user(
     )
     .bind(username)
     .bind(password)
-    .execute(&*pool)
+    .execute(executor)
     .await
     .map_err(|e| QueryError::from(e).into())?;
 
     Ok(query.rows_affected() > 0)
 }
 
-pub async fn login_user(
+pub async fn login_user<'e, E>(
     username: &str,
     password: &str,
-    pool: &Pool<Postgres>,
-) -> Result<String, potion::Error> {
-    let user = get_user(pool, username).await?;
+    executor: E,
+) -> Result<String, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let user = get_user(executor, username).await?;
     if user.is_none() {
         return Err(HtmlError::InvalidRequest.new("Invalid credentials"));
     }
 
     let user = user.unwrap();
-    let authenticated = verify_password(password, &user.password)
-        .map_err(|_e| panic!("!"))
-        .unwrap();
+    let authenticated = verify_password(password, &user.password).unwrap_or(false);
     if !authenticated {
         return Err(HtmlError::InvalidRequest.new("Invalid credentials"));
     }
@@ -77,3 +102,443 @@ pub async fn login_user(
 
     Ok(session)
 }
+
+/// Checks the password of a user before changes to the account
+async fn authenticate<'e, E>(
+    user_id: i32,
+    password: &str,
+    executor: E,
+) -> Result<User, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let user = get_user_by_id(executor, user_id)
+        .await?
+        .ok_or_else(|| SdkError::not_found("User doesn't exist").into())?;
+
+    match verify_password(password, &user.password).unwrap_or(false) {
+        true => Ok(user),
+        false => Err(SdkError::unauthorized("Invalid credentials").into()),
+    }
+}
+
+pub async fn change_password<'a, A>(
+    user_id: i32,
+    old_password: &str,
+    new_password: &str,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    if new_password.is_empty() {
+        return Err(SdkError::validation("Password can't be empty").into());
+    }
+
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    authenticate(user_id, old_password, &mut *tr).await?;
+    let hash = hash_pasword(new_password)
+        .map_err(|_| SdkError::internal("Could not hash password").into())?;
+
+    sqlx::query("UPDATE users SET password = $1 WHERE id = $2")
+        .bind(hash)
+        .bind(user_id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+/// Changes the username, also in the access lists of shared cabinets.
+/// Sessions carry the username, so a new session should be generated afterwards.
+pub async fn rename_user<'a, A>(user_id: i32, username: &str, conn: A) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let username = username.trim();
+    if username.is_empty() {
+        return Err(SdkError::validation("Username can't be empty").into());
+    }
+    if username == DELETED_USERNAME {
+        return Err(SdkError::validation("Username is reserved").into());
+    }
+
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let user = get_user_by_id(&mut *tr, user_id)
+        .await?
+        .ok_or_else(|| SdkError::not_found("User doesn't exist").into())?;
+    if user.username == DELETED_USERNAME {
+        return Err(SdkError::validation("The placeholder user can't be renamed").into());
+    }
+    if let Some(user) = get_user(&mut *tr, username).await? {
+        if user.id != user_id {
+            return Err(SdkError::conflict("Username is already taken").into());
+        }
+    }
+
+    sqlx::query("UPDATE users SET username = $1 WHERE id = $2")
+        .bind(username)
+        .bind(user_id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    sqlx::query("UPDATE shared_cabinets SET user_username = $1 WHERE user_id = $2")
+        .bind(username)
+        .bind(user_id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+pub async fn get_user_profile<'e, E>(
+    user_id: i32,
+    executor: E,
+) -> Result<Option<UserProfile>, potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let row: Option<UserProfile> = sqlx::query_as(
+        "
+        SELECT u.id, u.username, u.display_name, u.bio,
+            (SELECT COUNT(*) FROM drink_recipes r WHERE r.author_id = u.id) AS recipe_count,
+            (SELECT COUNT(*) FROM recipe_ratings rr WHERE rr.user_id = u.id) AS rating_count
+        FROM users u
+        WHERE u.id = $1
+    ",
+    )
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(row)
+}
+
+/// Sets the display name and bio of a user. Empty values clear them.
+pub async fn set_user_profile<'e, E>(
+    user_id: i32,
+    display_name: Option<String>,
+    bio: Option<String>,
+    executor: E,
+) -> Result<(), potion::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let display_name = display_name
+        .map(|name| name.trim().to_owned())
+        .filter(|name| !name.is_empty());
+    let bio = bio
+        .map(|bio| bio.trim().to_owned())
+        .filter(|bio| !bio.is_empty());
+
+    if display_name
+        .as_ref()
+        .is_some_and(|name| name.chars().count() > DISPLAY_NAME_MAX_LENGTH)
+    {
+        return Err(SdkError::validation(&format!(
+            "Display name can be at most {DISPLAY_NAME_MAX_LENGTH} characters"
+        ))
+        .into());
+    }
+    if bio
+        .as_ref()
+        .is_some_and(|bio| bio.chars().count() > BIO_MAX_LENGTH)
+    {
+        return Err(SdkError::validation(&format!(
+            "Bio can be at most {BIO_MAX_LENGTH} characters"
+        ))
+        .into());
+    }
+
+    let result = sqlx::query("UPDATE users SET display_name = $1, bio = $2 WHERE id = $3")
+        .bind(display_name)
+        .bind(bio)
+        .bind(user_id)
+        .execute(executor)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    if result.rows_affected() == 0 {
+        return Err(SdkError::not_found("User doesn't exist").into());
+    }
+
+    Ok(())
+}
+
+/// Deletes a user after checking their password. Authored recipes and incredients are handed over
+/// according to `authored`, and revisions they made lose their author. Ratings, favorites,
+/// owned cabinets and everything the user added to shared cabinets are deleted.
+pub async fn delete_user<'a, A>(
+    user_id: i32,
+    password: &str,
+    authored: AuthoredContent,
+    conn: A,
+) -> Result<(), potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let user = authenticate(user_id, password, &mut *tr).await?;
+    if user.username == DELETED_USERNAME {
+        return Err(SdkError::validation("The placeholder user can't be deleted").into());
+    }
+
+    let heir = match authored {
+        AuthoredContent::Reassign(heir) if heir == user_id => {
+            return Err(SdkError::validation("Can't reassign content to the deleted user").into())
+        }
+        AuthoredContent::Reassign(heir) => get_user_by_id(&mut *tr, heir).await?,
+        AuthoredContent::Anonymize => get_user(&mut *tr, DELETED_USERNAME).await?,
+    }
+    .ok_or_else(|| SdkError::not_found("User to reassign content to doesn't exist").into())?;
+
+    // Names are unique per author, so recipes the heir already has a name for get the id appended,
+    // and a number after it while that is taken too
+    let heir_names: Vec<String> =
+        sqlx::query_scalar("SELECT name FROM drink_recipes WHERE author_id = $1")
+            .bind(heir.id)
+            .fetch_all(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+    let mut taken: HashSet<String> = heir_names.into_iter().collect();
+
+    let recipes: Vec<(i32, String)> =
+        sqlx::query_as("SELECT id, name FROM drink_recipes WHERE author_id = $1 ORDER BY id")
+            .bind(user_id)
+            .fetch_all(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+    for (id, name) in recipes {
+        if taken.insert(name.clone()) {
+            continue;
+        }
+
+        let mut renamed = format!("{name} ({id})");
+        let mut n = 2;
+        while taken.contains(&renamed) {
+            renamed = format!("{name} ({id}, {n})");
+            n += 1;
+        }
+
+        sqlx::query("UPDATE drink_recipes SET author_id = $1, name = $2 WHERE id = $3")
+            .bind(heir.id)
+            .bind(&renamed)
+            .bind(id)
+            .execute(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+        taken.insert(renamed);
+    }
+
+    sqlx::query("UPDATE drink_recipes SET author_id = $2 WHERE author_id = $1")
+        .bind(user_id)
+        .bind(heir.id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    sqlx::query("UPDATE drink_incredients SET author_id = $2 WHERE author_id = $1")
+        .bind(user_id)
+        .bind(heir.id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    sqlx::query("UPDATE recipe_revisions SET author_id = NULL WHERE author_id = $1")
+        .bind(user_id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    let rated: Vec<i32> =
+        sqlx::query_scalar("DELETE FROM recipe_ratings WHERE user_id = $1 RETURNING recipe_id")
+            .bind(user_id)
+            .fetch_all(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+    for recipe_id in rated {
+        update_recipe_rating(recipe_id, &mut *tr).await?;
+    }
+
+    sqlx::query(
+        "
+        UPDATE drink_recipes SET favorite_count = favorite_count - 1
+        WHERE id IN (SELECT drink_id FROM user_favorites WHERE user_id = $1)
+    ",
+    )
+    .bind(user_id)
+    .execute(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    sqlx::query("DELETE FROM user_favorites WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    sqlx::query("DELETE FROM user_incredients WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    for cabinet in list_own_cabinets(user_id, &mut *tr).await? {
+        delete_cabinet(cabinet.id, &mut *tr).await?;
+    }
+    for cabinet in list_friend_cabinets(user_id, &mut *tr).await? {
+        remove_user_from_cabinet(cabinet.id, user_id, &mut *tr).await?;
+    }
+
+    sqlx::query(
+        "DELETE FROM queue_drink WHERE queue_id IN (SELECT id FROM drink_randomizer_queue WHERE author = $1)",
+    )
+    .bind(user_id)
+    .execute(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    sqlx::query("DELETE FROM drink_randomizer_queue WHERE author = $1")
+        .bind(user_id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    sqlx::query("DELETE FROM users WHERE id = $1")
+        .bind(user_id)
+        .execute(&mut *tr)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+/// Collects everything stored about a user, see `UserDataExport::to_json`
+pub async fn export_user_data<'a, A>(user_id: i32, conn: A) -> Result<UserDataExport, potion::Error>
+where
+    A: Acquire<'a, Database = Postgres>,
+{
+    let mut tr = conn.begin().await.map_err(|e| QueryError::from(e).into())?;
+
+    let user = get_user_by_id(&mut *tr, user_id)
+        .await?
+        .ok_or_else(|| SdkError::not_found("User doesn't exist").into())?;
+    let profile = get_user_profile(user_id, &mut *tr)
+        .await?
+        .ok_or_else(|| SdkError::not_found("User doesn't exist").into())?;
+
+    let filter = RecipeFilter {
+        author: Some(user_id),
+        include_generated: true,
+        ..Default::default()
+    };
+    let recipes = export_recipes(&filter, &mut *tr).await?;
+
+    let incredients: Vec<IncredientName> =
+        sqlx::query_as("SELECT id, name FROM drink_incredients WHERE author_id = $1 ORDER BY name")
+            .bind(user_id)
+            .fetch_all(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+
+    let revisions: Vec<AuthoredRevision> = sqlx::query_as(
+        "
+        SELECT recipe_id, revision, created_at
+        FROM recipe_revisions
+        WHERE author_id = $1
+        ORDER BY created_at, id
+    ",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let ratings: Vec<RecipeRating> = sqlx::query_as(
+        "
+        SELECT rr.*, u.username
+        FROM recipe_ratings rr
+        INNER JOIN users u ON u.id = rr.user_id
+        WHERE rr.user_id = $1
+        ORDER BY rr.recipe_id
+    ",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let favorites: Vec<RecipeName> = sqlx::query_as(
+        "
+        SELECT r.id, r.name
+        FROM user_favorites f
+        INNER JOIN drink_recipes r ON r.id = f.drink_id
+        WHERE f.user_id = $1
+        ORDER BY r.name
+    ",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let owned_incredients: Vec<IncredientName> = sqlx::query_as(
+        "
+        SELECT d.id, d.name
+        FROM user_incredients ui
+        INNER JOIN drink_incredients d ON d.id = ui.incredient_id
+        WHERE ui.user_id = $1
+        ORDER BY d.name
+    ",
+    )
+    .bind(user_id)
+    .fetch_all(&mut *tr)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let cabinets: Vec<Cabinet> = list_friend_cabinets(user_id, &mut *tr).await?;
+
+    let cabinet_products: Vec<CabinetProduct> =
+        sqlx::query_as("SELECT * FROM cabinet_products WHERE owner_id = $1 ORDER BY id")
+            .bind(user_id)
+            .fetch_all(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+
+    let cabinet_mixers: Vec<CabinetMixer> =
+        sqlx::query_as("SELECT * FROM cabinet_mixers WHERE owner_id = $1 ORDER BY id")
+            .bind(user_id)
+            .fetch_all(&mut *tr)
+            .await
+            .map_err(|e| QueryError::from(e).into())?;
+
+    tr.commit().await.map_err(|e| QueryError::from(e).into())?;
+
+    Ok(UserDataExport {
+        exported_at: Utc::now(),
+        profile,
+        role: user.uid,
+        recipes,
+        incredients,
+        revisions,
+        ratings,
+        favorites,
+        owned_incredients,
+        cabinets,
+        cabinet_products,
+        cabinet_mixers,
+    })
+}
@@ -0,0 +1,601 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display},
+};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+
+use crate::error::QueryError;
+
+/* Postgres enum labels, as they are expected by the `sqlx::Type` enums in `schema.rs` and by `parsed_drinks.origin` */
+pub const EXPECTED_ENUMS: &[(&str, &[&str])] = &[
+    ("user_type", &["user", "creator", "admin"]),
+    (
+        "product_type",
+        &[
+            "light_alcohol_product",
+            "strong_alcohol_product",
+            "common",
+            "mixer",
+            "grocery",
+            "generated",
+        ],
+    ),
+    ("drink_type", &["cocktail", "shot", "punch", "generated"]),
+    (
+        "unit_type",
+        &[
+            "cl", "ml", "oz", "kpl", "tl", "dash", "dl", "l", "tbsp", "barspoon", "splash", "part",
+        ],
+    ),
+    ("retailer", &["superalko", "alko", "viking_line"]),
+    ("parser", &["nettibaari", "forms"]),
+    (
+        "method_action",
+        &["shake", "stir", "build", "muddle", "blend", "top"],
+    ),
+    (
+        "glassware",
+        &[
+            "coupe",
+            "martini",
+            "highball",
+            "collins",
+            "rocks",
+            "shot",
+            "wine",
+            "flute",
+            "hurricane",
+            "mug",
+            "punch_bowl",
+        ],
+    ),
+    ("ice_type", &["none", "cubed", "crushed", "large_cube"]),
+];
+
+/* Columns read by the row types in `schema.rs`, with their expected `udt_name` */
+pub const EXPECTED_TABLES: &[(&str, &[(&str, &str)])] = &[
+    (
+        "users",
+        &[
+            ("id", "int4"),
+            ("username", "text"),
+            ("password", "text"),
+            ("uid", "user_type"),
+            ("display_name", "text"),
+            ("bio", "text"),
+        ],
+    ),
+    (
+        "drink_incredients",
+        &[
+            ("id", "int4"),
+            ("type", "product_type"),
+            ("author_id", "int4"),
+            ("name", "text"),
+            ("recipe_id", "int4"),
+            ("category", "int4"),
+            ("abv_average", "float8"),
+            ("abv_max", "float8"),
+            ("abv_min", "float8"),
+            ("alko_price_average", "float8"),
+            ("alko_price_max", "float8"),
+            ("alko_price_min", "float8"),
+            ("superalko_price_average", "float8"),
+            ("superalko_price_max", "float8"),
+            ("superalko_price_min", "float8"),
+            ("alko_product_count", "int4"),
+            ("superalko_product_count", "int4"),
+            ("use_static_filter", "bool"),
+            ("use_static_filter_c", "bool"),
+            ("static_filter", "int4"),
+            ("static_filter_c", "int4"),
+            ("unit", "unit_type"),
+            ("piece_volume_ml", "float8"),
+            ("kcal", "float8"),
+            ("sugar", "float8"),
+            ("carbohydrates", "float8"),
+        ],
+    ),
+    (
+        "incredient_colors",
+        &[
+            ("incredient_id", "int4"),
+            ("r", "int4"),
+            ("g", "int4"),
+            ("b", "int4"),
+            ("a", "int4"),
+        ],
+    ),
+    ("categories", &[("id", "int4"), ("name", "text")]),
+    (
+        "subcategories",
+        &[
+            ("id", "int4"),
+            ("name", "text"),
+            ("category_id", "int4"),
+            ("product_count", "int4"),
+        ],
+    ),
+    (
+        "products",
+        &[
+            ("id", "int4"),
+            ("name", "text"),
+            ("href", "text"),
+            ("price", "float8"),
+            ("img", "text"),
+            ("volume", "float8"),
+            ("category_id", "int4"),
+            ("subcategory_id", "int4"),
+            ("currently_available", "bool"),
+            ("last_available", "timestamp"),
+            ("abv", "float8"),
+            ("aer", "float8"),
+            ("unit_price", "float8"),
+            ("checksum", "text"),
+            ("retailer", "retailer"),
+        ],
+    ),
+    (
+        "incredient_product_filters",
+        &[("incredient_id", "int4"), ("product_id", "int4")],
+    ),
+    (
+        "incredient_aliases",
+        &[("id", "int4"), ("incredient_id", "int4"), ("alias", "text")],
+    ),
+    (
+        "drink_recipes",
+        &[
+            ("id", "int4"),
+            ("type", "drink_type"),
+            ("author_id", "int4"),
+            ("name", "text"),
+            ("info", "text"),
+            ("recipe_id", "int4"),
+            ("tag_list", "text"),
+            ("total_volume", "float8"),
+            ("standard_servings", "float8"),
+            ("alko_price_per_serving", "float8"),
+            ("superalko_price_per_serving", "float8"),
+            ("alko_aer", "float8"),
+            ("superalko_aer", "float8"),
+            ("abv_average", "float8"),
+            ("abv_max", "float8"),
+            ("abv_min", "float8"),
+            ("alko_price_max", "float8"),
+            ("alko_price_min", "float8"),
+            ("alko_price_average", "float8"),
+            ("superalko_price_max", "float8"),
+            ("superalko_price_min", "float8"),
+            ("superalko_price_average", "float8"),
+            ("incredient_count", "int4"),
+            ("favorite_count", "int4"),
+            ("rating_count", "int4"),
+            ("rating_average", "float8"),
+            ("rating_bayesian", "float8"),
+            ("available_superalko", "bool"),
+            ("available_alko", "bool"),
+            ("import_origin", "int4"),
+            ("parent_id", "int4"),
+            ("diluted_volume", "float8"),
+            ("diluted_abv", "float8"),
+            ("servings", "int4"),
+            ("kcal", "float8"),
+            ("sugar", "float8"),
+            ("carbohydrates", "float8"),
+            ("unknown_piece_volume", "bool"),
+            ("glassware", "glassware"),
+            ("ice", "ice_type"),
+            ("garnish", "text"),
+        ],
+    ),
+    (
+        "recipe_steps",
+        &[
+            ("recipe_id", "int4"),
+            ("position", "int4"),
+            ("action", "method_action"),
+            ("instruction", "text"),
+        ],
+    ),
+    (
+        "recipe_ratings",
+        &[
+            ("recipe_id", "int4"),
+            ("user_id", "int4"),
+            ("score", "int4"),
+            ("review", "text"),
+            ("created_at", "timestamp"),
+            ("updated_at", "timestamp"),
+        ],
+    ),
+    (
+        "recipe_revisions",
+        &[
+            ("id", "int4"),
+            ("recipe_id", "int4"),
+            ("revision", "int4"),
+            ("author_id", "int4"),
+            ("created_at", "timestamp"),
+            ("name", "text"),
+            ("type", "drink_type"),
+            ("info", "text"),
+            ("tag_ids", "_int4"),
+        ],
+    ),
+    (
+        "recipe_revision_parts",
+        &[
+            ("revision_id", "int4"),
+            ("incredient_id", "int4"),
+            ("amount", "float8"),
+            ("unit", "unit_type"),
+        ],
+    ),
+    (
+        "recipe_parts",
+        &[
+            ("recipe_id", "int4"),
+            ("incredient_id", "int4"),
+            ("amount", "float8"),
+            ("amount_standard", "float8"),
+            ("unit", "unit_type"),
+        ],
+    ),
+    ("recipe_tags", &[("id", "int4"), ("name", "text")]),
+    (
+        "recipe_tags_map",
+        &[
+            ("recipe_id", "int4"),
+            ("tag_id", "int4"),
+            ("tag_name", "text"),
+        ],
+    ),
+    (
+        "cabinets",
+        &[
+            ("id", "int4"),
+            ("owner_id", "int4"),
+            ("name", "text"),
+            ("access_key", "text"),
+            ("checksum", "text"),
+        ],
+    ),
+    (
+        "shared_cabinets",
+        &[
+            ("cabinet_id", "int4"),
+            ("user_id", "int4"),
+            ("user_username", "text"),
+        ],
+    ),
+    (
+        "cabinet_products",
+        &[
+            ("id", "int4"),
+            ("cabinet_id", "int4"),
+            ("product_id", "int4"),
+            ("owner_id", "int4"),
+            ("name", "text"),
+            ("img", "text"),
+            ("href", "text"),
+            ("abv", "float8"),
+            ("amount_ml", "int4"),
+            ("usable", "bool"),
+        ],
+    ),
+    (
+        "cabinet_mixers",
+        &[
+            ("id", "int4"),
+            ("cabinet_id", "int4"),
+            ("incredient_id", "int4"),
+            ("owner_id", "int4"),
+            ("name", "text"),
+            ("unit", "unit_type"),
+            ("usable", "bool"),
+            ("amount", "float8"),
+        ],
+    ),
+    (
+        "parsed_drinks",
+        &[
+            ("id", "int4"),
+            ("value", "text"),
+            ("added", "bool"),
+            ("name", "text"),
+            ("incredient_names", "_text"),
+            ("duplicate_score", "float8"),
+        ],
+    ),
+    (
+        "drink_randomizer_queue",
+        &[
+            ("id", "int4"),
+            ("author", "int4"),
+            ("cabinet_ref", "int4"),
+            ("multiplier", "float8"),
+            ("count", "int4"),
+            ("allow_duplicates", "bool"),
+        ],
+    ),
+    (
+        "queue_drink",
+        &[
+            ("id", "int4"),
+            ("queue_id", "int4"),
+            ("recipe_id", "int4"),
+            ("revealed", "bool"),
+            ("revealed_by_user", "int4"),
+        ],
+    ),
+    (
+        "product_price_history",
+        &[("product_id", "int4"), ("price", "float8")],
+    ),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SchemaMismatch {
+    MissingTable {
+        table: String,
+    },
+    MissingColumn {
+        table: String,
+        column: String,
+    },
+    ColumnType {
+        table: String,
+        column: String,
+        expected: String,
+        found: String,
+    },
+    MissingEnum {
+        type_name: String,
+    },
+    /// Variant exists in Rust, but can't be stored in the database
+    MissingEnumLabel {
+        type_name: String,
+        label: String,
+    },
+    /// Label exists in the database, but can't be decoded in Rust
+    UnknownEnumLabel {
+        type_name: String,
+        label: String,
+    },
+}
+
+impl Display for SchemaMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaMismatch::MissingTable { table } => write!(f, "Missing table {table}"),
+            SchemaMismatch::MissingColumn { table, column } => {
+                write!(f, "Missing column {table}.{column}")
+            }
+            SchemaMismatch::ColumnType {
+                table,
+                column,
+                expected,
+                found,
+            } => write!(f, "Column {table}.{column} is {found}, expected {expected}"),
+            SchemaMismatch::MissingEnum { type_name } => write!(f, "Missing enum {type_name}"),
+            SchemaMismatch::MissingEnumLabel { type_name, label } => {
+                write!(f, "Enum {type_name} is missing label '{label}'")
+            }
+            SchemaMismatch::UnknownEnumLabel { type_name, label } => {
+                write!(f, "Enum {type_name} has unknown label '{label}'")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SchemaReport {
+    pub mismatches: Vec<SchemaMismatch>,
+}
+
+impl SchemaReport {
+    pub fn is_compatible(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Compares the live database against the tables and enums the SDK expects.
+/// Does not fail on mismatches; inspect the returned report instead.
+pub async fn verify_schema(pool: &Pool<Postgres>) -> Result<SchemaReport, potion::Error> {
+    let columns: Vec<(String, String, String)> = sqlx::query_as(
+        "
+        SELECT table_name::text, column_name::text, udt_name::text
+        FROM information_schema.columns
+        WHERE table_schema = current_schema()
+    ",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let labels: Vec<(String, String)> = sqlx::query_as(
+        "
+        SELECT t.typname::text, e.enumlabel::text
+        FROM pg_type t
+        INNER JOIN pg_enum e ON e.enumtypid = t.oid
+        INNER JOIN pg_namespace n ON n.oid = t.typnamespace
+        WHERE n.nspname = current_schema()
+        ORDER BY t.typname, e.enumsortorder
+    ",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    let mut tables: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    columns.into_iter().for_each(|(table, column, udt)| {
+        tables.entry(table).or_default().insert(column, udt);
+    });
+
+    let mut enums: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    labels.into_iter().for_each(|(type_name, label)| {
+        enums.entry(type_name).or_default().insert(label);
+    });
+
+    Ok(compare_schema(&tables, &enums))
+}
+
+fn compare_schema(
+    tables: &BTreeMap<String, BTreeMap<String, String>>,
+    enums: &BTreeMap<String, BTreeSet<String>>,
+) -> SchemaReport {
+    let mut mismatches = vec![];
+
+    for (table, expected_columns) in EXPECTED_TABLES {
+        let Some(columns) = tables.get(*table) else {
+            mismatches.push(SchemaMismatch::MissingTable {
+                table: table.to_string(),
+            });
+            continue;
+        };
+
+        for (column, expected) in expected_columns.iter() {
+            match columns.get(*column) {
+                None => mismatches.push(SchemaMismatch::MissingColumn {
+                    table: table.to_string(),
+                    column: column.to_string(),
+                }),
+                Some(found) if found != expected => mismatches.push(SchemaMismatch::ColumnType {
+                    table: table.to_string(),
+                    column: column.to_string(),
+                    expected: expected.to_string(),
+                    found: found.to_owned(),
+                }),
+                Some(_) => {}
+            }
+        }
+    }
+
+    for (type_name, expected_labels) in EXPECTED_ENUMS {
+        let Some(labels) = enums.get(*type_name) else {
+            mismatches.push(SchemaMismatch::MissingEnum {
+                type_name: type_name.to_string(),
+            });
+            continue;
+        };
+
+        expected_labels
+            .iter()
+            .filter(|label| !labels.contains(**label))
+            .for_each(|label| {
+                mismatches.push(SchemaMismatch::MissingEnumLabel {
+                    type_name: type_name.to_string(),
+                    label: label.to_string(),
+                })
+            });
+
+        labels
+            .iter()
+            .filter(|label| !expected_labels.contains(&label.as_str()))
+            .for_each(|label| {
+                mismatches.push(SchemaMismatch::UnknownEnumLabel {
+                    type_name: type_name.to_string(),
+                    label: label.to_owned(),
+                })
+            });
+    }
+
+    SchemaReport { mismatches }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expected_schema() -> (
+        BTreeMap<String, BTreeMap<String, String>>,
+        BTreeMap<String, BTreeSet<String>>,
+    ) {
+        let tables = EXPECTED_TABLES
+            .iter()
+            .map(|(table, columns)| {
+                let columns = columns
+                    .iter()
+                    .map(|(column, udt)| (column.to_string(), udt.to_string()))
+                    .collect();
+                (table.to_string(), columns)
+            })
+            .collect();
+        let enums = EXPECTED_ENUMS
+            .iter()
+            .map(|(type_name, labels)| {
+                let labels = labels.iter().map(|label| label.to_string()).collect();
+                (type_name.to_string(), labels)
+            })
+            .collect();
+        (tables, enums)
+    }
+
+    #[test]
+    fn accepts_expected_schema() {
+        let (tables, enums) = expected_schema();
+        assert!(compare_schema(&tables, &enums).is_compatible());
+    }
+
+    #[test]
+    fn reports_table_mismatches() {
+        let (mut tables, enums) = expected_schema();
+        tables.remove("recipe_steps");
+        let recipes = tables.get_mut("drink_recipes").unwrap();
+        recipes.remove("servings");
+        recipes.insert("kcal".to_owned(), "int4".to_owned());
+        recipes.insert("legacy".to_owned(), "text".to_owned());
+
+        let report = compare_schema(&tables, &enums);
+        assert_eq!(
+            report.mismatches,
+            vec![
+                SchemaMismatch::MissingColumn {
+                    table: "drink_recipes".to_owned(),
+                    column: "servings".to_owned(),
+                },
+                SchemaMismatch::ColumnType {
+                    table: "drink_recipes".to_owned(),
+                    column: "kcal".to_owned(),
+                    expected: "float8".to_owned(),
+                    found: "int4".to_owned(),
+                },
+                SchemaMismatch::MissingTable {
+                    table: "recipe_steps".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_enum_mismatches() {
+        let (tables, mut enums) = expected_schema();
+        enums.remove("ice_type");
+        let retailers = enums.get_mut("retailer").unwrap();
+        retailers.remove("viking_line");
+        retailers.insert("lidl".to_owned());
+
+        let report = compare_schema(&tables, &enums);
+        assert_eq!(
+            report.mismatches,
+            vec![
+                SchemaMismatch::MissingEnumLabel {
+                    type_name: "retailer".to_owned(),
+                    label: "viking_line".to_owned(),
+                },
+                SchemaMismatch::UnknownEnumLabel {
+                    type_name: "retailer".to_owned(),
+                    label: "lidl".to_owned(),
+                },
+                SchemaMismatch::MissingEnum {
+                    type_name: "ice_type".to_owned(),
+                },
+            ]
+        );
+    }
+}
@@ -1,83 +1,254 @@
 use std::fmt::{self, Display};
 
 use potion::{Error, HtmlError};
+use serde::{Deserialize, Serialize};
+use sqlx::error::ErrorKind;
 use warp::reject::Rejection;
 
+/// Service-wide error taxonomy. Every SDK error converts into one of these,
+/// which in turn maps to a `potion::Error` with a matching status code.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SdkError {
+    NotFound {
+        info: String,
+    },
+    /// Unique constraint violation
+    Conflict {
+        constraint: Option<String>,
+        info: String,
+    },
+    ForeignKeyViolation {
+        constraint: Option<String>,
+        info: String,
+    },
+    Validation {
+        info: String,
+    },
+    Unauthorized {
+        info: String,
+    },
+    /// Database or cache could not be reached
+    Unavailable {
+        info: String,
+    },
+    Internal {
+        info: String,
+    },
+}
+
+impl SdkError {
+    pub fn not_found(info: &str) -> Self {
+        Self::NotFound {
+            info: info.to_string(),
+        }
+    }
+
+    pub fn conflict(info: &str) -> Self {
+        Self::Conflict {
+            constraint: None,
+            info: info.to_string(),
+        }
+    }
+
+    pub fn validation(info: &str) -> Self {
+        Self::Validation {
+            info: info.to_string(),
+        }
+    }
+
+    pub fn unauthorized(info: &str) -> Self {
+        Self::Unauthorized {
+            info: info.to_string(),
+        }
+    }
+
+    pub fn internal(info: &str) -> Self {
+        Self::Internal {
+            info: info.to_string(),
+        }
+    }
+
+    pub fn code(&self) -> u16 {
+        match self {
+            SdkError::NotFound { .. } => 404,
+            SdkError::Conflict { .. } => 409,
+            SdkError::ForeignKeyViolation { .. } => 409,
+            SdkError::Validation { .. } => 400,
+            SdkError::Unauthorized { .. } => 401,
+            SdkError::Unavailable { .. } => 503,
+            SdkError::Internal { .. } => 500,
+        }
+    }
+
+    pub fn info(&self) -> &str {
+        match self {
+            SdkError::NotFound { info }
+            | SdkError::Conflict { info, .. }
+            | SdkError::ForeignKeyViolation { info, .. }
+            | SdkError::Validation { info }
+            | SdkError::Unauthorized { info }
+            | SdkError::Unavailable { info }
+            | SdkError::Internal { info } => info,
+        }
+    }
+}
+
+impl From<sqlx::Error> for SdkError {
+    fn from(value: sqlx::Error) -> Self {
+        match value {
+            sqlx::Error::Database(e) => {
+                let constraint = e.constraint().map(|c| c.to_owned());
+                let info = format!("{e}");
+                match e.kind() {
+                    ErrorKind::UniqueViolation => Self::Conflict { constraint, info },
+                    ErrorKind::ForeignKeyViolation => {
+                        Self::ForeignKeyViolation { constraint, info }
+                    }
+                    ErrorKind::NotNullViolation | ErrorKind::CheckViolation => {
+                        Self::Validation { info }
+                    }
+                    _ => match e.code().as_deref() {
+                        // invalid_text_representation, numeric_value_out_of_range
+                        Some("22P02") | Some("22003") => Self::Validation { info },
+                        _ => Self::Internal { info },
+                    },
+                }
+            }
+            sqlx::Error::RowNotFound => Self::not_found("Row not found"),
+            e @ (sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed) => Self::Unavailable {
+                info: format!("{e}"),
+            },
+            e => Self::internal(&format!("{e}")),
+        }
+    }
+}
+
+impl From<redis::RedisError> for SdkError {
+    fn from(value: redis::RedisError) -> Self {
+        let info = format!("{:?} - {:?}", value.code(), value.detail());
+        if value.is_io_error()
+            || value.is_connection_refusal()
+            || value.is_connection_dropped()
+            || value.is_timeout()
+        {
+            Self::Unavailable { info }
+        } else {
+            Self::Internal { info }
+        }
+    }
+}
+
+impl From<QueryError> for SdkError {
+    fn from(value: QueryError) -> Self {
+        value.inner
+    }
+}
+
+impl From<CacheError> for SdkError {
+    fn from(value: CacheError) -> Self {
+        value.inner
+    }
+}
+
+impl From<TypeError> for SdkError {
+    fn from(value: TypeError) -> Self {
+        Self::Validation { info: value.info }
+    }
+}
+
+impl Into<Error> for SdkError {
+    fn into(self) -> Error {
+        match self {
+            SdkError::Validation { info } => HtmlError::InvalidRequest.new(&info),
+            SdkError::Unauthorized { info } => HtmlError::Unauthorized.new(&info),
+            _ => Error {
+                code: self.code(),
+                info: Some(self.info().to_owned()),
+                redirect: None,
+            },
+        }
+    }
+}
+
+impl Into<Rejection> for SdkError {
+    fn into(self) -> Rejection {
+        let error: Error = self.into();
+        error.into()
+    }
+}
+
+impl Display for SdkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.info(), self.code())
+    }
+}
+
+impl std::error::Error for SdkError {}
+
 pub struct QueryError {
-    info: String,
+    inner: SdkError,
 }
 
 impl QueryError {
     pub fn new(info: String) -> Self {
-        Self { info }
+        Self {
+            inner: SdkError::Internal { info },
+        }
+    }
+
+    pub fn kind(&self) -> &SdkError {
+        &self.inner
     }
 }
 
 impl From<sqlx::Error> for QueryError {
     fn from(value: sqlx::Error) -> Self {
-        match value {
-            sqlx::Error::Configuration(e) => Self::new(format!("{e}")),
-            sqlx::Error::Database(e) => Self::new(format!("{e}")),
-            sqlx::Error::Io(e) => Self::new(format!("{e}")),
-            sqlx::Error::Tls(e) => Self::new(format!("{e}")),
-            sqlx::Error::Protocol(e) => Self::new(format!("{e}")),
-            sqlx::Error::RowNotFound => Self::new(format!("RowNotFound")),
-            sqlx::Error::TypeNotFound { type_name } => {
-                Self::new(format!("Type not found: {type_name}"))
-            }
-            sqlx::Error::ColumnIndexOutOfBounds { index, len } => {
-                Self::new(format!("Column index out of bounds {index} ({len})"))
-            }
-            sqlx::Error::ColumnNotFound(e) => Self::new(format!("{e}")),
-            sqlx::Error::ColumnDecode { index, source } => {
-                Self::new(format!("Column decode {index} ({source})"))
-            }
-            sqlx::Error::Decode(e) => Self::new(format!("{e}")),
-            sqlx::Error::AnyDriverError(e) => Self::new(format!("{e}")),
-            sqlx::Error::PoolTimedOut => Self::new(format!("Pool timed out")),
-            sqlx::Error::PoolClosed => Self::new(format!("Pool closed")),
-            sqlx::Error::WorkerCrashed => Self::new(format!("Worker crashed")),
-            sqlx::Error::Migrate(e) => Self::new(format!("{e}")),
-            _ => Self::new(format!("Unknown error")),
+        Self {
+            inner: SdkError::from(value),
         }
     }
 }
 
+impl From<sqlx::migrate::MigrateError> for QueryError {
+    fn from(value: sqlx::migrate::MigrateError) -> Self {
+        Self::new(format!("Migration failed: {value}"))
+    }
+}
+
 impl Into<Error> for QueryError {
     fn into(self) -> Error {
-        Error {
-            code: 500,
-            info: Some(self.info),
-            redirect: None,
-        }
+        self.inner.into()
     }
 }
 
 pub struct CacheError {
-    info: String,
+    inner: SdkError,
 }
 
 impl From<redis::RedisError> for CacheError {
     fn from(value: redis::RedisError) -> Self {
         Self {
-            info: format!("{:?} - {:?}", value.code(), value.detail()),
+            inner: SdkError::from(value),
         }
     }
 }
 
 impl CacheError {
     pub fn new(info: String) -> Self {
-        Self { info }
+        Self {
+            inner: SdkError::Internal { info },
+        }
     }
 }
 
 impl Into<Error> for CacheError {
     fn into(self) -> Error {
-        Error {
-            code: 500,
-            info: Some(self.info),
-            redirect: None,
-        }
+        self.inner.into()
     }
 }
 
@@ -112,3 +283,84 @@ impl Into<Rejection> for TypeError {
         HtmlError::InvalidRequest.new(&self.info).into()
     }
 }
+
+/// Unit conversion that can't be done without more information
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UnitError {
+    /// `kpl` needs the volume of one piece of the incredient
+    MissingPieceVolume,
+    /// Unit volume isn't positive
+    InvalidVolume,
+}
+
+impl Display for UnitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnitError::MissingPieceVolume => write!(f, "Incredient has no volume per piece"),
+            UnitError::InvalidVolume => write!(f, "Unit volume must be positive"),
+        }
+    }
+}
+
+impl std::error::Error for UnitError {}
+
+impl From<UnitError> for SdkError {
+    fn from(value: UnitError) -> Self {
+        SdkError::validation(&value.to_string())
+    }
+}
+
+impl Into<potion::Error> for UnitError {
+    fn into(self) -> potion::Error {
+        SdkError::from(self).into()
+    }
+}
+
+/// Standard Recipe Syntax parse error. `column` counts characters from 1
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SrsError {
+    pub column: usize,
+    pub expected: String,
+    pub found: String,
+}
+
+impl SrsError {
+    pub fn new(column: usize, expected: &str, found: &str) -> Self {
+        Self {
+            column,
+            expected: expected.to_string(),
+            found: found.to_string(),
+        }
+    }
+}
+
+impl Display for SrsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid syntax at column {}; expected {}, found {}",
+            self.column, self.expected, self.found
+        )
+    }
+}
+
+impl std::error::Error for SrsError {}
+
+impl From<SrsError> for TypeError {
+    fn from(value: SrsError) -> Self {
+        TypeError::new(&value.to_string())
+    }
+}
+
+impl From<SrsError> for SdkError {
+    fn from(value: SrsError) -> Self {
+        SdkError::validation(&value.to_string())
+    }
+}
+
+impl Into<potion::Error> for SrsError {
+    fn into(self) -> potion::Error {
+        SdkError::from(self).into()
+    }
+}
@@ -0,0 +1,370 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, QueryBuilder};
+
+use crate::{
+    schema::{
+        Glassware, IceType, MethodAction, ProductType, RecipeAvailability, RecipeType, Retailer,
+    },
+    search::{SearchMode, TextSearch},
+};
+
+/// Inclusive range, either end may be left open
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Range {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl Range {
+    pub fn new(min: Option<f64>, max: Option<f64>) -> Self {
+        Self { min, max }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.is_none() && self.max.is_none()
+    }
+
+    /// Pushes `column >= min AND column <= max`, or `TRUE` when the range is open on both ends
+    fn push<'args>(&self, column: &str, builder: &mut QueryBuilder<'args, Postgres>) {
+        if self.is_empty() {
+            builder.push("TRUE");
+            return;
+        }
+
+        let mut conditions = builder.separated(" AND ");
+        if let Some(min) = self.min {
+            conditions.push(format!("{column} >= "));
+            conditions.push_bind_unseparated(min);
+        }
+        if let Some(max) = self.max {
+            conditions.push(format!("{column} <= "));
+            conditions.push_bind_unseparated(max);
+        }
+    }
+}
+
+/// Filters applied to a query, which already contains a `WHERE` clause.
+/// Every condition is appended as `AND ...` with its values bound as parameters.
+pub trait QueryFilter {
+    fn push_conditions<'args>(&self, builder: &mut QueryBuilder<'args, Postgres>);
+}
+
+fn push_in<'args, T>(column: &str, values: &[T], builder: &mut QueryBuilder<'args, Postgres>)
+where
+    T: 'args + Clone + Send + sqlx::Encode<'args, Postgres> + sqlx::Type<Postgres>,
+{
+    builder.push(format!(" AND {column} IN ("));
+    let mut list = builder.separated(", ");
+    values.iter().for_each(|value| {
+        list.push_bind(value.clone());
+    });
+    builder.push(")");
+}
+
+fn push_search<'args>(
+    column: &str,
+    search: &str,
+    mode: SearchMode,
+    builder: &mut QueryBuilder<'args, Postgres>,
+) {
+    match mode {
+        SearchMode::Pattern => {
+            builder
+                .push(format!(" AND {column} ILIKE "))
+                .push_bind(search.to_owned());
+        }
+        SearchMode::FullText => {
+            if let Some(search) = TextSearch::new(search) {
+                builder.push(" AND ");
+                search.push_match(column, builder);
+            }
+        }
+    }
+}
+
+/// Filter for `drink_recipes`, aliased as `r`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecipeFilter {
+    /// Any of the categories. Generated recipes are hidden unless requested here or with `include_generated`
+    pub categories: Vec<RecipeType>,
+    pub include_generated: bool,
+    pub author: Option<i32>,
+    /// `ILIKE` pattern, or free text with `SearchMode::FullText`, matched against the name
+    pub search: Option<String>,
+    pub search_mode: SearchMode,
+    pub abv: Range,
+    /// ABV after dilution from ice, see `RecipeDilution`
+    pub diluted_abv: Range,
+    /// Volume in ml after dilution from ice
+    pub diluted_volume: Range,
+    /// Calories per serving
+    pub kcal: Range,
+    /// Sugar per serving, in grams
+    pub sugar: Range,
+    /// Carbohydrates per serving, in grams
+    pub carbohydrates: Range,
+    /// Average price of the whole recipe in the retailer selected by `availability`,
+    /// or in any retailer where the recipe is available
+    pub price: Range,
+    pub availability: Option<RecipeAvailability>,
+    /// Recipe must have every tag
+    pub tags: Vec<i32>,
+    /// Recipe must contain every incredient
+    pub include_incredients: Vec<i32>,
+    /// Recipe must not contain any of the incredients
+    pub exclude_incredients: Vec<i32>,
+    /// Recipe must have a step with every action
+    pub actions: Vec<MethodAction>,
+    /// Any of the glasses
+    pub glassware: Vec<Glassware>,
+    /// Any of the ice types
+    pub ice: Vec<IceType>,
+}
+
+impl QueryFilter for RecipeFilter {
+    fn push_conditions<'args>(&self, builder: &mut QueryBuilder<'args, Postgres>) {
+        if !self.categories.is_empty() {
+            push_in("r.type", &self.categories, builder);
+        } else if !self.include_generated {
+            builder.push(" AND r.type != 'generated'");
+        }
+
+        if let Some(author) = self.author {
+            builder.push(" AND r.author_id = ").push_bind(author);
+        }
+
+        if let Some(search) = &self.search {
+            push_search("r.name", search, self.search_mode, builder);
+        }
+
+        if !self.abv.is_empty() {
+            builder.push(" AND ");
+            self.abv.push("r.abv_average", builder);
+        }
+
+        if !self.diluted_abv.is_empty() {
+            builder.push(" AND ");
+            self.diluted_abv.push("r.diluted_abv", builder);
+        }
+
+        if !self.diluted_volume.is_empty() {
+            builder.push(" AND ");
+            self.diluted_volume.push("r.diluted_volume", builder);
+        }
+
+        if !self.kcal.is_empty() {
+            builder.push(" AND ");
+            self.kcal.push("r.kcal", builder);
+        }
+
+        if !self.sugar.is_empty() {
+            builder.push(" AND ");
+            self.sugar.push("r.sugar", builder);
+        }
+
+        if !self.carbohydrates.is_empty() {
+            builder.push(" AND ");
+            self.carbohydrates.push("r.carbohydrates", builder);
+        }
+
+        match self.availability {
+            Some(RecipeAvailability::Alko) => {
+                builder.push(" AND r.available_alko");
+            }
+            Some(RecipeAvailability::Superalko) => {
+                builder.push(" AND r.available_superalko");
+            }
+            // TODO see #39
+            Some(RecipeAvailability::VikingLine) => {}
+            Some(RecipeAvailability::Any) | None => {}
+        }
+
+        if !self.price.is_empty() {
+            match self.availability {
+                Some(RecipeAvailability::Alko) => {
+                    builder.push(" AND ");
+                    self.price.push("r.alko_price_average", builder);
+                }
+                Some(RecipeAvailability::Superalko) => {
+                    builder.push(" AND ");
+                    self.price.push("r.superalko_price_average", builder);
+                }
+                _ => {
+                    builder.push(" AND ((r.available_alko AND ");
+                    self.price.push("r.alko_price_average", builder);
+                    builder.push(") OR (r.available_superalko AND ");
+                    self.price.push("r.superalko_price_average", builder);
+                    builder.push("))");
+                }
+            }
+        }
+
+        if !self.tags.is_empty() {
+            builder.push(" AND r.id IN (SELECT recipe_id FROM recipe_tags_map WHERE TRUE");
+            push_in("tag_id", &self.tags, builder);
+            builder
+                .push(" GROUP BY recipe_id HAVING COUNT(DISTINCT tag_id) = ")
+                .push_bind(self.tags.len() as i64)
+                .push(")");
+        }
+
+        if !self.include_incredients.is_empty() {
+            builder.push(" AND r.recipe_id IN (SELECT recipe_id FROM recipe_parts WHERE TRUE");
+            push_in("incredient_id", &self.include_incredients, builder);
+            builder
+                .push(" GROUP BY recipe_id HAVING COUNT(DISTINCT incredient_id) = ")
+                .push_bind(self.include_incredients.len() as i64)
+                .push(")");
+        }
+
+        if !self.exclude_incredients.is_empty() {
+            builder.push(" AND r.recipe_id NOT IN (SELECT recipe_id FROM recipe_parts WHERE TRUE");
+            push_in("incredient_id", &self.exclude_incredients, builder);
+            builder.push(")");
+        }
+
+        if !self.actions.is_empty() {
+            builder.push(" AND r.id IN (SELECT recipe_id FROM recipe_steps WHERE TRUE");
+            push_in("action", &self.actions, builder);
+            builder
+                .push(" GROUP BY recipe_id HAVING COUNT(DISTINCT action) = ")
+                .push_bind(self.actions.len() as i64)
+                .push(")");
+        }
+
+        if !self.glassware.is_empty() {
+            push_in("r.glassware", &self.glassware, builder);
+        }
+
+        if !self.ice.is_empty() {
+            push_in("r.ice", &self.ice, builder);
+        }
+    }
+}
+
+/// Filter for `drink_incredients`, aliased as `d`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IncredientFilter {
+    /// Any of the categories
+    pub categories: Vec<ProductType>,
+    pub author: Option<i32>,
+    /// `ILIKE` pattern, or free text with `SearchMode::FullText`, matched against the name
+    pub search: Option<String>,
+    pub search_mode: SearchMode,
+    pub abv: Range,
+    /// Average price in the retailer selected by `availability`,
+    /// or in any retailer that has matching products
+    pub price: Range,
+    pub availability: Option<RecipeAvailability>,
+}
+
+impl QueryFilter for IncredientFilter {
+    fn push_conditions<'args>(&self, builder: &mut QueryBuilder<'args, Postgres>) {
+        if !self.categories.is_empty() {
+            push_in("d.type", &self.categories, builder);
+        }
+
+        if let Some(author) = self.author {
+            builder.push(" AND d.author_id = ").push_bind(author);
+        }
+
+        if let Some(search) = &self.search {
+            push_search("d.name", search, self.search_mode, builder);
+        }
+
+        if !self.abv.is_empty() {
+            builder.push(" AND ");
+            self.abv.push("d.abv_average", builder);
+        }
+
+        match self.availability {
+            Some(RecipeAvailability::Alko) => {
+                builder.push(" AND d.alko_product_count > 0");
+            }
+            Some(RecipeAvailability::Superalko) => {
+                builder.push(" AND d.superalko_product_count > 0");
+            }
+            // TODO see #39
+            Some(RecipeAvailability::VikingLine) => {}
+            Some(RecipeAvailability::Any) | None => {}
+        }
+
+        if !self.price.is_empty() {
+            match self.availability {
+                Some(RecipeAvailability::Alko) => {
+                    builder.push(" AND ");
+                    self.price.push("d.alko_price_average", builder);
+                }
+                Some(RecipeAvailability::Superalko) => {
+                    builder.push(" AND ");
+                    self.price.push("d.superalko_price_average", builder);
+                }
+                _ => {
+                    builder.push(" AND ((d.alko_product_count > 0 AND ");
+                    self.price.push("d.alko_price_average", builder);
+                    builder.push(") OR (d.superalko_product_count > 0 AND ");
+                    self.price.push("d.superalko_price_average", builder);
+                    builder.push("))");
+                }
+            }
+        }
+    }
+}
+
+/// Filter for `products`, aliased as `p`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProductFilter {
+    /// `ILIKE` pattern, or free text with `SearchMode::FullText`, matched against the name
+    pub search: Option<String>,
+    pub search_mode: SearchMode,
+    /// Any of the categories
+    pub categories: Vec<i32>,
+    /// Any of the subcategories
+    pub subcategories: Vec<i32>,
+    pub abv: Range,
+    pub price: Range,
+    /// Any of the retailers
+    pub retailers: Vec<Retailer>,
+}
+
+impl ProductFilter {
+    pub fn with_availability(mut self, availability: Option<RecipeAvailability>) -> Self {
+        match availability {
+            Some(RecipeAvailability::Alko) => self.retailers = vec![Retailer::Alko],
+            Some(RecipeAvailability::Superalko) => self.retailers = vec![Retailer::Superalko],
+            Some(RecipeAvailability::VikingLine) => self.retailers = vec![Retailer::VikingLine],
+            Some(RecipeAvailability::Any) | None => {}
+        }
+        self
+    }
+}
+
+impl QueryFilter for ProductFilter {
+    fn push_conditions<'args>(&self, builder: &mut QueryBuilder<'args, Postgres>) {
+        if let Some(search) = &self.search {
+            push_search("p.name", search, self.search_mode, builder);
+        }
+
+        if !self.categories.is_empty() {
+            push_in("p.category_id", &self.categories, builder);
+        }
+
+        if !self.subcategories.is_empty() {
+            push_in("p.subcategory_id", &self.subcategories, builder);
+        }
+
+        if !self.abv.is_empty() {
+            builder.push(" AND ");
+            self.abv.push("p.abv", builder);
+        }
+
+        if !self.price.is_empty() {
+            builder.push(" AND ");
+            self.price.push("p.price", builder);
+        }
+
+        if !self.retailers.is_empty() {
+            push_in("p.retailer", &self.retailers, builder);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::TypeError,
+    schema::{Glassware, IceType, MethodAction, RecipeType, UnitType},
+};
+
+/*
+Versioned JSON format for moving recipes between environments and other apps.
+Incredients are referenced by name, so documents don't depend on database ids.
+
+{
+    "version": 1,
+    "recipes": [
+        {
+            "name": "Daiquiri",
+            "type": "cocktail",
+            "info": "Shake with ice",
+            "parts": [
+                { "incredient": "Rum", "amount": 6, "unit": "cl", "color": "#f5deb3" },
+                { "incredient": "Limettimehu", "amount": 2, "unit": "cl" }
+            ],
+            "tags": ["IBA"],
+            "steps": [
+                { "action": "shake", "instruction": "Shake hard with ice" }
+            ],
+            "glassware": "coupe",
+            "garnish": "Lime wheel"
+        }
+    ]
+}
+*/
+
+/// Version written by this crate. Documents with a newer version are rejected.
+pub const INTERCHANGE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RecipeDocument {
+    pub version: u32,
+    pub recipes: Vec<InterchangeRecipe>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct InterchangeRecipe {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub recipe_type: RecipeType,
+    #[serde(default)]
+    pub info: String,
+    pub parts: Vec<InterchangePart>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<InterchangeStep>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glassware: Option<Glassware>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ice: Option<IceType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub garnish: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct InterchangeStep {
+    pub action: MethodAction,
+    #[serde(default)]
+    pub instruction: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct InterchangePart {
+    /// Name of the incredient, matched exactly or by alias when imported
+    pub incredient: String,
+    pub amount: f64,
+    pub unit: UnitType,
+    /// Hex color of the incredient. Only set on import if the incredient has no color.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+impl RecipeDocument {
+    pub fn new(recipes: Vec<InterchangeRecipe>) -> Self {
+        Self {
+            version: INTERCHANGE_VERSION,
+            recipes,
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, TypeError> {
+        let document: Self = serde_json::from_str(json)
+            .map_err(|e| TypeError::new(&format!("Invalid recipe document: {e}")))?;
+
+        match document.version <= INTERCHANGE_VERSION {
+            true => Ok(document),
+            false => Err(TypeError::new(&format!(
+                "Unsupported recipe document version {}, the newest supported is {}",
+                document.version, INTERCHANGE_VERSION
+            ))),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InterchangeStatus {
+    Created,
+    /// The importing user has an identical recipe with the same name, so importing again changes nothing
+    Unchanged,
+    /// The importing user has a different recipe with the same name, the recipe was skipped
+    Conflict,
+    /// The recipe was skipped, see `detail`
+    Invalid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterchangeResult {
+    pub name: String,
+    pub status: InterchangeStatus,
+    /// Created, identical or conflicting recipe
+    pub recipe_id: Option<i32>,
+    pub detail: Option<String>,
+}
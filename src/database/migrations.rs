@@ -0,0 +1,95 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    migrate::{Migrate, Migrator},
+    Pool, Postgres,
+};
+
+use crate::error::QueryError;
+
+/// Ordered set of schema migrations embedded into the crate at compile time.
+/// New migrations are added as `NNNN_description.sql` files in `src/database/migrations`
+/// and must never be modified once released.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./src/database/migrations");
+
+/// Version of the migration that reproduces the legacy `schema.sql`
+const BASELINE_VERSION: i64 = 1;
+
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub description: String,
+    pub installed_on: DateTime<Utc>,
+    pub success: bool,
+}
+
+/// Applies every pending migration. Safe to call on every service startup.
+pub async fn run_migrations(pool: &Pool<Postgres>) -> Result<(), potion::Error> {
+    adopt_legacy_schema(pool).await?;
+
+    MIGRATOR
+        .run(pool)
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
+
+pub async fn list_applied_migrations(
+    pool: &Pool<Postgres>,
+) -> Result<Vec<AppliedMigration>, potion::Error> {
+    let rows: Vec<AppliedMigration> = sqlx::query_as(
+        "SELECT version, description, installed_on, success FROM _sqlx_migrations ORDER BY version",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(rows)
+}
+
+/// Databases initialized from the old `schema.sql` already contain the baseline tables,
+/// but have no migration history. Marks the baseline as applied so that it isn't re-run.
+async fn adopt_legacy_schema(pool: &Pool<Postgres>) -> Result<(), potion::Error> {
+    let (legacy,): (bool,) = sqlx::query_as(
+        "SELECT to_regclass('_sqlx_migrations') IS NULL AND to_regclass('drink_recipes') IS NOT NULL",
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    if !legacy {
+        return Ok(());
+    }
+
+    let baseline = MIGRATOR
+        .iter()
+        .find(|m| m.version == BASELINE_VERSION)
+        .ok_or_else(|| QueryError::new("Baseline migration is missing".to_owned()).into())?;
+
+    log::info!("> Adopting legacy schema as migration {BASELINE_VERSION}");
+
+    let mut conn = pool
+        .acquire()
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    conn.ensure_migrations_table()
+        .await
+        .map_err(|e| QueryError::from(e).into())?;
+
+    sqlx::query(
+        "
+        INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+        VALUES ($1, $2, true, $3, -1)
+    ",
+    )
+    .bind(baseline.version)
+    .bind(baseline.description.as_ref())
+    .bind(baseline.checksum.as_ref())
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| QueryError::from(e).into())?;
+
+    Ok(())
+}
@@ -1,39 +1,3 @@
-DROP TABLE IF EXISTS users CASCADE;
-DROP TABLE IF EXISTS drink_recipes CASCADE;
-DROP TABLE IF EXISTS recipe_tags CASCADE;
-DROP TABLE IF EXISTS recipe_tags_map CASCADE;
-DROP TABLE IF EXISTS drink_incredients CASCADE;
-DROP TABLE IF EXISTS recipes CASCADE;
-DROP TABLE IF EXISTS recipe_parts CASCADE;
-
-DROP TABLE IF EXISTS categories CASCADE;
-DROP TABLE IF EXISTS subcategories CASCADE;
-DROP TABLE IF EXISTS products CASCADE;
-DROP TABLE IF EXISTS user_incredients CASCADE;
-DROP TABLE IF EXISTS user_favorites CASCADE;
-
-DROP TABLE IF EXISTS recipe_tags CASCADE;
-DROP TABLE IF EXISTS recipe_tags CASCADE;
-DROP TABLE IF EXISTS cabinets CASCADE;
-DROP TABLE IF EXISTS shared_cabinets CASCADE;
-DROP TABLE IF EXISTS cabinet_products CASCADE;
-
-DROP TABLE IF EXISTS global_cache CASCADE;
-DROP TABLE IF EXISTS ingredient_cache CASCADE;
-DROP TABLE IF EXISTS recipe_cache CASCADE;
-
-
-DROP TABLE IF EXISTS incredient_product_filters CASCADE;
-
-DROP TYPE IF EXISTS user_type CASCADE;
-DROP TYPE IF EXISTS product_type CASCADE;
-DROP TYPE IF EXISTS drink_type CASCADE;
-DROP TYPE IF EXISTS unit_type CASCADE;
-DROP TYPE IF EXISTS retailer CASCADE;
-
-
-
-
 CREATE TYPE user_type AS ENUM ('user', 'creator', 'admin');
 CREATE TYPE product_type AS ENUM ( 'light_alcohol_product', 'strong_alcohol_product', 'common', 'mixer', 'grocery', 'generated');
 CREATE TYPE drink_type AS ENUM ( 'cocktail', 'shot', 'punch', 'generated' );
@@ -199,7 +163,7 @@ CREATE TABLE products (
     id SERIAL NOT NULL PRIMARY KEY,
 
     name TEXT NOT NULL,
-    href UNIQUE TEXT NOT NULL,
+    href TEXT UNIQUE NOT NULL,
     price FLOAT NOT NULL,
     img TEXT NOT NULL,
     volume FLOAT NOT NULL,
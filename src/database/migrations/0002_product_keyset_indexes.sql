@@ -0,0 +1,5 @@
+-- Indexes for keyset pagination of the product catalogue, see `filter_products_by_cursor`
+CREATE INDEX IF NOT EXISTS products_name_id_idx ON products (name, id);
+CREATE INDEX IF NOT EXISTS products_price_id_idx ON products (price, id);
+CREATE INDEX IF NOT EXISTS products_unit_price_id_idx ON products (unit_price, id);
+CREATE INDEX IF NOT EXISTS products_aer_id_idx ON products (aer, id);
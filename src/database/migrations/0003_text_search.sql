@@ -0,0 +1,22 @@
+-- Full-text and trigram search, see `search.rs`
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE EXTENSION IF NOT EXISTS unaccent;
+
+-- Finnish stemming with ä/ö/å folded, so that "jaloviina" and "jäloviina" are equal
+CREATE TEXT SEARCH CONFIGURATION finnish_unaccent ( COPY = finnish );
+ALTER TEXT SEARCH CONFIGURATION finnish_unaccent
+    ALTER MAPPING FOR hword, hword_part, word WITH unaccent, finnish_stem;
+
+-- `unaccent` itself is only STABLE, which can't be used in an index
+CREATE OR REPLACE FUNCTION search_normalize(value TEXT) RETURNS TEXT AS $$
+    SELECT lower(unaccent('unaccent'::regdictionary, value))
+$$ LANGUAGE SQL IMMUTABLE PARALLEL SAFE STRICT;
+
+CREATE INDEX IF NOT EXISTS products_name_fts_idx ON products USING GIN (to_tsvector('finnish_unaccent', name));
+CREATE INDEX IF NOT EXISTS products_name_trgm_idx ON products USING GIN (search_normalize(name) gin_trgm_ops);
+
+CREATE INDEX IF NOT EXISTS drink_incredients_name_fts_idx ON drink_incredients USING GIN (to_tsvector('finnish_unaccent', name));
+CREATE INDEX IF NOT EXISTS drink_incredients_name_trgm_idx ON drink_incredients USING GIN (search_normalize(name) gin_trgm_ops);
+
+CREATE INDEX IF NOT EXISTS drink_recipes_name_fts_idx ON drink_recipes USING GIN (to_tsvector('finnish_unaccent', name));
+CREATE INDEX IF NOT EXISTS drink_recipes_name_trgm_idx ON drink_recipes USING GIN (search_normalize(name) gin_trgm_ops);
@@ -0,0 +1,10 @@
+-- Units added to `UnitType`, their volumes are defined in `UnitType::ml`
+ALTER TYPE unit_type ADD VALUE IF NOT EXISTS 'dl';
+ALTER TYPE unit_type ADD VALUE IF NOT EXISTS 'l';
+ALTER TYPE unit_type ADD VALUE IF NOT EXISTS 'tbsp';
+ALTER TYPE unit_type ADD VALUE IF NOT EXISTS 'barspoon';
+ALTER TYPE unit_type ADD VALUE IF NOT EXISTS 'splash';
+ALTER TYPE unit_type ADD VALUE IF NOT EXISTS 'part';
+
+-- Volume of one `kpl` of the incredient, pieces have no volume without it
+ALTER TABLE drink_incredients ADD COLUMN IF NOT EXISTS piece_volume_ml FLOAT CHECK (piece_volume_ml > 0);
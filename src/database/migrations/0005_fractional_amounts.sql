@@ -0,0 +1,3 @@
+-- Amounts such as 1.5 oz or 0.75 oz, every integer amount is represented exactly
+ALTER TABLE recipe_parts ALTER COLUMN amount TYPE FLOAT USING amount::FLOAT;
+ALTER TABLE cabinet_mixers ALTER COLUMN amount TYPE FLOAT USING amount::FLOAT;
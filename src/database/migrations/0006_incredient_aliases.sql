@@ -0,0 +1,10 @@
+-- Alternative names of incredients, used to resolve imported recipes
+CREATE TABLE IF NOT EXISTS incredient_aliases (
+    id SERIAL PRIMARY KEY NOT NULL,
+    incredient_id INTEGER NOT NULL,
+    alias TEXT NOT NULL,
+
+    FOREIGN KEY (incredient_id) REFERENCES drink_incredients (id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS incredient_aliases_alias_idx ON incredient_aliases (LOWER(alias));
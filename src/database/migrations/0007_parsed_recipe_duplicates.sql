@@ -0,0 +1,17 @@
+-- Fingerprint of parsed recipes for duplicate detection, filled in by `index_parsed_recipes`.
+-- `incredient_names` are normalized with `search_normalize`, resolved to incredient names when possible
+ALTER TABLE parsed_drinks ADD COLUMN IF NOT EXISTS name TEXT;
+ALTER TABLE parsed_drinks ADD COLUMN IF NOT EXISTS incredient_names TEXT[];
+-- Best similarity to an existing recipe or an earlier parsed recipe
+ALTER TABLE parsed_drinks ADD COLUMN IF NOT EXISTS duplicate_score FLOAT;
+
+CREATE INDEX IF NOT EXISTS parsed_drinks_name_trgm_idx ON parsed_drinks USING GIN (search_normalize(name) gin_trgm_ops);
+
+-- Share of distinct elements in common, 0 when both are empty
+CREATE OR REPLACE FUNCTION array_jaccard(a TEXT[], b TEXT[]) RETURNS FLOAT AS $$
+    SELECT COALESCE(
+        (SELECT COUNT(*) FROM (SELECT unnest(a) INTERSECT SELECT unnest(b)) i)::FLOAT
+            / NULLIF((SELECT COUNT(*) FROM (SELECT unnest(a) UNION SELECT unnest(b)) u), 0),
+        0
+    )
+$$ LANGUAGE SQL IMMUTABLE PARALLEL SAFE;
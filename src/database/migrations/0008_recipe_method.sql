@@ -0,0 +1,22 @@
+-- Structured preparation method, see `RecipeMethod`
+CREATE TYPE method_action AS ENUM ('shake', 'stir', 'build', 'muddle', 'blend', 'top');
+CREATE TYPE glassware AS ENUM (
+    'coupe', 'martini', 'highball', 'collins', 'rocks', 'shot', 'wine', 'flute', 'hurricane', 'mug', 'punch_bowl'
+);
+CREATE TYPE ice_type AS ENUM ('none', 'cubed', 'crushed', 'large_cube');
+
+ALTER TABLE drink_recipes ADD COLUMN IF NOT EXISTS glassware glassware NULL DEFAULT NULL;
+ALTER TABLE drink_recipes ADD COLUMN IF NOT EXISTS ice ice_type NULL DEFAULT NULL;
+ALTER TABLE drink_recipes ADD COLUMN IF NOT EXISTS garnish TEXT NULL DEFAULT NULL;
+
+CREATE TABLE IF NOT EXISTS recipe_steps (
+    recipe_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    action method_action NOT NULL,
+    instruction TEXT NOT NULL DEFAULT '',
+
+    FOREIGN KEY (recipe_id) REFERENCES drink_recipes (id),
+    PRIMARY KEY (recipe_id, position)
+);
+
+CREATE INDEX IF NOT EXISTS recipe_steps_action_idx ON recipe_steps (action, recipe_id);
@@ -0,0 +1,8 @@
+-- Volume and ABV after dilution from ice, see `RecipeDilution`.
+-- Recipes start out undiluted, `recalculate_recipe_dilution` fills in the ones with a method.
+ALTER TABLE drink_recipes ADD COLUMN IF NOT EXISTS diluted_volume FLOAT NOT NULL DEFAULT 0;
+ALTER TABLE drink_recipes ADD COLUMN IF NOT EXISTS diluted_abv FLOAT NOT NULL DEFAULT 0;
+
+UPDATE drink_recipes SET diluted_volume = total_volume, diluted_abv = abv_average;
+
+CREATE INDEX IF NOT EXISTS drink_recipes_diluted_abv_id_idx ON drink_recipes (diluted_abv, id);
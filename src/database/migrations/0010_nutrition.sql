@@ -0,0 +1,16 @@
+-- Nutrition per 100 ml, or per piece for incredients measured in `kpl`.
+-- Calories exclude ethanol, which is derived from the ABV.
+ALTER TABLE drink_incredients ADD COLUMN IF NOT EXISTS kcal FLOAT NULL DEFAULT NULL;
+ALTER TABLE drink_incredients ADD COLUMN IF NOT EXISTS sugar FLOAT NULL DEFAULT NULL;
+ALTER TABLE drink_incredients ADD COLUMN IF NOT EXISTS carbohydrates FLOAT NULL DEFAULT NULL;
+
+-- Nutrition per serving of the recipe, the rest of the cached data is of the whole recipe.
+-- Recipes are one serving until the `recipe_servings` migration adds `servings`.
+ALTER TABLE drink_recipes ADD COLUMN IF NOT EXISTS kcal FLOAT NOT NULL DEFAULT 0;
+ALTER TABLE drink_recipes ADD COLUMN IF NOT EXISTS sugar FLOAT NOT NULL DEFAULT 0;
+ALTER TABLE drink_recipes ADD COLUMN IF NOT EXISTS carbohydrates FLOAT NOT NULL DEFAULT 0;
+
+-- Until incredients have nutrition, recipes only have the calories of their ethanol
+UPDATE drink_recipes SET kcal = standard_servings * 17.7 * 0.789 * 7;
+
+CREATE INDEX IF NOT EXISTS drink_recipes_kcal_id_idx ON drink_recipes (kcal, id);
@@ -0,0 +1,44 @@
+-- Snapshots of the name, type, info, parts and tags of a recipe after every change, see `RecipeRevision`
+CREATE TABLE IF NOT EXISTS recipe_revisions (
+    id SERIAL PRIMARY KEY,
+    recipe_id INTEGER NOT NULL,
+    revision INTEGER NOT NULL,
+    -- Unknown for the revisions recorded by this migration
+    author_id INTEGER NULL DEFAULT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT (NOW() at time zone 'utc'),
+
+    name TEXT NOT NULL,
+    type drink_type NOT NULL,
+    info TEXT NOT NULL,
+    tag_ids INTEGER[] NOT NULL DEFAULT '{}',
+
+    FOREIGN KEY (recipe_id) REFERENCES drink_recipes (id),
+    FOREIGN KEY (author_id) REFERENCES users (id),
+    UNIQUE (recipe_id, revision)
+);
+
+CREATE TABLE IF NOT EXISTS recipe_revision_parts (
+    revision_id INTEGER NOT NULL,
+    incredient_id INTEGER NOT NULL,
+    amount FLOAT NOT NULL,
+    unit unit_type NOT NULL,
+
+    FOREIGN KEY (revision_id) REFERENCES recipe_revisions (id),
+    FOREIGN KEY (incredient_id) REFERENCES drink_incredients (id),
+    PRIMARY KEY (revision_id, incredient_id)
+);
+
+-- History starts from the current state of every recipe
+INSERT INTO recipe_revisions (recipe_id, revision, name, type, info, tag_ids)
+SELECT r.id, 1, r.name, r.type, r.info,
+    ARRAY(SELECT m.tag_id FROM recipe_tags_map m WHERE m.recipe_id = r.id ORDER BY m.tag_id)
+FROM drink_recipes r
+ON CONFLICT DO NOTHING;
+
+INSERT INTO recipe_revision_parts (revision_id, incredient_id, amount, unit)
+SELECT v.id, rp.incredient_id, rp.amount, rp.unit
+FROM recipe_revisions v
+INNER JOIN drink_recipes r ON r.id = v.recipe_id
+INNER JOIN recipe_parts rp ON rp.recipe_id = r.recipe_id
+WHERE v.revision = 1
+ON CONFLICT DO NOTHING;
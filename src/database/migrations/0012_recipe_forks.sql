@@ -0,0 +1,8 @@
+-- Forks link a recipe to the recipe it was copied from, see `fork_recipe`.
+-- Names only need to be unique per author, so users can keep their own variant under the same name.
+ALTER TABLE drink_recipes ADD COLUMN IF NOT EXISTS parent_id INTEGER NULL DEFAULT NULL REFERENCES drink_recipes(id);
+
+ALTER TABLE drink_recipes DROP CONSTRAINT IF EXISTS drink_recipes_name_key;
+ALTER TABLE drink_recipes ADD CONSTRAINT drink_recipes_author_id_name_key UNIQUE (author_id, name);
+
+CREATE INDEX IF NOT EXISTS drink_recipes_parent_id_idx ON drink_recipes (parent_id);
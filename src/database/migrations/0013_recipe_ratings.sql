@@ -0,0 +1,26 @@
+-- One rating per user per recipe, with an optional review
+CREATE TABLE IF NOT EXISTS recipe_ratings (
+    recipe_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    score INTEGER NOT NULL CHECK (score BETWEEN 1 AND 5),
+    review TEXT NULL DEFAULT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT (NOW() at time zone 'utc'),
+    updated_at TIMESTAMP NOT NULL DEFAULT (NOW() at time zone 'utc'),
+
+    PRIMARY KEY (recipe_id, user_id),
+    FOREIGN KEY (recipe_id) REFERENCES drink_recipes(id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX IF NOT EXISTS recipe_ratings_user_id_idx ON recipe_ratings (user_id);
+
+-- Aggregates of the ratings, see `update_recipe_rating`.
+-- Unrated recipes have the bayesian average of `RATING_PRIOR_MEAN`.
+ALTER TABLE drink_recipes ADD COLUMN IF NOT EXISTS rating_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE drink_recipes ADD COLUMN IF NOT EXISTS rating_average FLOAT NOT NULL DEFAULT 0;
+ALTER TABLE drink_recipes ADD COLUMN IF NOT EXISTS rating_bayesian FLOAT NOT NULL DEFAULT 3;
+
+CREATE INDEX IF NOT EXISTS drink_recipes_rating_bayesian_id_idx ON drink_recipes (rating_bayesian, id);
+
+-- Favorites used to be counted on the recipe with the id of the user
+UPDATE drink_recipes r SET favorite_count = (SELECT COUNT(*) FROM user_favorites f WHERE f.drink_id = r.id);
@@ -0,0 +1,18 @@
+-- Public profile of a user, see `set_user_profile`
+ALTER TABLE users ADD COLUMN IF NOT EXISTS display_name TEXT NULL DEFAULT NULL;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS bio TEXT NULL DEFAULT NULL;
+
+-- An existing account can't become the placeholder, since its content would be mixed with the
+-- content of deleted users
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM users WHERE username = '[deleted]' AND password != '') THEN
+        RAISE EXCEPTION 'User "[deleted]" already exists, rename it before running this migration';
+    END IF;
+END
+$$;
+
+-- Placeholder author of the recipes and incredients of deleted users, see `DELETED_USERNAME`.
+-- The password isn't a valid hash, so nobody can log in as it.
+INSERT INTO users (username, password) VALUES ('[deleted]', '') ON CONFLICT (username) DO NOTHING;
+
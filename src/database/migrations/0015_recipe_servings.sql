@@ -0,0 +1,4 @@
+-- Number of servings the recipe as written makes, such as the glasses of a punch.
+-- The nutrition of recipes added by the `nutrition` migration is per serving, so a recipe
+-- is no longer assumed to be one serving. Existing recipes make one serving.
+ALTER TABLE drink_recipes ADD COLUMN IF NOT EXISTS servings INT NOT NULL DEFAULT 1 CHECK (servings > 0);
@@ -0,0 +1,10 @@
+-- Recipes with `kpl` parts of incredients without a volume per piece. Those parts were stored
+-- before the `units` migration and count as 0 ml, so the volume, strength and prices are too low.
+ALTER TABLE drink_recipes ADD COLUMN IF NOT EXISTS unknown_piece_volume BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE drink_recipes r SET unknown_piece_volume = TRUE
+WHERE EXISTS (
+    SELECT 1 FROM recipe_parts rp
+    INNER JOIN drink_incredients d ON d.id = rp.incredient_id
+    WHERE rp.recipe_id = r.recipe_id AND rp.unit = 'kpl' AND d.piece_volume_ml IS NULL
+);
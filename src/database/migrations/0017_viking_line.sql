@@ -0,0 +1,2 @@
+-- Retailer of `Retailer::VikingLine`, which the baseline is missing
+ALTER TYPE retailer ADD VALUE IF NOT EXISTS 'viking_line';
@@ -1,4 +1,10 @@
 use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, QueryBuilder};
+
+use crate::{
+    constants::{MAX_PAGE_SIZE, MIN_PAGE_SIZE},
+    jwt::{generate_page_cursor, parse_page_cursor},
+};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PageContext<T> {
@@ -61,3 +67,160 @@ impl<T> PageContext<T> {
         }
     }
 }
+
+/// Value of the sort key at a cursor position
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CursorValue {
+    Number(f64),
+    Text(String),
+}
+
+/// Position in a keyset paginated listing. Handed out to clients only as a signed token.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Cursor {
+    /// Name of the ordering the cursor was created for
+    pub order: String,
+    pub value: CursorValue,
+    pub id: i32,
+    /// Whether the cursor points to the previous page
+    pub backwards: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CursorRequest {
+    /// `next_cursor` or `prev_cursor` of a previous page, `None` for the first page
+    pub cursor: Option<String>,
+    pub page_size: Option<i64>,
+    /// Counting every matching row is expensive on large tables, so it has to be requested
+    pub include_total: bool,
+}
+
+impl CursorRequest {
+    pub fn page_size(&self, default: i64) -> i64 {
+        self.page_size
+            .unwrap_or(default)
+            .clamp(MIN_PAGE_SIZE, MAX_PAGE_SIZE)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CursorPage<T> {
+    pub rows: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub prev_cursor: Option<String>,
+    pub total_rows: Option<i64>,
+    pub page_size: i64,
+}
+
+/// Sort key of a keyset paginated query. Rows are ordered by `column` and then by `id_column`,
+/// so that the position of a row is unique and stable while other rows are inserted.
+pub struct Keyset<T> {
+    pub order: &'static str,
+    pub column: &'static str,
+    pub id_column: &'static str,
+    pub descending: bool,
+    pub value: fn(&T) -> CursorValue,
+    pub id: fn(&T) -> i32,
+}
+
+impl<T> Keyset<T> {
+    /// Parses and validates the cursor of a request against this ordering
+    pub fn parse_cursor(&self, request: &CursorRequest) -> Result<Option<Cursor>, potion::Error> {
+        let Some(token) = &request.cursor else {
+            return Ok(None);
+        };
+
+        let cursor = parse_page_cursor(token)?;
+        if cursor.order != self.order {
+            return Err(
+                potion::HtmlError::InvalidRequest.new("Cursor was created for another ordering")
+            );
+        }
+
+        Ok(Some(cursor))
+    }
+
+    /// Pushes `AND (column, id) > (value, id)` for the cursor, and the matching `ORDER BY` and `LIMIT`.
+    /// One row more than the page size is fetched to detect whether there is a following page.
+    pub fn push_page<'args>(
+        &self,
+        cursor: Option<&Cursor>,
+        page_size: i64,
+        builder: &mut QueryBuilder<'args, Postgres>,
+    ) {
+        let backwards = cursor.map(|c| c.backwards).unwrap_or(false);
+        let descending = self.descending != backwards;
+
+        if let Some(cursor) = cursor {
+            let comparison = if descending { "<" } else { ">" };
+            builder.push(format!(
+                " AND ({}, {}) {comparison} (",
+                self.column, self.id_column
+            ));
+            match &cursor.value {
+                CursorValue::Number(value) => builder.push_bind(*value),
+                CursorValue::Text(value) => builder.push_bind(value.clone()),
+            };
+            builder.push(", ").push_bind(cursor.id).push(")");
+        }
+
+        let direction = if descending { "DESC" } else { "ASC" };
+        builder
+            .push(format!(
+                " ORDER BY {} {direction}, {} {direction} LIMIT ",
+                self.column, self.id_column
+            ))
+            .push_bind(page_size + 1);
+    }
+
+    fn cursor(&self, row: &T, backwards: bool) -> String {
+        generate_page_cursor(&Cursor {
+            order: self.order.to_owned(),
+            value: (self.value)(row),
+            id: (self.id)(row),
+            backwards,
+        })
+    }
+}
+
+impl<T> CursorPage<T> {
+    /// Builds the page from rows fetched with `Keyset::push_page`
+    pub fn from_rows(
+        mut rows: Vec<T>,
+        keyset: &Keyset<T>,
+        cursor: Option<&Cursor>,
+        page_size: i64,
+        total_rows: Option<i64>,
+    ) -> Self {
+        let backwards = cursor.map(|c| c.backwards).unwrap_or(false);
+        let has_more = rows.len() as i64 > page_size;
+        rows.truncate(page_size.max(0) as usize);
+
+        if backwards {
+            rows.reverse();
+        }
+
+        let (has_next, has_prev) = match backwards {
+            false => (has_more, cursor.is_some()),
+            true => (true, has_more),
+        };
+
+        let next_cursor = rows
+            .last()
+            .filter(|_| has_next)
+            .map(|row| keyset.cursor(row, false));
+        let prev_cursor = rows
+            .first()
+            .filter(|_| has_prev)
+            .map(|row| keyset.cursor(row, true));
+
+        Self {
+            rows,
+            next_cursor,
+            prev_cursor,
+            total_rows,
+            page_size,
+        }
+    }
+}
@@ -0,0 +1,618 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    schema::{parse_amount, IncredientName, MatchKind, UnitType},
+    srs::StandardRecipePart,
+};
+
+/*
+Tolerant parser for ingredient lists pasted as free text, in Finnish or English:
+"4 cl vodka, 2cl lime juice, dash of bitters, top with soda"
+"vodka 4 cl; puolitoista cl limettimehua; täytä soodavedellä"
+*/
+
+const UNIT_ALIASES: &[(&str, UnitType)] = &[
+    ("cl", UnitType::Cl),
+    ("cls", UnitType::Cl),
+    ("centiliter", UnitType::Cl),
+    ("centiliters", UnitType::Cl),
+    ("centilitre", UnitType::Cl),
+    ("centilitres", UnitType::Cl),
+    ("senttilitra", UnitType::Cl),
+    ("senttilitraa", UnitType::Cl),
+    ("ml", UnitType::Ml),
+    ("milliliter", UnitType::Ml),
+    ("milliliters", UnitType::Ml),
+    ("millilitre", UnitType::Ml),
+    ("millilitres", UnitType::Ml),
+    ("millilitra", UnitType::Ml),
+    ("millilitraa", UnitType::Ml),
+    ("dl", UnitType::Dl),
+    ("deciliter", UnitType::Dl),
+    ("deciliters", UnitType::Dl),
+    ("desilitra", UnitType::Dl),
+    ("desilitraa", UnitType::Dl),
+    ("l", UnitType::L),
+    ("liter", UnitType::L),
+    ("liters", UnitType::L),
+    ("litre", UnitType::L),
+    ("litres", UnitType::L),
+    ("litra", UnitType::L),
+    ("litraa", UnitType::L),
+    ("oz", UnitType::Oz),
+    ("ounce", UnitType::Oz),
+    ("ounces", UnitType::Oz),
+    ("unssi", UnitType::Oz),
+    ("unssia", UnitType::Oz),
+    ("tl", UnitType::Tl),
+    ("tsp", UnitType::Tl),
+    ("teaspoon", UnitType::Tl),
+    ("teaspoons", UnitType::Tl),
+    ("teelusikka", UnitType::Tl),
+    ("teelusikallinen", UnitType::Tl),
+    ("rkl", UnitType::Tbsp),
+    ("tbsp", UnitType::Tbsp),
+    ("tablespoon", UnitType::Tbsp),
+    ("tablespoons", UnitType::Tbsp),
+    ("ruokalusikka", UnitType::Tbsp),
+    ("ruokalusikallinen", UnitType::Tbsp),
+    ("barspoon", UnitType::Barspoon),
+    ("barspoons", UnitType::Barspoon),
+    ("baarilusikka", UnitType::Barspoon),
+    ("baarilusikallinen", UnitType::Barspoon),
+    ("dash", UnitType::Dash),
+    ("dashes", UnitType::Dash),
+    ("drop", UnitType::Dash),
+    ("drops", UnitType::Dash),
+    ("tippa", UnitType::Dash),
+    ("tippaa", UnitType::Dash),
+    ("splash", UnitType::Splash),
+    ("splashes", UnitType::Splash),
+    ("tilkka", UnitType::Splash),
+    ("tilkkaa", UnitType::Splash),
+    ("loraus", UnitType::Splash),
+    ("part", UnitType::Part),
+    ("parts", UnitType::Part),
+    ("osa", UnitType::Part),
+    ("osaa", UnitType::Part),
+    ("kpl", UnitType::Kpl),
+    ("pc", UnitType::Kpl),
+    ("pcs", UnitType::Kpl),
+    ("piece", UnitType::Kpl),
+    ("pieces", UnitType::Kpl),
+    ("kappale", UnitType::Kpl),
+    ("kappaletta", UnitType::Kpl),
+];
+
+const NUMBER_WORDS: &[(&str, f64)] = &[
+    ("a", 1.),
+    ("an", 1.),
+    ("one", 1.),
+    ("two", 2.),
+    ("three", 3.),
+    ("four", 4.),
+    ("five", 5.),
+    ("six", 6.),
+    ("seven", 7.),
+    ("eight", 8.),
+    ("nine", 9.),
+    ("ten", 10.),
+    ("twelve", 12.),
+    ("half", 0.5),
+    ("quarter", 0.25),
+    ("yksi", 1.),
+    ("kaksi", 2.),
+    ("kolme", 3.),
+    ("neljä", 4.),
+    ("viisi", 5.),
+    ("kuusi", 6.),
+    ("seitsemän", 7.),
+    ("kahdeksan", 8.),
+    ("yhdeksän", 9.),
+    ("kymmenen", 10.),
+    ("puoli", 0.5),
+    ("puolikas", 0.5),
+    ("puolitoista", 1.5),
+    ("pari", 2.),
+];
+
+/// Words between the amount and the name, such as "of" in "dash of bitters"
+const FILLER_WORDS: &[&str] = &["of", "with", "a", "an"];
+
+const TOP_PREFIXES: &[&str] = &[
+    "top up with",
+    "top with",
+    "top up",
+    "top",
+    "fill up with",
+    "fill with",
+    "täytä",
+    "täydennä",
+];
+
+const GARNISH_WORDS: &[&str] = &[
+    "for garnish",
+    "to garnish",
+    "garnish with",
+    "garnish",
+    "koristeeksi",
+    "koristeluun",
+];
+
+/// Last words of a name that make the part a garnish
+const GARNISH_CUTS: &[&str] = &[
+    "wheel",
+    "wheels",
+    "wedge",
+    "wedges",
+    "twist",
+    "peel",
+    "slice",
+    "slices",
+    "viipale",
+    "viipaleita",
+    "lohko",
+    "lohkoja",
+    "siivu",
+    "kierre",
+];
+
+const OPTIONAL_WORDS: &[&str] = &["(optional)", "optional", "halutessa", "valinnainen"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ParsedIngredient {
+    /// Text the item was parsed from
+    pub source: String,
+    /// `None` for amounts left to taste, such as "top with soda"
+    pub amount: Option<f64>,
+    pub unit: Option<UnitType>,
+    pub name: String,
+    pub optional: bool,
+    pub garnish: bool,
+    /// Between 0 and 1, how certain the parser is of the amount, unit and name
+    pub confidence: f64,
+}
+
+impl ParsedIngredient {
+    /// Missing amounts and units become a single piece
+    pub fn to_part(&self, incredient_name: &str) -> StandardRecipePart {
+        StandardRecipePart {
+            amount: self.amount.unwrap_or(1.),
+            unit: self.unit.clone().unwrap_or(UnitType::Kpl),
+            incredient_name: incredient_name.to_owned(),
+            optional: self.optional,
+            garnish: self.garnish,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolvedIngredient {
+    pub item: ParsedIngredient,
+    /// Best match, if it's similar enough to the parsed name
+    pub incredient: Option<IncredientName>,
+    pub kind: Option<MatchKind>,
+    /// Between 0 and 1, similarity of the best match
+    pub match_score: f64,
+    /// Closest incredients, best first
+    pub candidates: Vec<IncredientName>,
+    /// Parse confidence multiplied by the match score
+    pub confidence: f64,
+}
+
+/// Splits the text into items on new lines, semicolons and commas, except decimal commas,
+/// and parses each of them. Items without a name are left out.
+pub fn parse_ingredient_list(text: &str) -> Vec<ParsedIngredient> {
+    split_items(text)
+        .iter()
+        .filter_map(|item| parse_ingredient(item))
+        .collect()
+}
+
+fn split_items(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut items = vec![];
+    let mut item = String::new();
+
+    for (i, c) in chars.iter().enumerate() {
+        let decimal_comma = *c == ','
+            && i > 0
+            && chars[i - 1].is_ascii_digit()
+            && chars.get(i + 1).is_some_and(|next| next.is_ascii_digit());
+
+        match c {
+            '\n' | ';' | '•' => items.push(std::mem::take(&mut item)),
+            ',' if !decimal_comma => items.push(std::mem::take(&mut item)),
+            _ => item.push(*c),
+        }
+    }
+    items.push(item);
+
+    items
+        .into_iter()
+        .map(|item| {
+            item.trim()
+                .trim_start_matches(['-', '*', '–'])
+                .trim()
+                .to_owned()
+        })
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Parses a single item, such as "4 cl vodka", "dash of bitters" or "vodka 4 cl"
+pub fn parse_ingredient(item: &str) -> Option<ParsedIngredient> {
+    let mut text = format!(" {} ", item.to_lowercase());
+    let mut confidence: f64 = 1.;
+
+    let optional = remove_words(&mut text, OPTIONAL_WORDS);
+    let garnish = remove_words(&mut text, GARNISH_WORDS);
+
+    let trimmed = text.trim().to_owned();
+    if let Some(rest) = TOP_PREFIXES
+        .iter()
+        .find_map(|prefix| trimmed.strip_prefix(prefix))
+        .filter(|rest| rest.is_empty() || rest.starts_with(' '))
+    {
+        let name = clean_name(&tokens(rest));
+        return (!name.is_empty()).then(|| ParsedIngredient {
+            source: item.to_owned(),
+            amount: None,
+            unit: None,
+            name,
+            optional,
+            garnish,
+            confidence: 0.8,
+        });
+    }
+
+    let mut words = tokens(&text);
+
+    let mut measure = take_measure(&mut words);
+    if measure.is_none() {
+        // Finnish lists often have the amount last, "vodka 4 cl"
+        words.reverse();
+        measure = take_measure_reversed(&mut words);
+        words.reverse();
+    }
+
+    // "lime wedge" is a garnish of one lime
+    let cut = words.len() > 1
+        && words
+            .last()
+            .is_some_and(|word| GARNISH_CUTS.contains(&word.as_str()));
+    if cut {
+        words.pop();
+    }
+
+    let (amount, unit) = match measure {
+        Some(Measure {
+            amount,
+            unit,
+            confidence: measure_confidence,
+        }) => {
+            confidence *= measure_confidence;
+            match unit {
+                Some(unit) => (Some(amount), Some(unit)),
+                // "2 limes"
+                None => {
+                    confidence *= 0.85;
+                    (Some(amount), Some(UnitType::Kpl))
+                }
+            }
+        }
+        None if cut => {
+            confidence *= 0.8;
+            (Some(1.), Some(UnitType::Kpl))
+        }
+        None => {
+            confidence *= 0.5;
+            (None, None)
+        }
+    };
+
+    let name = clean_name(&words);
+    if name.is_empty() {
+        return None;
+    }
+
+    Some(ParsedIngredient {
+        source: item.to_owned(),
+        amount,
+        unit,
+        name,
+        optional,
+        garnish: garnish || cut,
+        confidence,
+    })
+}
+
+struct Measure {
+    amount: f64,
+    unit: Option<UnitType>,
+    confidence: f64,
+}
+
+/// Removes the first of `words` found in `text`, returning whether one was found
+fn remove_words(text: &mut String, words: &[&str]) -> bool {
+    let found = words.iter().find_map(|word| {
+        let pattern = format!(" {word} ");
+        text.find(&pattern).map(|index| (index, pattern.len()))
+    });
+
+    match found {
+        Some((index, len)) => {
+            text.replace_range(index..index + len, " ");
+            true
+        }
+        None => false,
+    }
+}
+
+/// Splits on whitespace, and between a number and a unit written together, such as "4cl"
+fn tokens(text: &str) -> Vec<String> {
+    let mut words = vec![];
+
+    for word in text.split_whitespace() {
+        let word = word.trim_matches(|c: char| c == '(' || c == ')' || c == ':' || c == '.');
+        if word.is_empty() {
+            continue;
+        }
+
+        let split = word
+            .char_indices()
+            .find(|(i, c)| *i > 0 && c.is_alphabetic())
+            .map(|(i, _)| i)
+            .filter(|i| parse_amount(&word[..*i]).is_some());
+
+        match split {
+            Some(i) => {
+                words.push(word[..i].to_owned());
+                words.push(word[i..].to_owned());
+            }
+            None => words.push(word.to_owned()),
+        }
+    }
+
+    words
+}
+
+fn unit_alias(word: &str) -> Option<UnitType> {
+    let word = word.trim_end_matches('.');
+    UNIT_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == word)
+        .map(|(_, unit)| unit.clone())
+}
+
+fn number_word(word: &str) -> Option<f64> {
+    NUMBER_WORDS
+        .iter()
+        .find(|(number, _)| *number == word)
+        .map(|(_, value)| *value)
+}
+
+/// Amount such as "1", "1,5", "¾", "1-2" or "1 1/2", returning the number of words used
+fn amount_at(words: &[String]) -> Option<(f64, usize, f64)> {
+    let first = words.first()?;
+
+    if let Some((low, high)) = first.split_once('-') {
+        let (low, high) = (parse_amount(low)?, parse_amount(high)?);
+        return Some(((low + high) / 2., 1, 0.8));
+    }
+
+    if let Some(amount) = parse_amount(first) {
+        let fraction = words
+            .get(1)
+            .filter(|word| word.contains('/') || !word.chars().any(|c| c.is_ascii_alphanumeric()))
+            .and_then(|word| parse_amount(word));
+        return match fraction {
+            Some(fraction) if fraction < 1. => Some((amount + fraction, 2, 1.)),
+            _ => Some((amount, 1, 1.)),
+        };
+    }
+
+    let amount = number_word(first)?;
+    // "half a lime", "puoli limeä"
+    match (amount < 1., words.get(1).map(|word| word.as_str())) {
+        (true, Some("a" | "an")) => Some((amount, 2, 0.9)),
+        _ => Some((amount, 1, 0.9)),
+    }
+}
+
+fn take_measure(words: &mut Vec<String>) -> Option<Measure> {
+    if let Some((amount, used, confidence)) = amount_at(words) {
+        let unit = words.get(used).and_then(|word| unit_alias(word));
+        let (unit, used) = match (unit, words.get(used).map(|word| word.as_str())) {
+            (Some(unit), _) => (Some(unit), used + 1),
+            // "bar spoon"
+            (None, Some("bar")) if words.get(used + 1).is_some_and(|w| w.starts_with("spoon")) => {
+                (Some(UnitType::Barspoon), used + 2)
+            }
+            _ => (None, used),
+        };
+
+        // A lone article without a unit is part of the name, "a lime" is still one lime
+        words.drain(..used);
+        return Some(Measure {
+            amount,
+            unit,
+            confidence,
+        });
+    }
+
+    // "dash of bitters"
+    let unit = unit_alias(words.first()?)?;
+    words.remove(0);
+    Some(Measure {
+        amount: 1.,
+        unit: Some(unit),
+        confidence: 0.9,
+    })
+}
+
+/// `words` are in reverse order, so the unit comes before the amount
+fn take_measure_reversed(words: &mut Vec<String>) -> Option<Measure> {
+    let unit = words.first().and_then(|word| unit_alias(word));
+    let start = match unit {
+        Some(_) => 1,
+        None => 0,
+    };
+
+    let word = words.get(start)?;
+    let amount = parse_amount(word).or_else(|| number_word(word))?;
+    if words.len() <= start + 1 {
+        return None;
+    }
+
+    words.drain(..start + 1);
+    Some(Measure {
+        amount,
+        unit,
+        confidence: 0.9,
+    })
+}
+
+fn clean_name(words: &[String]) -> String {
+    let start = words
+        .iter()
+        .position(|word| !FILLER_WORDS.contains(&word.as_str()))
+        .unwrap_or(words.len());
+
+    words[start..].join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(item: &str) -> (Option<f64>, Option<UnitType>, String, bool, bool) {
+        let parsed = parse_ingredient(item).unwrap();
+        (
+            parsed.amount,
+            parsed.unit,
+            parsed.name,
+            parsed.optional,
+            parsed.garnish,
+        )
+    }
+
+    #[test]
+    fn parses_amount_unit_and_name() {
+        assert_eq!(
+            parsed("4 cl vodka"),
+            (
+                Some(4.),
+                Some(UnitType::Cl),
+                String::from("vodka"),
+                false,
+                false
+            )
+        );
+        assert_eq!(
+            parsed("2cl Lime Juice"),
+            (
+                Some(2.),
+                Some(UnitType::Cl),
+                String::from("lime juice"),
+                false,
+                false
+            )
+        );
+        assert_eq!(
+            parsed("1 1/2 oz Jaloviina"),
+            (
+                Some(1.5),
+                Some(UnitType::Oz),
+                String::from("jaloviina"),
+                false,
+                false
+            )
+        );
+        assert_eq!(parse_ingredient("4 cl vodka").unwrap().confidence, 1.);
+    }
+
+    #[test]
+    fn parses_finnish_amounts_after_the_name() {
+        assert_eq!(
+            parsed("vodka 4 cl"),
+            (
+                Some(4.),
+                Some(UnitType::Cl),
+                String::from("vodka"),
+                false,
+                false
+            )
+        );
+        assert_eq!(parsed("puolitoista cl limettimehua").0, Some(1.5));
+    }
+
+    #[test]
+    fn parses_number_words_and_ranges() {
+        assert_eq!(
+            parsed("dash of bitters"),
+            (
+                Some(1.),
+                Some(UnitType::Dash),
+                String::from("bitters"),
+                false,
+                false
+            )
+        );
+        assert_eq!(
+            parsed("half a lime"),
+            (
+                Some(0.5),
+                Some(UnitType::Kpl),
+                String::from("lime"),
+                false,
+                false
+            )
+        );
+        assert_eq!(parsed("1-2 tl sokeria").0, Some(1.5));
+        assert!(parse_ingredient("1-2 tl sokeria").unwrap().confidence < 1.);
+    }
+
+    #[test]
+    fn parses_markers() {
+        assert_eq!(
+            parsed("lime wedge for garnish"),
+            (
+                Some(1.),
+                Some(UnitType::Kpl),
+                String::from("lime"),
+                false,
+                true
+            )
+        );
+        assert_eq!(
+            parsed("1,5 dl cola (optional)"),
+            (
+                Some(1.5),
+                Some(UnitType::Dl),
+                String::from("cola"),
+                true,
+                false
+            )
+        );
+        assert_eq!(
+            parsed("top with soda"),
+            (None, None, String::from("soda"), false, false)
+        );
+    }
+
+    #[test]
+    fn rejects_items_without_a_name() {
+        assert!(parse_ingredient("4 cl").is_none());
+        assert!(parse_ingredient("top with").is_none());
+    }
+
+    #[test]
+    fn splits_lists_except_decimal_commas() {
+        let items = parse_ingredient_list("- 1,5 cl vodka\n- 2 cl lime juice, soda; ");
+        let names: Vec<&str> = items.iter().map(|item| item.name.as_str()).collect();
+
+        assert_eq!(names, vec!["vodka", "lime juice", "soda"]);
+        assert_eq!(items[0].amount, Some(1.5));
+    }
+}
@@ -1,6 +1,6 @@
 use std::collections::BTreeMap;
 
-use super::error::TypeError;
+use super::error::{TypeError, UnitError};
 use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -8,7 +8,10 @@ use sqlx::{postgres::PgRow, Decode, FromRow, Postgres, Row};
 
 use chrono::serde::ts_seconds;
 
-use crate::StandardRecipeSyntax;
+use crate::{
+    interchange::RecipeDocument, StandardRecipeSyntax, BLENDED_DILUTION, BUILT_DILUTION, PART_ML,
+    SHAKEN_DILUTION, STIRRED_DILUTION,
+};
 
 pub type Uuid = i32;
 
@@ -92,9 +95,53 @@ pub enum UnitType {
     Cl,
     Ml,
     Oz,
+    /// Piece, its volume depends on the incredient
     Kpl,
+    /// Teaspoon
     Tl,
     Dash,
+    Dl,
+    L,
+    /// Tablespoon
+    Tbsp,
+    Barspoon,
+    Splash,
+    /// Relative amount, always `PART_ML`
+    Part,
+}
+
+impl UnitType {
+    pub const ALL: &'static [UnitType] = &[
+        UnitType::Cl,
+        UnitType::Ml,
+        UnitType::Oz,
+        UnitType::Kpl,
+        UnitType::Tl,
+        UnitType::Dash,
+        UnitType::Dl,
+        UnitType::L,
+        UnitType::Tbsp,
+        UnitType::Barspoon,
+        UnitType::Splash,
+        UnitType::Part,
+    ];
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            UnitType::Cl => "cl",
+            UnitType::Ml => "ml",
+            UnitType::Oz => "oz",
+            UnitType::Kpl => "kpl",
+            UnitType::Tl => "tl",
+            UnitType::Dash => "dash",
+            UnitType::Dl => "dl",
+            UnitType::L => "l",
+            UnitType::Tbsp => "tbsp",
+            UnitType::Barspoon => "barspoon",
+            UnitType::Splash => "splash",
+            UnitType::Part => "part",
+        }
+    }
 }
 
 impl TryFrom<Value> for UnitType {
@@ -102,15 +149,11 @@ impl TryFrom<Value> for UnitType {
 
     fn try_from(value: Value) -> Result<Self, Self::Error> {
         match value.as_str() {
-            Some(value) => match value {
-                "cl" => Ok(Self::Cl),
-                "ml" => Ok(Self::Ml),
-                "oz" => Ok(Self::Oz),
-                "kpl" => Ok(Self::Kpl),
-                "tl" => Ok(Self::Tl),
-                "dash" => Ok(Self::Dash),
-                _ => Err(TypeError::new("Invalid variant")),
-            },
+            Some(value) => UnitType::ALL
+                .iter()
+                .find(|unit| unit.as_str() == value)
+                .cloned()
+                .ok_or(TypeError::new("Invalid variant")),
             None => return Err(TypeError::new("Failed to parse value as string")),
         }
     }
@@ -118,14 +161,7 @@ impl TryFrom<Value> for UnitType {
 
 impl ToString for UnitType {
     fn to_string(&self) -> String {
-        match self {
-            UnitType::Cl => String::from("cl"),
-            UnitType::Ml => String::from("ml"),
-            UnitType::Oz => String::from("oz"),
-            UnitType::Kpl => String::from("kpl"),
-            UnitType::Tl => String::from("tl"),
-            UnitType::Dash => String::from("dash"),
-        }
+        String::from(self.as_str())
     }
 }
 
@@ -135,47 +171,204 @@ impl Default for UnitType {
     }
 }
 
+/// Volumes of the units that aren't fixed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct UnitVolumes {
+    /// Volume of one `kpl` of the incredient in ml
+    pub piece_ml: Option<f64>,
+}
+
 impl UnitType {
-    /* conversion table */
-    pub fn convert(&self, value: f64, other: Self) -> (Self, f64) {
-        match (&self, &other) {
-            (UnitType::Cl, UnitType::Cl) => (other, value),
-            (UnitType::Cl, UnitType::Ml) => (other, value * 10.),
-            (UnitType::Cl, UnitType::Oz) => (other, value * 0.338140227),
-            (UnitType::Cl, UnitType::Kpl) => (other, 0.),
-            (UnitType::Ml, UnitType::Cl) => (other, value * 0.1),
-            (UnitType::Ml, UnitType::Ml) => (other, value),
-            (UnitType::Ml, UnitType::Oz) => (other, value * 0.0338140227),
-            (UnitType::Ml, UnitType::Kpl) => (other, 0.),
-            (UnitType::Oz, UnitType::Cl) => (other, value * 2.95735296),
-            (UnitType::Oz, UnitType::Ml) => (other, value * 29.5735296),
-            (UnitType::Oz, UnitType::Oz) => (other, value),
-            (UnitType::Oz, UnitType::Kpl) => (other, 0.),
-            (UnitType::Kpl, UnitType::Cl) => (other, 0.),
-            (UnitType::Kpl, UnitType::Ml) => (other, 0.),
-            (UnitType::Kpl, UnitType::Oz) => (other, 0.),
-            (UnitType::Kpl, UnitType::Kpl) => (other, value),
-            (UnitType::Cl, UnitType::Tl) => (other, value * 0.676280454),
-            (UnitType::Cl, UnitType::Dash) => (other, value * (0.625 / 10.)),
-            (UnitType::Ml, UnitType::Tl) => (other, value * 0.0676280454),
-            (UnitType::Ml, UnitType::Dash) => (other, value * 0.625),
-            (UnitType::Oz, UnitType::Tl) => (other, value * 2.),
-            (UnitType::Oz, UnitType::Dash) => (other, value * 29.5735296 * 0.625),
-            (UnitType::Kpl, UnitType::Tl) => (other, 0.),
-            (UnitType::Kpl, UnitType::Dash) => (other, 0.),
-            (UnitType::Tl, UnitType::Cl) => (other, 0.),
-            (UnitType::Tl, UnitType::Ml) => (other, 0.),
-            (UnitType::Tl, UnitType::Oz) => (other, 0.),
-            (UnitType::Tl, UnitType::Kpl) => (other, 0.),
-            (UnitType::Tl, UnitType::Tl) => (other, value),
-            (UnitType::Tl, UnitType::Dash) => (other, value * 0.),
-            (UnitType::Dash, UnitType::Cl) => (other, value * 0.3080575996094 * 10.),
-            (UnitType::Dash, UnitType::Ml) => (other, value * 0.3080575996094),
-            (UnitType::Dash, UnitType::Oz) => (other, value * 0.01084211177902),
-            (UnitType::Dash, UnitType::Kpl) => (other, 0.),
-            (UnitType::Dash, UnitType::Tl) => (other, value * 0.02053717330729),
-            (UnitType::Dash, UnitType::Dash) => (other, value),
+    /// Volume of one unit in ml. `None` for `kpl`, which depends on `UnitVolumes`
+    pub fn ml(&self) -> Option<f64> {
+        match self {
+            UnitType::Ml => Some(1.),
+            UnitType::Cl => Some(10.),
+            UnitType::Dl => Some(100.),
+            UnitType::L => Some(1000.),
+            UnitType::Oz => Some(29.5735296),
+            UnitType::Tl => Some(5.),
+            UnitType::Tbsp => Some(15.),
+            UnitType::Barspoon => Some(5.),
+            UnitType::Dash => Some(0.3080575996094),
+            UnitType::Splash => Some(29.5735296 / 4.),
+            UnitType::Part => Some(PART_ML),
+            UnitType::Kpl => None,
+        }
+    }
+
+    fn ml_with(&self, volumes: &UnitVolumes) -> Result<f64, UnitError> {
+        let ml = match self {
+            UnitType::Kpl => volumes.piece_ml.ok_or(UnitError::MissingPieceVolume)?,
+            unit => unit.ml().unwrap_or_default(),
+        };
+
+        match ml > 0. {
+            true => Ok(ml),
+            false => Err(UnitError::InvalidVolume),
+        }
+    }
+
+    pub fn to_ml(&self, value: f64, volumes: &UnitVolumes) -> Result<f64, UnitError> {
+        Ok(value * self.ml_with(volumes)?)
+    }
+
+    pub fn from_ml(&self, ml: f64, volumes: &UnitVolumes) -> Result<f64, UnitError> {
+        Ok(ml / self.ml_with(volumes)?)
+    }
+
+    /// Converts between units with a fixed volume. Use `convert_with` for `kpl`
+    pub fn convert(&self, value: f64, other: Self) -> Result<(Self, f64), UnitError> {
+        self.convert_with(value, other, &UnitVolumes::default())
+    }
+
+    pub fn convert_with(
+        &self,
+        value: f64,
+        other: Self,
+        volumes: &UnitVolumes,
+    ) -> Result<(Self, f64), UnitError> {
+        if *self == other {
+            return Ok((other, value));
+        }
+
+        let ml = self.to_ml(value, volumes)?;
+        let value = other.from_ml(ml, volumes)?;
+        Ok((other, value))
+    }
+
+    /// Smallest amount that is practical to measure behind the bar
+    pub fn increment(&self) -> f64 {
+        match self {
+            UnitType::Cl => 0.5,
+            UnitType::Ml => 5.,
+            UnitType::Oz => 0.25,
+            UnitType::Kpl => 1.,
+            UnitType::Tl => 0.5,
+            UnitType::Dash => 1.,
+            UnitType::Dl => 0.25,
+            UnitType::L => 0.05,
+            UnitType::Tbsp => 0.5,
+            UnitType::Barspoon => 1.,
+            UnitType::Splash => 1.,
+            UnitType::Part => 0.5,
+        }
+    }
+
+    /// Rounds to the nearest increment. Positive amounts are never rounded down to zero
+    pub fn round(&self, value: f64) -> f64 {
+        let increment = self.increment();
+        match value > 0. {
+            true => ((value / increment).round() * increment).max(increment),
+            false => 0.,
+        }
+    }
+
+    /// Amount with the unit, such as "¾ oz"
+    pub fn format_amount(&self, amount: f64) -> String {
+        format!("{} {}", format_fraction(amount), self.as_str())
+    }
+}
+
+const FRACTIONS: &[(f64, char)] = &[
+    (1. / 8., '⅛'),
+    (1. / 4., '¼'),
+    (1. / 3., '⅓'),
+    (3. / 8., '⅜'),
+    (1. / 2., '½'),
+    (5. / 8., '⅝'),
+    (2. / 3., '⅔'),
+    (3. / 4., '¾'),
+    (7. / 8., '⅞'),
+];
+
+/// Largest difference at which an amount is displayed as a fraction
+const FRACTION_TOLERANCE: f64 = 0.01;
+
+/// Formats an amount with common fractions, such as "¾" or "1½".
+/// Other amounts are formatted as decimals with at most two decimal places.
+pub fn format_fraction(amount: f64) -> String {
+    if !amount.is_finite() || amount < 0. {
+        return amount.to_string();
+    }
+
+    let mut whole = amount.trunc();
+    let mut part = amount - whole;
+    if part > 1. - FRACTION_TOLERANCE {
+        whole += 1.;
+        part = 0.;
+    }
+
+    if part < FRACTION_TOLERANCE {
+        return format!("{whole}");
+    }
+
+    match FRACTIONS
+        .iter()
+        .find(|(value, _)| (part - value).abs() < FRACTION_TOLERANCE)
+    {
+        Some((_, symbol)) if whole > 0. => format!("{whole}{symbol}"),
+        Some((_, symbol)) => symbol.to_string(),
+        None => format!("{amount:.2}")
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_owned(),
+    }
+}
+
+/// Parses an amount written as a decimal ("1.5" or "1,5"), a fraction ("3/4", "1 1/2")
+/// or with a fraction symbol ("¾", "1½"). Two words are a whole number and a fraction below 1.
+/// Negative amounts are rejected
+pub fn parse_amount(value: &str) -> Option<f64> {
+    let words: Vec<&str> = value.split_whitespace().collect();
+    let amount = match words.as_slice() {
+        [word] => parse_amount_word(word)?,
+        [whole, fraction] => {
+            let whole: u32 = whole.parse().ok()?;
+            whole as f64 + parse_proper_fraction(fraction)?
         }
+        _ => return None,
+    };
+
+    match amount.is_finite() && amount >= 0. {
+        true => Some(amount),
+        false => None,
+    }
+}
+
+/// Fraction below 1 written as "1/2" or "½"
+fn parse_proper_fraction(word: &str) -> Option<f64> {
+    let is_fraction = word.contains('/')
+        || FRACTIONS
+            .iter()
+            .any(|(_, symbol)| word == symbol.to_string());
+    if !is_fraction {
+        return None;
+    }
+
+    parse_amount_word(word).filter(|value| *value > 0. && *value < 1.)
+}
+
+fn parse_amount_word(word: &str) -> Option<f64> {
+    if let Some((numerator, denominator)) = word.split_once('/') {
+        let numerator: f64 = numerator.parse().ok()?;
+        let denominator: f64 = denominator.parse().ok()?;
+        return match denominator > 0. {
+            true => Some(numerator / denominator),
+            false => None,
+        };
+    }
+
+    let fraction = FRACTIONS.iter().find(|(_, symbol)| word.ends_with(*symbol));
+    match fraction {
+        Some((value, symbol)) => {
+            let whole = word.trim_end_matches(*symbol);
+            match whole.is_empty() {
+                true => Some(*value),
+                false => Some(whole.parse::<f64>().ok()? + value),
+            }
+        }
+        None => word.replace(',', ".").parse().ok(),
     }
 }
 
@@ -244,6 +437,18 @@ pub enum RecipeOrder {
     PriceSuperalkoDesc,
     PriceAlkoAsc,
     PriceAlkoDesc,
+    DilutedAbvAsc,
+    DilutedAbvDesc,
+    DilutedVolumeAsc,
+    DilutedVolumeDesc,
+    KcalAsc,
+    KcalDesc,
+    SugarAsc,
+    SugarDesc,
+    CarbohydratesAsc,
+    CarbohydratesDesc,
+    /// Bayesian average rating, highest first
+    TopRated,
 }
 
 impl TryFrom<Value> for RecipeOrder {
@@ -265,6 +470,17 @@ impl TryFrom<Value> for RecipeOrder {
                 "price_superalko_desc" => Ok(Self::PriceSuperalkoDesc),
                 "price_alko_asc" => Ok(Self::PriceAlkoAsc),
                 "price_alko_desc" => Ok(Self::PriceAlkoDesc),
+                "diluted_abv_asc" => Ok(Self::DilutedAbvAsc),
+                "diluted_abv_desc" => Ok(Self::DilutedAbvDesc),
+                "diluted_volume_asc" => Ok(Self::DilutedVolumeAsc),
+                "diluted_volume_desc" => Ok(Self::DilutedVolumeDesc),
+                "kcal_asc" => Ok(Self::KcalAsc),
+                "kcal_desc" => Ok(Self::KcalDesc),
+                "sugar_asc" => Ok(Self::SugarAsc),
+                "sugar_desc" => Ok(Self::SugarDesc),
+                "carbohydrates_asc" => Ok(Self::CarbohydratesAsc),
+                "carbohydrates_desc" => Ok(Self::CarbohydratesDesc),
+                "top_rated" => Ok(Self::TopRated),
                 _ => Err(TypeError::new("Invalid variant")),
             },
             None => return Err(TypeError::new("Failed to parse value as string")),
@@ -344,6 +560,73 @@ pub struct User {
     pub uid: UserRole,
 }
 
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
+pub struct UserProfile {
+    pub id: Uuid,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub recipe_count: i64,
+    pub rating_count: i64,
+}
+
+/// What happens to the recipes and incredients of a deleted user
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthoredContent {
+    /// Moved to the placeholder user `DELETED_USERNAME`
+    Anonymize,
+    /// Moved to another user
+    Reassign(Uuid),
+}
+
+/// Everything stored about a user, see `export_user_data`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserDataExport {
+    #[serde(with = "ts_seconds")]
+    pub exported_at: DateTime<Utc>,
+    pub profile: UserProfile,
+    pub role: UserRole,
+    /// Recipes authored by the user
+    pub recipes: RecipeDocument,
+    pub incredients: Vec<IncredientName>,
+    pub revisions: Vec<AuthoredRevision>,
+    pub ratings: Vec<RecipeRating>,
+    pub favorites: Vec<RecipeName>,
+    /// Incredients marked as owned by the user
+    pub owned_incredients: Vec<IncredientName>,
+    /// Owned cabinets and the ones shared with the user
+    pub cabinets: Vec<Cabinet>,
+    pub cabinet_products: Vec<CabinetProduct>,
+    pub cabinet_mixers: Vec<CabinetMixer>,
+}
+
+impl UserDataExport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthoredRevision {
+    pub recipe_id: Uuid,
+    pub revision: i32,
+    #[serde(with = "ts_seconds")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl<'r> FromRow<'r, PgRow> for AuthoredRevision {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            recipe_id: row.try_get("recipe_id")?,
+            revision: row.try_get("revision")?,
+            created_at: row
+                .try_get("created_at")
+                .map(|v: NaiveDateTime| v.and_utc())?,
+        })
+    }
+}
+
 #[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
 pub struct Incredient {
     pub id: Uuid,
@@ -375,6 +658,21 @@ pub struct Incredient {
     pub static_filter_c: Option<i32>,
 
     pub unit: UnitType,
+    /// Volume of one `kpl` in ml
+    pub piece_volume_ml: Option<f64>,
+
+    /// Per 100 ml, or per piece when `unit` is `kpl`. Calories exclude ethanol, which is derived from the ABV
+    pub kcal: Option<f64>,
+    pub sugar: Option<f64>,
+    pub carbohydrates: Option<f64>,
+}
+
+/// Nutrition of an incredient, see `Incredient::kcal`. Sugar is in grams and included in carbohydrates.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct IncredientNutrition {
+    pub kcal: Option<f64>,
+    pub sugar: Option<f64>,
+    pub carbohydrates: Option<f64>,
 }
 
 #[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
@@ -601,8 +899,11 @@ pub struct Recipe {
     pub recipe_id: Uuid,
     pub tag_list: Vec<String>,
 
+    /// Volume of the whole recipe as written, which makes `servings` servings
     pub total_volume: f64,
+    /// Ethanol of the whole recipe in standard servings of `STANDARD_SERVING_ML`
     pub standard_servings: f64,
+    /// Price per standard serving of ethanol, not per serving of the recipe
     pub alko_price_per_serving: f64,
     pub superalko_price_per_serving: f64,
 
@@ -613,6 +914,7 @@ pub struct Recipe {
     pub abv_max: f64,
     pub abv_min: f64,
 
+    /// Prices of the whole recipe
     pub alko_price_max: f64,
     pub alko_price_min: f64,
     pub alko_price_average: f64,
@@ -623,11 +925,36 @@ pub struct Recipe {
 
     pub incredient_count: i32,
     pub favorite_count: i32,
+    pub rating_count: i32,
+    /// Mean score of the ratings, 0 if there are none
+    pub rating_average: f64,
+    /// Mean score pulled towards `RATING_PRIOR_MEAN`, used to rank recipes
+    pub rating_bayesian: f64,
 
     pub available_superalko: bool,
     pub available_alko: bool,
 
     pub import_origin: Option<i32>,
+    /// Recipe this one was forked from
+    pub parent_id: Option<Uuid>,
+
+    pub diluted_volume: f64,
+    pub diluted_abv: f64,
+
+    /// Number of servings the recipe as written makes
+    pub servings: i32,
+    /// Nutrition per serving, the whole recipe divided by `servings`
+    pub kcal: f64,
+    pub sugar: f64,
+    pub carbohydrates: f64,
+    /// Some `kpl` parts count as 0 ml, so the volume, strength and prices are too low
+    pub unknown_piece_volume: bool,
+
+    pub glassware: Option<Glassware>,
+    pub ice: Option<IceType>,
+    pub garnish: Option<String>,
+    /// Preparation steps in order, only selected by `get_recipe`
+    pub steps: Vec<RecipeStep>,
 }
 
 impl FromRow<'_, PgRow> for Recipe {
@@ -638,6 +965,14 @@ impl FromRow<'_, PgRow> for Recipe {
             .map(|x| x.trim().to_owned())
             .filter(|x| x != "")
             .collect();
+        let steps: Vec<RecipeStep> = match row.try_get::<String, _>("steps") {
+            Ok(steps) => serde_json::from_str(&steps).map_err(|e| sqlx::Error::ColumnDecode {
+                index: "steps".to_owned(),
+                source: Box::new(e),
+            })?,
+            Err(sqlx::Error::ColumnNotFound(_)) => vec![],
+            Err(e) => return Err(e),
+        };
         Ok(Recipe {
             id: row.try_get("id")?,
             r#type: row.try_get("type")?,
@@ -663,13 +998,149 @@ impl FromRow<'_, PgRow> for Recipe {
             superalko_price_average: row.try_get("superalko_price_average")?,
             incredient_count: row.try_get("incredient_count")?,
             favorite_count: row.try_get("favorite_count")?,
+            rating_count: row.try_get("rating_count")?,
+            rating_average: row.try_get("rating_average")?,
+            rating_bayesian: row.try_get("rating_bayesian")?,
             available_superalko: row.try_get("available_superalko")?,
             available_alko: row.try_get("available_alko")?,
             import_origin: row.try_get("import_origin")?,
+            parent_id: row.try_get("parent_id")?,
+            diluted_volume: row.try_get("diluted_volume")?,
+            diluted_abv: row.try_get("diluted_abv")?,
+            servings: row.try_get("servings")?,
+            kcal: row.try_get("kcal")?,
+            sugar: row.try_get("sugar")?,
+            carbohydrates: row.try_get("carbohydrates")?,
+            unknown_piece_volume: row.try_get("unknown_piece_volume")?,
+            glassware: row.try_get("glassware")?,
+            ice: row.try_get("ice")?,
+            garnish: row.try_get("garnish")?,
+            steps,
         })
     }
 }
 
+#[derive(
+    Clone, Copy, Debug, PartialEq, PartialOrd, sqlx::Type, Serialize, Deserialize, Eq, Ord, Hash,
+)]
+#[sqlx(type_name = "method_action", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum MethodAction {
+    Shake,
+    Stir,
+    Build,
+    Muddle,
+    Blend,
+    Top,
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, PartialOrd, sqlx::Type, Serialize, Deserialize, Eq, Ord, Hash,
+)]
+#[sqlx(type_name = "glassware", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Glassware {
+    Coupe,
+    Martini,
+    Highball,
+    Collins,
+    Rocks,
+    Shot,
+    Wine,
+    Flute,
+    Hurricane,
+    Mug,
+    PunchBowl,
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, PartialOrd, sqlx::Type, Serialize, Deserialize, Eq, Ord, Hash,
+)]
+#[sqlx(type_name = "ice_type", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum IceType {
+    None,
+    Cubed,
+    Crushed,
+    LargeCube,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RecipeStep {
+    /// Starts from 1
+    pub position: i32,
+    pub action: MethodAction,
+    pub instruction: String,
+}
+
+/// Everything `set_recipe_method` replaces. Steps are stored in the order given,
+/// their `position` is ignored.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct RecipeMethod {
+    pub steps: Vec<RecipeStep>,
+    pub glassware: Option<Glassware>,
+    pub ice: Option<IceType>,
+    pub garnish: Option<String>,
+}
+
+impl From<Recipe> for RecipeMethod {
+    fn from(value: Recipe) -> Self {
+        Self {
+            steps: value.steps,
+            glassware: value.glassware,
+            ice: value.ice,
+            garnish: value.garnish,
+        }
+    }
+}
+
+/// Volume and ABV of a recipe once the ice used to prepare it has melted.
+/// The last shake, stir, blend or build step decides the dilution, built drinks without ice aren't diluted.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct RecipeDilution {
+    pub action: Option<MethodAction>,
+    /// Water added as a share of the undiluted volume
+    pub dilution: f64,
+    pub diluted_volume: f64,
+    pub diluted_abv: f64,
+}
+
+impl RecipeDilution {
+    /// `volume` in ml and `abv` in percent, as in `RecipeCacheData`
+    pub fn new(steps: &[MethodAction], ice: Option<IceType>, volume: f64, abv: f64) -> Self {
+        let action = steps.iter().rev().copied().find(|action| {
+            matches!(
+                action,
+                MethodAction::Shake
+                    | MethodAction::Stir
+                    | MethodAction::Blend
+                    | MethodAction::Build
+            )
+        });
+
+        let quadratic = |[a, b, c]: [f64; 3]| {
+            let abv = abv / 100.0;
+            a * abv * abv + b * abv + c
+        };
+        let dilution = match (action, ice) {
+            (Some(MethodAction::Shake), _) => quadratic(SHAKEN_DILUTION),
+            (Some(MethodAction::Stir), _) => quadratic(STIRRED_DILUTION),
+            (Some(MethodAction::Blend), _) => BLENDED_DILUTION,
+            (Some(MethodAction::Build), Some(IceType::None)) => 0.0,
+            (Some(MethodAction::Build), _) => BUILT_DILUTION,
+            _ => 0.0,
+        }
+        .max(0.0);
+
+        Self {
+            action,
+            dilution,
+            diluted_volume: volume * (1.0 + dilution),
+            diluted_abv: abv / (1.0 + dilution),
+        }
+    }
+}
+
 pub struct RecipeMinimal {
     pub id: Uuid,
     pub r#type: RecipeType,
@@ -716,10 +1187,24 @@ pub struct RecipeRowPartial {
 
     pub incredient_count: i32,
     pub favorite_count: i32,
+    pub rating_count: i32,
+    pub rating_average: f64,
+    pub rating_bayesian: f64,
 
     pub available_superalko: bool,
     pub available_alko: bool,
 
+    pub parent_id: Option<Uuid>,
+
+    pub diluted_volume: f64,
+    pub diluted_abv: f64,
+
+    pub servings: i32,
+    pub kcal: f64,
+    pub sugar: f64,
+    pub carbohydrates: f64,
+    pub unknown_piece_volume: bool,
+
     pub count: i64,
 }
 
@@ -733,8 +1218,11 @@ pub struct RecipeRow {
 
     pub tag_list: Vec<String>,
 
+    /// Volume of the whole recipe as written, which makes `servings` servings
     pub total_volume: f64,
+    /// Ethanol of the whole recipe in standard servings of `STANDARD_SERVING_ML`
     pub standard_servings: f64,
+    /// Price per standard serving of ethanol, not per serving of the recipe
     pub alko_price_per_serving: f64,
     pub superalko_price_per_serving: f64,
 
@@ -745,6 +1233,7 @@ pub struct RecipeRow {
     pub abv_max: f64,
     pub abv_min: f64,
 
+    /// Prices of the whole recipe
     pub alko_price_max: f64,
     pub alko_price_min: f64,
     pub alko_price_average: f64,
@@ -755,10 +1244,26 @@ pub struct RecipeRow {
 
     pub incredient_count: i32,
     pub favorite_count: i32,
+    pub rating_count: i32,
+    pub rating_average: f64,
+    pub rating_bayesian: f64,
 
     pub available_superalko: bool,
     pub available_alko: bool,
 
+    pub parent_id: Option<Uuid>,
+
+    pub diluted_volume: f64,
+    pub diluted_abv: f64,
+
+    /// Number of servings the recipe as written makes
+    pub servings: i32,
+    /// Nutrition per serving, the whole recipe divided by `servings`
+    pub kcal: f64,
+    pub sugar: f64,
+    pub carbohydrates: f64,
+    pub unknown_piece_volume: bool,
+
     pub count: i64,
 }
 
@@ -787,18 +1292,217 @@ impl From<RecipeRowPartial> for RecipeRow {
             superalko_price_average: value.superalko_price_average,
             incredient_count: value.incredient_count,
             favorite_count: value.favorite_count,
+            rating_count: value.rating_count,
+            rating_average: value.rating_average,
+            rating_bayesian: value.rating_bayesian,
             available_superalko: value.available_superalko,
             available_alko: value.available_alko,
+            parent_id: value.parent_id,
+            diluted_volume: value.diluted_volume,
+            diluted_abv: value.diluted_abv,
+            servings: value.servings,
+            kcal: value.kcal,
+            sugar: value.sugar,
+            carbohydrates: value.carbohydrates,
+            unknown_piece_volume: value.unknown_piece_volume,
             count: value.count,
         }
     }
 }
 
+/// A recipe in the fork tree of another recipe, with the username of its author for attribution
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
+pub struct RecipeVariant {
+    pub id: Uuid,
+    pub name: String,
+    pub author_id: Uuid,
+    pub author: String,
+    pub parent_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecipeRating {
+    pub recipe_id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    /// From 1 to 5
+    pub score: i32,
+    pub review: Option<String>,
+    #[serde(with = "ts_seconds")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "ts_seconds")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl<'r> FromRow<'r, PgRow> for RecipeRating {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            recipe_id: row.try_get("recipe_id")?,
+            user_id: row.try_get("user_id")?,
+            username: row.try_get("username")?,
+            score: row.try_get("score")?,
+            review: row.try_get("review")?,
+            created_at: row
+                .try_get("created_at")
+                .map(|v: NaiveDateTime| v.and_utc())?,
+            updated_at: row
+                .try_get("updated_at")
+                .map(|v: NaiveDateTime| v.and_utc())?,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
+pub struct RecipeName {
+    pub id: Uuid,
+    pub name: String,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
+pub struct IncredientName {
+    pub id: Uuid,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MakeableRecipe {
+    pub recipe: RecipeRow,
+    /// Empty if the recipe can be made with the cabinet
+    pub missing: Vec<IncredientName>,
+    /// Servings the tracked stock allows. `None` if something is missing or no amounts are tracked
+    pub servings: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecipeServings {
+    pub recipe_id: Uuid,
+    /// How many times the recipe is made
+    pub servings: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShoppingListItem {
+    pub incredient_id: Uuid,
+    pub incredient_name: String,
+    pub product: Product,
+    /// Volume still needed after the cabinet, in ml
+    pub required_ml: f64,
+    pub bottles: i32,
+    pub total_price: f64,
+    /// Volume left over after making the recipes, in ml
+    pub leftover_ml: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetailerShoppingList {
+    pub retailer: Retailer,
+    pub items: Vec<ShoppingListItem>,
+    pub total_price: f64,
+}
+
+/// Part that can't be bought by volume, such as `kpl`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnmeasuredIncredient {
+    pub incredient_id: Uuid,
+    pub incredient_name: String,
+    pub amount: f64,
+    pub unit: UnitType,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShoppingList {
+    pub retailers: Vec<RetailerShoppingList>,
+    pub total_price: f64,
+    /// Incredients the cabinet already holds enough of
+    pub in_cabinet: Vec<IncredientName>,
+    /// Incredients without any product to buy them with
+    pub unavailable: Vec<IncredientName>,
+    pub unmeasured: Vec<UnmeasuredIncredient>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartyRequest {
+    pub guests: i32,
+    /// Standard servings per guest
+    pub servings_per_guest: f64,
+    pub budget: f64,
+    /// Prices and availability are taken from this retailer
+    pub retailer: Retailer,
+    /// Cabinet whose contents are left out of the shopping list
+    pub cabinet_id: Option<i32>,
+    /// Any of the categories. Every category except generated if empty
+    pub categories: Vec<RecipeType>,
+    /// Every recipe must have all of the tags
+    pub tags: Vec<i32>,
+    pub max_abv: Option<f64>,
+    /// Most different recipes on the menu, `PARTY_MENU_SIZE` by default
+    pub menu_size: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartyMenuItem {
+    pub recipe: RecipeRow,
+    /// How many times the recipe is made
+    pub batches: i32,
+    pub standard_servings: f64,
+    /// Estimate from the cached price per serving
+    pub estimated_price: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartyPlan {
+    pub menu: Vec<PartyMenuItem>,
+    pub target_servings: f64,
+    pub standard_servings: f64,
+    pub estimated_price: f64,
+    /// Whole bottles needed for the menu, so its total may exceed the estimate
+    pub shopping_list: ShoppingList,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum RecipeScale {
+    /// Every part is multiplied by the factor
+    Factor(f64),
+    /// Total volume in ml
+    Volume(f64),
+    /// Total standard servings
+    Servings(f64),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScaledRecipePart {
+    pub incredient_id: Uuid,
+    pub name: String,
+    /// Rounded to `UnitType::increment`
+    pub amount: f64,
+    pub unit: UnitType,
+    /// Rounded amount in ml
+    pub amount_standard: f64,
+}
+
+/// Recipe with its figures recomputed from the rounded parts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScaledRecipe {
+    pub recipe_id: Uuid,
+    pub factor: f64,
+    pub parts: Vec<ScaledRecipePart>,
+    pub total_volume: f64,
+    pub standard_servings: f64,
+    pub abv_average: f64,
+    pub abv_min: f64,
+    pub abv_max: f64,
+    pub alko_price_average: f64,
+    pub superalko_price_average: f64,
+    pub alko_price_per_serving: f64,
+    pub superalko_price_per_serving: f64,
+}
+
 #[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
 pub struct RecipePart {
     pub recipe_id: Uuid,
     pub incredient_id: Uuid,
-    pub amount: i32,
+    pub amount: f64,
     pub unit: UnitType,
     pub name: String,
 }
@@ -808,7 +1512,7 @@ impl RecipePart {
         let mut s = String::from("Ingredients: ");
 
         list.iter().for_each(|part| {
-            s += &format!("{}{} {}, ", part.amount, part.unit.to_string(), part.name);
+            s += &format!("{} {}, ", part.unit.format_amount(part.amount), part.name);
         });
 
         s
@@ -819,7 +1523,7 @@ impl RecipePart {
 pub struct RecipePartNoname {
     pub recipe_id: Uuid,
     pub incredient_id: Uuid,
-    pub amount: i32,
+    pub amount: f64,
     pub unit: UnitType,
 }
 
@@ -833,7 +1537,7 @@ pub struct IngredientsForDrink {
 #[derive(Serialize, Deserialize)]
 pub struct RecipePartNoId {
     pub ingredient_id: Uuid,
-    pub amount: i32,
+    pub amount: f64,
     pub name: String,
     pub unit: UnitType,
 }
@@ -849,6 +1553,13 @@ impl From<RecipePart> for RecipePartNoId {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewRecipePart {
+    pub incredient_id: Uuid,
+    pub amount: f64,
+    pub unit: UnitType,
+}
+
 #[derive(sqlx::FromRow, Debug, Default, Clone, Serialize, Deserialize)]
 pub struct RecipeCacheData {
     pub total_volume: f64,
@@ -876,6 +1587,14 @@ pub struct RecipeCacheData {
 
     pub available_superalko: bool,
     pub available_alko: bool,
+
+    /// Nutrition of the whole recipe, stored divided by `servings`
+    pub kcal: f64,
+    pub sugar: f64,
+    pub carbohydrates: f64,
+
+    /// Some `kpl` parts count as 0 ml, because their incredient has no volume per piece
+    pub unknown_piece_volume: bool,
 }
 
 #[derive(sqlx::FromRow, Debug, Default, Clone, Serialize, Deserialize)]
@@ -922,7 +1641,7 @@ pub struct CabinetMixer {
     pub unit: UnitType,
 
     pub usable: bool,
-    pub amount: Option<i32>,
+    pub amount: Option<f64>,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -934,9 +1653,9 @@ pub struct CabinetMixerOwned {
     pub name: String,
     pub unit: UnitType,
 
-    pub amount: Option<i32>,
+    pub amount: Option<f64>,
     pub owners: Vec<i32>,
-    pub owner_map: BTreeMap<i32, Option<i32>>,
+    pub owner_map: BTreeMap<i32, Option<f64>>,
 }
 
 impl From<CabinetMixer> for CabinetMixerOwned {
@@ -960,6 +1679,170 @@ pub struct RecipeTag {
     pub name: String,
 }
 
+/// Name, type, info, parts and tags of a recipe after a change, see `record_recipe_revision`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecipeRevision {
+    pub id: Uuid,
+    /// Id of the recipe in `drink_recipes`
+    pub recipe_id: Uuid,
+    /// Starts from 1 for every recipe
+    pub revision: i32,
+    /// `None` for the revisions recorded when the history was introduced
+    pub author_id: Option<Uuid>,
+    #[serde(with = "ts_seconds")]
+    pub created_at: DateTime<Utc>,
+
+    pub name: String,
+    pub r#type: RecipeType,
+    pub info: String,
+    pub parts: Vec<RevisionPart>,
+    pub tags: Vec<RecipeTag>,
+}
+
+impl RecipeRevision {
+    /// Same name, type, info, parts and tags
+    pub fn same_content(&self, other: &RecipeRevision) -> bool {
+        let parts = |revision: &RecipeRevision| {
+            let mut parts: Vec<(Uuid, f64, UnitType)> = revision
+                .parts
+                .iter()
+                .map(|part| (part.incredient_id, part.amount, part.unit.clone()))
+                .collect();
+            parts.sort_by_key(|part| part.0);
+            parts
+        };
+        let tag_ids = |revision: &RecipeRevision| {
+            let mut ids: Vec<Uuid> = revision.tags.iter().map(|tag| tag.id).collect();
+            ids.sort();
+            ids
+        };
+        self.name == other.name
+            && self.r#type == other.r#type
+            && self.info == other.info
+            && parts(self) == parts(other)
+            && tag_ids(self) == tag_ids(other)
+    }
+}
+
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RevisionPart {
+    pub incredient_id: Uuid,
+    pub name: String,
+    pub amount: f64,
+    pub unit: UnitType,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PartChange {
+    pub incredient_id: Uuid,
+    pub name: String,
+    pub kind: PartChangeKind,
+    /// `None` when the part was added
+    pub before: Option<(f64, UnitType)>,
+    /// `None` when the part was removed
+    pub after: Option<(f64, UnitType)>,
+}
+
+/// Changes from revision `from` to revision `to`. Fields that didn't change are `None`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevisionDiff {
+    pub recipe_id: Uuid,
+    pub from: i32,
+    pub to: i32,
+    pub name: Option<(String, String)>,
+    pub r#type: Option<(RecipeType, RecipeType)>,
+    pub info: Option<(String, String)>,
+    /// Ordered by incredient name
+    pub parts: Vec<PartChange>,
+    pub added_tags: Vec<RecipeTag>,
+    pub removed_tags: Vec<RecipeTag>,
+}
+
+impl RevisionDiff {
+    pub fn new(from: &RecipeRevision, to: &RecipeRevision) -> Self {
+        let changed = |a: &String, b: &String| match a != b {
+            true => Some((a.clone(), b.clone())),
+            false => None,
+        };
+
+        let mut parts: Vec<PartChange> = vec![];
+        for part in from.parts.iter() {
+            let after = to
+                .parts
+                .iter()
+                .find(|other| other.incredient_id == part.incredient_id);
+            let kind = match after {
+                None => PartChangeKind::Removed,
+                Some(after) if after == part => continue,
+                Some(_) => PartChangeKind::Changed,
+            };
+            parts.push(PartChange {
+                incredient_id: part.incredient_id,
+                name: part.name.clone(),
+                kind,
+                before: Some((part.amount, part.unit.clone())),
+                after: after.map(|after| (after.amount, after.unit.clone())),
+            });
+        }
+        to.parts
+            .iter()
+            .filter(|part| {
+                from.parts
+                    .iter()
+                    .all(|other| other.incredient_id != part.incredient_id)
+            })
+            .for_each(|part| {
+                parts.push(PartChange {
+                    incredient_id: part.incredient_id,
+                    name: part.name.clone(),
+                    kind: PartChangeKind::Added,
+                    before: None,
+                    after: Some((part.amount, part.unit.clone())),
+                })
+            });
+        parts.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let missing_from = |tags: &[RecipeTag], other: &[RecipeTag]| -> Vec<RecipeTag> {
+            tags.iter()
+                .filter(|tag| other.iter().all(|o| o.id != tag.id))
+                .cloned()
+                .collect()
+        };
+
+        Self {
+            recipe_id: to.recipe_id,
+            from: from.revision,
+            to: to.revision,
+            name: changed(&from.name, &to.name),
+            r#type: match from.r#type != to.r#type {
+                true => Some((from.r#type.clone(), to.r#type.clone())),
+                false => None,
+            },
+            info: changed(&from.info, &to.info),
+            parts,
+            added_tags: missing_from(&to.tags, &from.tags),
+            removed_tags: missing_from(&from.tags, &to.tags),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.r#type.is_none()
+            && self.info.is_none()
+            && self.parts.is_empty()
+            && self.added_tags.is_empty()
+            && self.removed_tags.is_empty()
+    }
+}
+
 #[derive(sqlx::FromRow, Debug, Default, Clone, Serialize, Deserialize, PartialEq, PartialOrd)]
 pub struct LinkedRecipeTag {
     pub recipe_id: Uuid,
@@ -995,12 +1878,114 @@ impl TryInto<ParsedRecipe> for ParsedRecipeRow {
     }
 }
 
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
+pub struct IncredientAlias {
+    pub id: i32,
+    pub incredient_id: Uuid,
+    pub alias: String,
+}
+
+/// How an incredient name was resolved, from the most to the least certain
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchKind {
+    Exact,
+    Alias,
+    Translation,
+    Fuzzy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncredientMatch {
+    /// Best candidate, if it's similar enough to the name
+    pub incredient: Option<IncredientName>,
+    pub kind: Option<MatchKind>,
+    /// Between 0 and 1, 1 for exact, alias and translation matches
+    pub score: f64,
+    /// Closest incredients, best first
+    pub candidates: Vec<IncredientName>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportedPart {
+    /// Name in the parsed recipe
+    pub source_name: String,
+    pub incredient: IncredientName,
+    pub amount: f64,
+    pub unit: UnitType,
+    pub kind: MatchKind,
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnresolvedPart {
+    pub source_name: String,
+    pub amount: f64,
+    pub unit: UnitType,
+    pub reason: String,
+    pub candidates: Vec<IncredientName>,
+}
+
+/// Outcome of importing a parsed recipe
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub recipe_id: i32,
+    pub parts: Vec<ImportedPart>,
+    /// Parts left out of the recipe
+    pub unresolved: Vec<UnresolvedPart>,
+    pub tags: Vec<RecipeTag>,
+    /// Tags that didn't exist before the import
+    pub created_tags: Vec<String>,
+}
+
+impl ImportReport {
+    /// Parts were left out, or matched only fuzzily
+    pub fn needs_review(&self) -> bool {
+        !self.unresolved.is_empty() || self.parts.iter().any(|part| part.kind == MatchKind::Fuzzy)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateSource {
+    /// A recipe in `drink_recipes`
+    Recipe,
+    /// Another row of `parsed_drinks`
+    Parsed,
+}
+
+impl TryFrom<String> for DuplicateSource {
+    type Error = TypeError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "recipe" => Ok(Self::Recipe),
+            "parsed" => Ok(Self::Parsed),
+            _ => Err(TypeError::new("Invalid variant")),
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateCandidate {
+    #[sqlx(try_from = "String")]
+    pub source: DuplicateSource,
+    pub id: i32,
+    pub name: String,
+    /// Trigram similarity of the normalized names
+    pub name_score: f64,
+    /// Share of incredients in common
+    pub incredient_score: f64,
+    /// Average of the two, between 0 and 1
+    pub score: f64,
+}
+
 #[derive(sqlx::FromRow, Debug, Default, Clone, Serialize, Deserialize, PartialEq, PartialOrd)]
 pub struct DrinkRandomizerQueue {
     pub id: i32,
     pub author: Uuid,
     pub cabinet_ref: Option<i32>,
-    pub multiplier: f32,
+    pub multiplier: f64,
     pub count: i32,
     pub allow_duplicates: bool,
 }
@@ -1021,3 +2006,43 @@ pub struct ProductPriceHistoryEntry {
     pub product_id: i32,
     pub price: f64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_decimals_and_fractions() {
+        assert_eq!(parse_amount("1.5"), Some(1.5));
+        assert_eq!(parse_amount("1,5"), Some(1.5));
+        assert_eq!(parse_amount("3/4"), Some(0.75));
+        assert_eq!(parse_amount("1 1/2"), Some(1.5));
+        assert_eq!(parse_amount("2 ¼"), Some(2.25));
+        assert_eq!(parse_amount("¾"), Some(0.75));
+        assert_eq!(parse_amount("1½"), Some(1.5));
+    }
+
+    #[test]
+    fn rejects_invalid_amounts() {
+        assert_eq!(parse_amount(""), None);
+        assert_eq!(parse_amount("-1"), None);
+        assert_eq!(parse_amount("1/0"), None);
+        assert_eq!(parse_amount("1 2 3"), None);
+        assert_eq!(parse_amount("1 2"), None);
+        assert_eq!(parse_amount("1/2 1/2"), None);
+        assert_eq!(parse_amount("1 3/2"), None);
+        assert_eq!(parse_amount("1.5 1/2"), None);
+        assert_eq!(parse_amount("1 0.5"), None);
+        assert_eq!(parse_amount("inf"), None);
+        assert_eq!(parse_amount("a lot"), None);
+    }
+
+    #[test]
+    fn formats_fractions() {
+        assert_eq!(format_fraction(0.75), "¾");
+        assert_eq!(format_fraction(1.5), "1½");
+        assert_eq!(format_fraction(2.), "2");
+        assert_eq!(format_fraction(0.999), "1");
+        assert_eq!(format_fraction(1.4), "1.4");
+    }
+}
@@ -0,0 +1,189 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, QueryBuilder};
+
+use crate::{constants::SEARCH_SYNONYMS, error::TypeError};
+
+/// Text search configuration created in the `text_search` migration
+const SEARCH_CONFIG: &str = "finnish_unaccent";
+
+/// Minimum `word_similarity` for a fuzzy match
+pub const FUZZY_THRESHOLD: f64 = 0.5;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchMode {
+    /// `search` is an `ILIKE` pattern
+    #[default]
+    Pattern,
+    /// `search` is free text, matched with Finnish full-text search and trigram similarity
+    FullText,
+}
+
+/// Free text query matched against a name column.
+/// A row matches if every word is a prefix of a (stemmed) word in the name,
+/// or if the whole query is similar enough to a part of the name.
+#[derive(Clone, Debug)]
+pub struct TextSearch {
+    pub query: String,
+    tsquery: String,
+}
+
+impl TextSearch {
+    /// Returns `None` if the query doesn't contain any words
+    pub fn new(query: &str) -> Option<Self> {
+        let words: Vec<String> = query
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| word.to_lowercase())
+            .collect();
+
+        if words.is_empty() {
+            return None;
+        }
+
+        let tsquery = words
+            .iter()
+            .map(|word| match synonym(word) {
+                Some(synonym) => format!("({}:* | {})", word, prefix_all(synonym)),
+                None => format!("{word}:*"),
+            })
+            .collect::<Vec<String>>()
+            .join(" & ");
+
+        Some(Self {
+            query: query.trim().to_owned(),
+            tsquery,
+        })
+    }
+
+    pub fn from_filter(search: &Option<String>, mode: SearchMode) -> Option<Self> {
+        match (search, mode) {
+            (Some(search), SearchMode::FullText) => Self::new(search),
+            _ => None,
+        }
+    }
+
+    /// Pushes the boolean match condition for `column`.
+    /// The fuzzy match uses the indexable `<%` operator, so the query must run in a transaction
+    /// where `set_search_threshold` has set its threshold to `FUZZY_THRESHOLD`.
+    pub fn push_match<'args>(&self, column: &str, builder: &mut QueryBuilder<'args, Postgres>) {
+        builder
+            .push(format!(
+                "(to_tsvector('{SEARCH_CONFIG}', {column}) @@ to_tsquery('{SEARCH_CONFIG}', "
+            ))
+            .push_bind(self.tsquery.clone())
+            .push(") OR search_normalize(")
+            .push_bind(self.query.clone())
+            .push(format!(") <% search_normalize({column}))"));
+    }
+
+    /// Pushes the relevance score of `column` as `FLOAT8`. Higher is better.
+    pub fn push_rank<'args>(&self, column: &str, builder: &mut QueryBuilder<'args, Postgres>) {
+        builder
+            .push(format!(
+                "(ts_rank(to_tsvector('{SEARCH_CONFIG}', {column}), to_tsquery('{SEARCH_CONFIG}', "
+            ))
+            .push_bind(self.tsquery.clone())
+            .push(")) + word_similarity(search_normalize(")
+            .push_bind(self.query.clone())
+            .push(format!("), search_normalize({column})))::FLOAT8"));
+    }
+
+    /// Pushes `column` with the matched words wrapped in `<mark></mark>`
+    pub fn push_highlight<'args>(&self, column: &str, builder: &mut QueryBuilder<'args, Postgres>) {
+        builder
+            .push(format!(
+                "ts_headline('{SEARCH_CONFIG}', {column}, to_tsquery('{SEARCH_CONFIG}', "
+            ))
+            .push_bind(self.tsquery.clone())
+            .push("), 'StartSel=<mark>, StopSel=</mark>, HighlightAll=true')");
+    }
+}
+
+/// Pushes ` ORDER BY` with `order`. Without an explicit order, full-text searches are ordered
+/// by the relevance of `column` and other listings by name.
+pub fn push_order<'args>(
+    order: Option<&str>,
+    search: &Option<String>,
+    mode: SearchMode,
+    column: &str,
+    builder: &mut QueryBuilder<'args, Postgres>,
+) {
+    builder.push(" ORDER BY ");
+    match (order, TextSearch::from_filter(search, mode)) {
+        (Some(order), _) => {
+            builder.push(order);
+        }
+        (None, Some(search)) => {
+            search.push_rank(column, builder);
+            builder.push(" DESC, name");
+        }
+        (None, None) => {
+            builder.push("name");
+        }
+    }
+}
+
+fn synonym(word: &str) -> Option<&'static str> {
+    SEARCH_SYNONYMS
+        .iter()
+        .find(|(name, _)| *name == word)
+        .map(|(_, synonym)| *synonym)
+}
+
+fn prefix_all(words: &str) -> String {
+    let words: Vec<String> = words
+        .split_whitespace()
+        .map(|word| format!("{word}:*"))
+        .collect();
+
+    format!("({})", words.join(" & "))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchEntity {
+    Product,
+    Incredient,
+    Recipe,
+}
+
+impl SearchEntity {
+    pub const ALL: &'static [SearchEntity] = &[
+        SearchEntity::Product,
+        SearchEntity::Incredient,
+        SearchEntity::Recipe,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchEntity::Product => "product",
+            SearchEntity::Incredient => "incredient",
+            SearchEntity::Recipe => "recipe",
+        }
+    }
+}
+
+impl TryFrom<String> for SearchEntity {
+    type Error = TypeError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "product" => Ok(Self::Product),
+            "incredient" => Ok(Self::Incredient),
+            "recipe" => Ok(Self::Recipe),
+            _ => Err(TypeError::new("Invalid variant")),
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    #[sqlx(try_from = "String")]
+    pub kind: SearchEntity,
+    pub id: i32,
+    pub name: String,
+    pub score: f64,
+    /// Name with the matched words wrapped in `<mark></mark>`
+    pub highlight: String,
+}
@@ -1,123 +1,503 @@
-use std::collections::VecDeque;
-
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::schema::UnitType;
+use crate::schema::{parse_amount, RecipeType, UnitType};
 
-use super::error::TypeError;
+use super::error::SrsError;
 
 /*
 Standard Recipe Syntax (SRS)
 
+v1:
 name            amount  unit    incredient      amount
 Sex On The Beach|2|cl|vodka|2|cl|Persikkalikööri|4|cl|Karpalomehu|8|cl|Appelsiinimehu#IBA/Experimental
+
+v2 starts with a version header, which may contain metadata:
+srs2;type=cocktail;info=Shake with ice|Daiquiri|2|oz|Rum|¾|oz|Lime juice|?~1|kpl|Lime wheel#IBA
+
+- `\` escapes the next character, so names may contain `|`, `#`, `/`, `;` and `=`
+- Amounts may be decimals or fractions, such as `1.5`, `3/4` or `¾`
+- The amount may be prefixed with `?` for an optional part and `~` for a garnish
+- Metadata keys are `type` (cocktail, shot, punch or generated) and `info`
 */
 
-#[derive(Debug, Clone, Serialize)]
+const V2_HEADER: &str = "srs2";
+
+/// Characters escaped in v2 text
+const SPECIAL_CHARACTERS: &[char] = &['\\', '|', '#', '/', ';', '='];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 pub struct StandardRecipeSyntax {
     pub name: String,
     pub parts: Vec<StandardRecipePart>,
     pub tags: Vec<String>,
+    pub recipe_type: Option<RecipeType>,
+    pub info: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct StandardRecipePart {
-    pub amount: i32,
+    pub amount: f64,
     pub unit: UnitType,
     pub incredient_name: String,
+    pub optional: bool,
+    pub garnish: bool,
+}
+
+impl StandardRecipeSyntax {
+    /// v1 can't represent metadata, markers, names containing separators or recipes without parts
+    fn is_v1_compatible(&self) -> bool {
+        let plain = |text: &str| !text.contains(['|', '#']);
+
+        self.recipe_type.is_none()
+            && self.info.is_none()
+            && !self.parts.is_empty()
+            && !self.name.is_empty()
+            && plain(&self.name)
+            && !is_v2(&self.name)
+            && self
+                .parts
+                .iter()
+                .all(|part| !part.optional && !part.garnish && plain(&part.incredient_name))
+            && self
+                .tags
+                .iter()
+                .all(|tag| !tag.is_empty() && plain(tag) && !tag.contains('/'))
+    }
+}
+
+fn is_v2(value: &str) -> bool {
+    value
+        .strip_prefix(V2_HEADER)
+        .is_some_and(|rest| rest.starts_with([';', '|']))
 }
 
 impl TryFrom<String> for StandardRecipeSyntax {
-    type Error = TypeError;
+    type Error = SrsError;
 
     fn try_from(value: String) -> Result<Self, Self::Error> {
-        let data: VecDeque<&str> = value.split("#").collect();
-        let mut recipe_parts: VecDeque<&str> = data.get(0).unwrap_or(&"").split("|").collect();
-        let recipe_tags: VecDeque<&str> = data.get(1).unwrap_or(&"").split("/").collect();
-
-        let mut parts: Vec<StandardRecipePart> = vec![];
-        if recipe_parts.len() <= 1 {
-            return Err(TypeError::new("Invalid syntax"));
+        let v2 = is_v2(&value);
+        let mut parser = Parser {
+            chars: value.chars().collect(),
+            position: 0,
+            v2,
         };
 
-        let name = recipe_parts.pop_front().unwrap();
-        let mut i = 1;
+        parser.parse()
+    }
+}
 
-        let mut tmp = StandardRecipePart {
-            amount: 0,
-            unit: UnitType::Cl,
-            incredient_name: String::new(),
+impl Into<String> for StandardRecipeSyntax {
+    /// Written as v1 when possible, so that older readers understand it
+    fn into(self) -> String {
+        let v1 = self.is_v1_compatible();
+        let text = |value: &str| match v1 {
+            true => value.to_owned(),
+            false => escape(value),
         };
 
-        for part in recipe_parts.iter() {
-            match i {
-                1 => {
-                    let amount = part.parse::<i32>().ok();
-                    if amount.is_none() {
-                        return Err(TypeError::new("Invalid syntax; Invalid amount"));
-                    }
+        let mut s = String::new();
+        if !v1 {
+            s += V2_HEADER;
+            if let Some(recipe_type) = &self.recipe_type {
+                s += &format!(";type={}", recipe_type_str(recipe_type));
+            }
+            if let Some(info) = &self.info {
+                s += &format!(";info={}", escape(info));
+            }
+            s += "|";
+        }
 
-                    tmp.amount = amount.unwrap();
-                }
-                2 => {
-                    let value = Value::String(String::from(*part));
-                    let unit = UnitType::try_from(value).ok();
-                    if unit.is_none() {
-                        return Err(TypeError::new("Invalid syntax; Invalid unit"));
-                    }
+        s += &text(&self.name);
+
+        self.parts.iter().for_each(|rp| {
+            let mut markers = String::new();
+            if rp.optional {
+                markers.push('?');
+            }
+            if rp.garnish {
+                markers.push('~');
+            }
+
+            s += &format!(
+                "|{}{}|{}|{}",
+                markers,
+                rp.amount,
+                rp.unit.to_string(),
+                text(&rp.incredient_name)
+            );
+        });
 
-                    tmp.unit = unit.unwrap();
+        self.tags.iter().enumerate().for_each(|(i, tag)| {
+            if i == 0 {
+                s += &format!("#{}", text(tag));
+            } else {
+                s += &format!("/{}", text(tag));
+            }
+        });
+
+        s
+    }
+}
+
+fn escape(value: &str) -> String {
+    let mut s = String::with_capacity(value.len());
+    value.chars().for_each(|c| {
+        if SPECIAL_CHARACTERS.contains(&c) {
+            s.push('\\');
+        }
+        s.push(c);
+    });
+    s
+}
+
+fn recipe_type_str(recipe_type: &RecipeType) -> &'static str {
+    match recipe_type {
+        RecipeType::Cocktail => "cocktail",
+        RecipeType::Shot => "shot",
+        RecipeType::Punch => "punch",
+        RecipeType::Generated => "generated",
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    /// v1 has no escapes, metadata or markers
+    v2: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn column(&self) -> usize {
+        self.position + 1
+    }
+
+    fn found(&self) -> String {
+        match self.peek() {
+            Some(c) => format!("'{c}'"),
+            None => String::from("end of input"),
+        }
+    }
+
+    fn error(&self, expected: &str) -> SrsError {
+        SrsError::new(self.column(), expected, &self.found())
+    }
+
+    fn expect(&mut self, c: char, expected: &str) -> Result<(), SrsError> {
+        match self.peek() == Some(c) {
+            true => {
+                self.position += 1;
+                Ok(())
+            }
+            false => Err(self.error(expected)),
+        }
+    }
+
+    /// Reads until one of `stops` or the end of input, returning the text and its column
+    fn field(&mut self, stops: &[char]) -> Result<(String, usize), SrsError> {
+        let column = self.column();
+        let mut value = String::new();
+
+        while let Some(c) = self.peek() {
+            if stops.contains(&c) {
+                break;
+            }
+
+            self.position += 1;
+            if c == '\\' && self.v2 {
+                match self.peek() {
+                    Some(escaped) => {
+                        value.push(escaped);
+                        self.position += 1;
+                    }
+                    None => return Err(self.error("escaped character")),
                 }
-                3 => {
-                    tmp.incredient_name = String::from(*part);
+            } else {
+                value.push(c);
+            }
+        }
+
+        Ok((value, column))
+    }
 
-                    parts.push(tmp);
+    fn parse(&mut self) -> Result<StandardRecipeSyntax, SrsError> {
+        let mut srs = StandardRecipeSyntax::default();
 
-                    tmp = StandardRecipePart {
-                        amount: 0,
-                        unit: UnitType::Cl,
-                        incredient_name: String::new(),
+        if self.v2 {
+            self.position = V2_HEADER.chars().count();
+            self.parse_metadata(&mut srs)?;
+            self.expect('|', "'|' and recipe name")?;
+        }
+
+        let (name, column) = self.field(&['|', '#'])?;
+        if name.is_empty() {
+            return Err(SrsError::new(column, "recipe name", &self.found()));
+        }
+        srs.name = name;
+
+        while self.peek() == Some('|') {
+            self.position += 1;
+            srs.parts.push(self.parse_part()?);
+        }
+
+        if self.peek() == Some('#') {
+            self.position += 1;
+            srs.tags = self.parse_tags()?;
+        }
+
+        match self.peek() {
+            None => Ok(srs),
+            Some(_) => Err(self.error("'|', '#' or end of input")),
+        }
+    }
+
+    fn parse_metadata(&mut self, srs: &mut StandardRecipeSyntax) -> Result<(), SrsError> {
+        while self.peek() == Some(';') {
+            self.position += 1;
+
+            let (key, column) = self.field(&['=', ';', '|', '#'])?;
+            self.expect('=', "'=' and value")?;
+            let (value, value_column) = self.field(&[';', '|', '#'])?;
+
+            match key.as_str() {
+                "type" => {
+                    let recipe_type = match value.as_str() {
+                        "generated" => Ok(RecipeType::Generated),
+                        _ => RecipeType::try_from(Value::String(value.clone())),
                     };
+                    match recipe_type {
+                        Ok(recipe_type) => srs.recipe_type = Some(recipe_type),
+                        Err(_) => {
+                            return Err(SrsError::new(
+                                value_column,
+                                "cocktail, shot, punch or generated",
+                                &format!("'{value}'"),
+                            ))
+                        }
+                    }
+                }
+                "info" => srs.info = Some(value),
+                _ => {
+                    return Err(SrsError::new(
+                        column,
+                        "'type' or 'info'",
+                        &format!("'{key}'"),
+                    ))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_part(&mut self) -> Result<StandardRecipePart, SrsError> {
+        let (amount, column) = self.field(&['|', '#'])?;
 
-                    i = 0;
+        let mut optional = false;
+        let mut garnish = false;
+        let mut number = amount.as_str();
+        if self.v2 {
+            loop {
+                if let Some(rest) = number.strip_prefix('?') {
+                    optional = true;
+                    number = rest;
+                } else if let Some(rest) = number.strip_prefix('~') {
+                    garnish = true;
+                    number = rest;
+                } else {
+                    break;
                 }
-                _ => unreachable!(),
             }
+        }
+
+        let amount = parse_amount(number)
+            .ok_or_else(|| SrsError::new(column, "amount", &format!("'{amount}'")))?;
+
+        self.expect('|', "'|' and unit")?;
+        let (unit, column) = self.field(&['|', '#'])?;
+        let unit = UnitType::try_from(Value::String(unit.clone())).map_err(|_| {
+            let units: Vec<&str> = UnitType::ALL.iter().map(|unit| unit.as_str()).collect();
+            SrsError::new(
+                column,
+                &format!("unit ({})", units.join(", ")),
+                &format!("'{unit}'"),
+            )
+        })?;
 
-            i += 1;
+        self.expect('|', "'|' and incredient name")?;
+        let (incredient_name, column) = self.field(&['|', '#'])?;
+        if incredient_name.is_empty() {
+            return Err(SrsError::new(column, "incredient name", &self.found()));
         }
 
-        Ok(Self {
-            name: name.to_string(),
-            parts,
-            tags: recipe_tags.iter().map(|tag| tag.to_string()).collect(),
+        Ok(StandardRecipePart {
+            amount,
+            unit,
+            incredient_name,
+            optional,
+            garnish,
         })
     }
-}
 
-impl Into<String> for StandardRecipeSyntax {
-    // Sex On The Beach|2|cl|vodka|2|cl|Persikkalikööri|4|cl|Karpalomehu|8|cl|Appelsiinimehu#IBA/Experimental
-    fn into(self) -> String {
-        let mut s = self.name;
+    fn parse_tags(&mut self) -> Result<Vec<String>, SrsError> {
+        let mut tags = vec![];
 
-        self.parts.iter().for_each(|rp| {
-            s += &format!(
-                "|{}|{}|{}",
-                rp.amount,
-                rp.unit.to_string(),
-                rp.incredient_name
-            );
-        });
+        loop {
+            let (tag, _) = self.field(&['/', '#'])?;
+            if !tag.is_empty() {
+                tags.push(tag);
+            }
 
-        self.tags.iter().enumerate().for_each(|(i, tag)| {
-            if i == 0 {
-                s += &format!("#{tag}");
-            } else {
-                s += &format!("/{tag}");
+            match self.peek() {
+                Some('/') => self.position += 1,
+                // v1 ignored everything after a second '#'
+                Some('#') if !self.v2 => {
+                    self.position = self.chars.len();
+                    break;
+                }
+                Some(_) => return Err(self.error("'/' or end of input")),
+                None => break,
             }
-        });
+        }
 
-        s
+        Ok(tags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(value: &str) -> Result<StandardRecipeSyntax, SrsError> {
+        StandardRecipeSyntax::try_from(value.to_owned())
+    }
+
+    fn serialize(srs: &StandardRecipeSyntax) -> String {
+        srs.clone().into()
+    }
+
+    fn part(amount: f64, unit: UnitType, incredient_name: &str) -> StandardRecipePart {
+        StandardRecipePart {
+            amount,
+            unit,
+            incredient_name: incredient_name.to_owned(),
+            optional: false,
+            garnish: false,
+        }
+    }
+
+    #[test]
+    fn parses_v1() {
+        let srs =
+            parse("Sex On The Beach|2|cl|vodka|2|cl|Persikkalikööri#IBA/Experimental").unwrap();
+
+        assert_eq!(srs.name, "Sex On The Beach");
+        assert_eq!(
+            srs.parts,
+            vec![
+                part(2., UnitType::Cl, "vodka"),
+                part(2., UnitType::Cl, "Persikkalikööri")
+            ]
+        );
+        assert_eq!(srs.tags, vec!["IBA", "Experimental"]);
+        assert_eq!(srs.recipe_type, None);
+        assert_eq!(srs.info, None);
+    }
+
+    #[test]
+    fn v1_has_no_escapes_and_ignores_a_second_tag_list() {
+        let srs = parse("A\\B|1|cl|Vodka#Tag#ignored").unwrap();
+
+        assert_eq!(srs.name, "A\\B");
+        assert_eq!(srs.tags, vec!["Tag"]);
+    }
+
+    #[test]
+    fn writes_v1_when_possible() {
+        let value = "Screwdriver|4|cl|Vodka|12|cl|Orange juice#Highball";
+
+        assert_eq!(serialize(&parse(value).unwrap()), value);
+    }
+
+    #[test]
+    fn roundtrips_v2() {
+        let srs = StandardRecipeSyntax {
+            name: String::from("Gin | Tonic #1"),
+            parts: vec![
+                part(4., UnitType::Cl, "Gin"),
+                StandardRecipePart {
+                    optional: true,
+                    garnish: true,
+                    ..part(1., UnitType::Kpl, "Lime; wheel")
+                },
+            ],
+            tags: vec![String::from("Long/Drinks"), String::from("Classic")],
+            recipe_type: Some(RecipeType::Cocktail),
+            info: Some(String::from("Build over ice; stir=gently")),
+        };
+
+        let value = serialize(&srs);
+        assert!(value.starts_with(r"srs2;type=cocktail;info=Build over ice\; stir\=gently|"));
+        assert!(value.contains(r"Gin \| Tonic \#1"));
+        assert!(value.contains("|?~1|kpl|"));
+        assert_eq!(parse(&value).unwrap(), srs);
+    }
+
+    #[test]
+    fn roundtrips_fractions_and_markers() {
+        let srs =
+            parse("srs2|Daiquiri|2|oz|Rum|¾|oz|Lime juice|1 1/2|cl|Syrup|?~1|kpl|Lime wheel#IBA")
+                .unwrap();
+
+        assert_eq!(srs.parts[1].amount, 0.75);
+        assert_eq!(srs.parts[2].amount, 1.5);
+        assert!(srs.parts[3].optional && srs.parts[3].garnish);
+        assert_eq!(parse(&serialize(&srs)).unwrap(), srs);
+    }
+
+    #[test]
+    fn roundtrips_recipes_without_parts() {
+        let srs = StandardRecipeSyntax {
+            name: String::from("Empty"),
+            tags: vec![String::from("Draft")],
+            ..Default::default()
+        };
+
+        let value = serialize(&srs);
+        assert_eq!(value, "srs2|Empty#Draft");
+        assert_eq!(parse(&value).unwrap(), srs);
+        assert_eq!(parse("Empty#Draft").unwrap(), srs);
+    }
+
+    #[test]
+    fn reports_error_columns() {
+        assert_eq!(
+            parse("srs2|Name|x|cl|Vodka").unwrap_err(),
+            SrsError::new(11, "amount", "'x'")
+        );
+        assert_eq!(
+            parse("|1|cl|Vodka").unwrap_err(),
+            SrsError::new(1, "recipe name", "'|'")
+        );
+        assert_eq!(
+            parse("Name|1|cl|").unwrap_err(),
+            SrsError::new(11, "incredient name", "end of input")
+        );
+        assert_eq!(
+            parse("srs2;color=red|Name|1|cl|Vodka").unwrap_err(),
+            SrsError::new(6, "'type' or 'info'", "'color'")
+        );
+        assert_eq!(
+            parse("srs2|Name\\").unwrap_err(),
+            SrsError::new(11, "escaped character", "end of input")
+        );
+
+        let unit = parse("Name|1|xx|Vodka").unwrap_err();
+        assert_eq!(unit.column, 8);
+        assert!(unit.expected.starts_with("unit ("));
     }
 }
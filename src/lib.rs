@@ -1,9 +1,15 @@
 mod database {
     pub mod actions;
+    pub mod compatibility;
     pub mod error;
+    pub mod filter;
     pub mod form;
+    pub mod interchange;
+    pub mod migrations;
     pub mod pagination;
+    pub mod recipe_text;
     pub mod schema;
+    pub mod search;
     pub mod srs;
 }
 mod authentication {